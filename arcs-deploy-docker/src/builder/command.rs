@@ -0,0 +1,383 @@
+use std::collections::VecDeque;
+use std::path::Path;
+use std::process::{ Output, Stdio };
+
+use async_trait::async_trait;
+use tokio::io::{ AsyncBufReadExt, AsyncWriteExt, BufReader };
+use tokio::process::Command;
+
+use crate::env::{ reg_password, reg_url, reg_username };
+use crate::error::DockerError;
+use crate::logging::*;
+use crate::version::LATEST_TAG;
+use crate::{ BUILD_LOG_TAIL_LINES, BuildConfig, LayerProgress };
+
+use super::{ ImageBuilder, registry_cache_enabled };
+
+/// Builds by running a rootless, buildah compatible builder (`buildah`, `podman`, ...), so no Docker socket is needed
+///
+/// Images are kept in the builder's own storage, and unlike the Docker daemon backend old versions aren't pruned from it.
+#[derive(Debug, Clone)]
+pub struct CommandBuilder {
+    program: String,
+}
+
+/// Output of a finished builder command
+///
+/// ## Fields
+/// - `success` - Whether the command exited with status 0
+/// - `lines` - Last lines the command wrote to stdout and stderr, interleaved
+struct CommandOutput {
+    success: bool,
+    lines: Vec<String>,
+}
+
+impl CommandOutput {
+    /// Last line of output, which is where builders put the reason they failed
+    fn last_line(&self) -> String {
+        self.lines.last().cloned().unwrap_or_else(|| "no output".to_string())
+    }
+}
+
+impl CommandBuilder {
+    pub fn new(program: impl Into<String>) -> Self {
+        Self { program: program.into() }
+    }
+
+    /// Runs the builder with `args` until it exits, calling `on_line` on every non-empty line of its output
+    ///
+    /// `stdin` is written to the command's standard input if given. Arguments aren't logged, since they can contain build
+    /// arguments like the flag.
+    ///
+    /// ## Returns
+    /// - `Ok(CommandOutput)` - The command ran, whether it succeeded or not
+    /// - `Err(DockerError)` - [`BuilderUnavailable`][DockerError::BuilderUnavailable] if the command couldn't be run
+    async fn run(&self, args: &[String], stdin: Option<&str>, mut on_line: impl FnMut(&str)) -> Result<CommandOutput, DockerError> {
+        let subcommand = args.first().map(String::as_str).unwrap_or_default();
+        debug!("Running {} {subcommand}", self.program);
+
+        let unavailable = |source| DockerError::BuilderUnavailable { program: self.program.clone(), source };
+
+        let mut child = Command::new(&self.program)
+            .args(args)
+            .stdin(if stdin.is_some() { Stdio::piped() } else { Stdio::null() })
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(unavailable)?;
+
+        if let (Some(input), Some(mut child_stdin)) = (stdin, child.stdin.take()) {
+            child_stdin.write_all(input.as_bytes()).await.map_err(unavailable)?;
+            // Dropping stdin closes it, so the command sees the end of its input
+        }
+
+        let (Some(stdout), Some(stderr)) = (child.stdout.take(), child.stderr.take()) else {
+            return Err(unavailable(std::io::Error::new(std::io::ErrorKind::BrokenPipe, "Builder output wasn't captured")));
+        };
+        let mut stdout = BufReader::new(stdout).lines();
+        let mut stderr = BufReader::new(stderr).lines();
+
+        let mut lines: VecDeque<String> = VecDeque::with_capacity(BUILD_LOG_TAIL_LINES);
+        let mut record = |line: String| {
+            if line.trim().is_empty() {
+                return;
+            }
+            trace!("{}: {line}", self.program);
+            on_line(&line);
+            if lines.len() == BUILD_LOG_TAIL_LINES {
+                lines.pop_front();
+            }
+            lines.push_back(line);
+        };
+
+        let (mut stdout_done, mut stderr_done) = (false, false);
+        while !(stdout_done && stderr_done) {
+            tokio::select! {
+                line = stdout.next_line(), if !stdout_done => match line {
+                    Ok(Some(line)) => record(line),
+                    _ => stdout_done = true,
+                },
+                line = stderr.next_line(), if !stderr_done => match line {
+                    Ok(Some(line)) => record(line),
+                    _ => stderr_done = true,
+                },
+            }
+        }
+
+        let status = child.wait().await.map_err(unavailable)?;
+        debug!("{} {subcommand} exited with {status}", self.program);
+
+        Ok(CommandOutput { success: status.success(), lines: lines.into() })
+    }
+
+    /// Runs the builder with `args` until it exits, capturing its standard output as it is
+    ///
+    /// ## Returns
+    /// - `Ok(Output)` - The command ran, whether it succeeded or not
+    /// - `Err(DockerError)` - [`BuilderUnavailable`][DockerError::BuilderUnavailable] if the command couldn't be run
+    async fn run_binary(&self, args: &[String]) -> Result<Output, DockerError> {
+        let subcommand = args.first().map(String::as_str).unwrap_or_default();
+        debug!("Running {} {subcommand}", self.program);
+
+        let output = Command::new(&self.program)
+            .args(args)
+            .stdin(Stdio::null())
+            .kill_on_drop(true)
+            .output()
+            .await
+            .map_err(|source| DockerError::BuilderUnavailable { program: self.program.clone(), source })?;
+
+        debug!("{} {subcommand} exited with {}", self.program, output.status);
+        Ok(output)
+    }
+
+    /// Error for a builder command that ran but failed
+    fn failed(&self, action: impl Into<String>, output: &CommandOutput) -> DockerError {
+        DockerError::BuilderFailed { program: self.program.clone(), action: action.into(), message: output.last_line() }
+    }
+
+    /// Removes a working container, one that doesn't exist counts as removed
+    async fn remove_container(&self, name: &str) -> Result<(), DockerError> {
+        let output = self.run(&["rm".to_string(), name.to_string()], None, |_| ()).await?;
+        if !output.success && !output.last_line().to_lowercase().contains("not found") {
+            error!("Error removing working container {name}");
+            return Err(self.failed(format!("remove container {name}"), &output));
+        }
+
+        Ok(())
+    }
+
+    /// Logs the builder into the remote registry, passing the password through stdin so it never shows up in `ps`
+    async fn login(&self, image: &str, action: &'static str) -> Result<(), DockerError> {
+        let registry = reg_url().split('/').next().unwrap_or_default();
+        let args = ["login", "--username", reg_username(), "--password-stdin", registry].map(String::from);
+
+        let output = self.run(&args, Some(reg_password()), |_| ()).await?;
+        if !output.success {
+            error!("{} failed to log into {registry}", self.program);
+            return Err(DockerError::RegistryRejected { action, image: image.to_string(), message: output.last_line() });
+        }
+
+        Ok(())
+    }
+
+    /// Pushes a single tag of an image, returning the digest the registry stored it under
+    async fn push_tag(&self, image: &str, tag: &str, on_progress: &(dyn Fn(&LayerProgress) + Sync)) -> Result<Option<String>, DockerError> {
+        let reference = format!("{image}:{tag}");
+        let digest_file = tempfile_path(&reference);
+        let args = [
+            "push".to_string(),
+            "--digestfile".to_string(),
+            digest_file.to_string_lossy().to_string(),
+            reference.clone(),
+            format!("docker://{reference}"),
+        ];
+
+        let output = self.run(&args, None, |line| {
+            // e.g. "Copying blob 3a1b2c4d5e6f done"
+            if let Some(blob) = line.strip_prefix("Copying blob ") {
+                let (layer, status) = blob.split_once(' ').unwrap_or((blob, "Copying"));
+                on_progress(&LayerProgress { layer: layer.to_string(), status: status.trim().to_string(), current: None, total: None });
+            }
+        }).await?;
+
+        let digest = std::fs::read_to_string(&digest_file).ok().map(|digest| digest.trim().to_string());
+        let _ = std::fs::remove_file(&digest_file);
+
+        if !output.success {
+            error!("Error pushing {reference}");
+            return Err(DockerError::RegistryRejected { action: "push", image: image.to_string(), message: output.last_line() });
+        }
+
+        debug!("Pushed tag {tag} of {image} ({digest:?})");
+        Ok(digest.filter(|digest| !digest.is_empty()))
+    }
+}
+
+/// Script that writes a tar archive of a path inside a working container to stdout, run in the builder's user namespace
+/// so that rootless storage can be mounted
+///
+/// Takes the builder, the container, the folder inside the container and the name of the file or folder in it, and exits with
+/// [`MISSING_PATH_STATUS`] if the path doesn't exist.
+const COPY_SCRIPT: &str = r#"root=$("$0" mount "$1") || exit 1
+cd "$root" && cd "./$2" && [ -e "$3" ] || exit 3
+tar -cf - -- "$3""#;
+
+/// Exit status of [`COPY_SCRIPT`] if the path to copy doesn't exist in the container
+const MISSING_PATH_STATUS: i32 = 3;
+
+/// File `buildah push --digestfile` writes the digest of a pushed `reference` to
+fn tempfile_path(reference: &str) -> std::path::PathBuf {
+    let escaped: String = reference
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+
+    std::env::temp_dir().join(format!("arcs-push-{}-{escaped}.digest", std::process::id()))
+}
+
+#[async_trait]
+impl ImageBuilder for CommandBuilder {
+    fn name(&self) -> &'static str {
+        "command"
+    }
+
+    fn applies_dockerignore(&self) -> bool {
+        true
+    }
+
+    /// The cache is kept in its own `<image>/buildcache` repository of the registry
+    async fn build(&self, context: &Path, image: &str, version: &str, config: &BuildConfig) -> Result<(), DockerError> {
+        let cache_repository = format!("{image}/buildcache");
+        let use_cache = registry_cache_enabled() && match self.login(image, "build").await {
+            Ok(()) => true,
+            Err(e) => {
+                warn!("Building {image} without the registry cache: {e}");
+                false
+            },
+        };
+
+        let mut args = vec![
+            "build".to_string(),
+            "--layers".to_string(),
+            "--file".to_string(),
+            context.join(config.dockerfile()).to_string_lossy().to_string(),
+            "--tag".to_string(),
+            format!("{image}:{LATEST_TAG}"),
+            "--tag".to_string(),
+            format!("{image}:{version}"),
+        ];
+
+        for (name, value) in &config.args {
+            args.extend(["--build-arg".to_string(), format!("{name}={value}")]);
+        }
+        if let Some(target) = &config.target {
+            args.extend(["--target".to_string(), target.clone()]);
+        }
+        for (name, value) in &config.labels {
+            args.extend(["--label".to_string(), format!("{name}={value}")]);
+        }
+        if config.no_cache {
+            args.push("--no-cache".to_string());
+        } else if use_cache {
+            args.extend(["--cache-from".to_string(), cache_repository.clone()]);
+        }
+        if use_cache {
+            args.extend(["--cache-to".to_string(), cache_repository]);
+        }
+        args.push(context.to_string_lossy().to_string());
+
+        let output = self.run(&args, None, |_| ()).await?;
+        if !output.success {
+            error!("Error building {image}");
+            return Err(DockerError::BuildFailed { image: image.to_string(), message: output.last_line(), log_tail: output.lines });
+        }
+
+        Ok(())
+    }
+
+    async fn push(&self, image: &str, version: &str, on_progress: &(dyn Fn(&LayerProgress) + Sync)) -> Result<Option<String>, DockerError> {
+        self.login(image, "push").await?;
+
+        let digest = self.push_tag(image, version, on_progress).await?;
+        self.push_tag(image, LATEST_TAG, on_progress).await?;
+
+        Ok(digest)
+    }
+
+    async fn pull(&self, image: &str, tag: &str) -> Result<Option<String>, DockerError> {
+        self.login(image, "pull").await?;
+
+        let reference = format!("{image}:{tag}");
+        let output = self.run(&["pull".to_string(), format!("docker://{reference}")], None, |_| ()).await?;
+        if !output.success {
+            error!("Error pulling {reference}");
+            return Err(DockerError::RegistryRejected { action: "pull", image: image.to_string(), message: output.last_line() });
+        }
+
+        let args = ["images", "--digests", "--format", "{{.Digest}}", &reference].map(String::from);
+        let output = self.run(&args, None, |_| ()).await?;
+        let digest = output.lines
+            .iter()
+            .map(|line| line.trim())
+            .find(|line| line.starts_with("sha256:"))
+            .map(str::to_string);
+
+        Ok(digest)
+    }
+
+    /// Creates a working container with `from`, mounts it and archives the path with `tar`, so the builder has to be
+    /// `buildah` (podman has no `from`) and `sh` and `tar` have to be installed
+    async fn copy_from_image(&self, image: &str, helper: &str, path: &Path) -> Result<Vec<u8>, DockerError> {
+        let args = ["from", "--name", helper, image].map(String::from);
+        let output = self.run(&args, None, |_| ()).await?;
+        if !output.success {
+            error!("Error creating working container {helper:?}");
+            return Err(self.failed(format!("create container {helper}"), &output));
+        }
+
+        // Relative paths are relative to the image's working directory
+        let args = ["inspect", "--type", "container", "--format", "{{.OCIv1.Config.WorkingDir}}", helper].map(String::from);
+        let working_dir = match self.run(&args, None, |_| ()).await {
+            Ok(output) if output.success => output.lines.last().map(|line| line.trim().to_string()).unwrap_or_default(),
+            _ => {
+                debug!("Couldn't inspect container {helper:?}, resolving {path:?} from /");
+                String::new()
+            },
+        };
+        let absolute_path = Path::new("/").join(working_dir).join(path);
+        let folder = absolute_path.parent().unwrap_or(Path::new("/")).to_string_lossy().to_string();
+        let name = absolute_path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_else(|| ".".to_string());
+
+        let args = [
+            "unshare".to_string(),
+            "sh".to_string(),
+            "-c".to_string(),
+            COPY_SCRIPT.to_string(),
+            self.program.clone(),
+            helper.to_string(),
+            folder,
+            name,
+        ];
+        let copied = self.run_binary(&args).await;
+
+        trace!("Cleaning up container {helper:?}");
+        if let Err(e) = self.remove_container(helper).await {
+            warn!("Failed to remove helper container {helper:?}, it is removed along with the challenge: {e}");
+        }
+
+        let output = copied?;
+        match output.status.code() {
+            Some(0) => Ok(output.stdout),
+            Some(MISSING_PATH_STATUS) => Err(DockerError::ContainerFileMissing { image: image.to_string(), path: absolute_path }),
+            _ => {
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                let message = stderr.lines().filter(|line| !line.trim().is_empty()).last().unwrap_or("no output").to_string();
+                error!("Error copying {absolute_path:?} out of container {helper:?}");
+                Err(DockerError::BuilderFailed { program: self.program.clone(), action: format!("copy {absolute_path:?} out of {image}"), message })
+            },
+        }
+    }
+
+    async fn remove_helpers(&self, prefix: &str) -> Result<usize, DockerError> {
+        let args = ["containers", "--all", "--format", "{{.ContainerName}}"].map(String::from);
+        // Not through `run`, which only keeps the last lines of output
+        let output = self.run_binary(&args).await?;
+        if !output.status.success() {
+            error!("Error listing working containers");
+            let message = String::from_utf8_lossy(&output.stderr).trim().to_string();
+            return Err(DockerError::BuilderFailed { program: self.program.clone(), action: "list containers".to_string(), message });
+        }
+
+        let names = String::from_utf8_lossy(&output.stdout);
+        let mut removed = 0;
+        for name in names.lines().map(str::trim).filter(|name| name.starts_with(prefix)) {
+            info!("Removing helper container {name}");
+            self.remove_container(name).await?;
+            removed += 1;
+        }
+
+        Ok(removed)
+    }
+}
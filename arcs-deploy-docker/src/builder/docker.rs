@@ -0,0 +1,293 @@
+use std::collections::{ HashMap, VecDeque };
+use std::path::Path;
+
+use async_trait::async_trait;
+use futures::stream::StreamExt;
+use hyper::{ Body, Request };
+use shiplift::Docker;
+use shiplift::image::{ BuildOptions, ImageBuildChunk, PullOptions, TagOptions };
+
+use crate::daemon::{ self, RegistryCredentials };
+use crate::env::{ reg_password, reg_url, reg_username };
+use crate::error::DockerError;
+use crate::extract::{ copy_with_daemon, remove_daemon_helpers };
+use crate::logging::*;
+use crate::version::{ LATEST_TAG, prune_image_versions };
+use crate::{ BUILD_LOG_TAIL_LINES, BuildConfig, LayerProgress };
+
+use super::{ ImageBuilder, registry_cache_enabled };
+
+/// Build argument that makes BuildKit embed cache metadata in the image, so the pushed image can be a cache source
+const INLINE_CACHE_ARG: &str = "BUILDKIT_INLINE_CACHE";
+
+/// Builds through the local Docker daemon, which needs access to its socket
+///
+/// Old versions of an image are pruned from the daemon after every build, see `IMAGE_RETAINED_VERSIONS`.
+#[derive(Clone)]
+pub struct DockerBuilder {
+    docker: Docker,
+}
+
+impl DockerBuilder {
+    pub fn new(docker: Docker) -> Self {
+        Self { docker }
+    }
+
+    /// Pushes a single tag of an image, returning the digest the registry stored it under
+    ///
+    /// The daemon's progress stream is read until the end, so errors the registry reports partway through the push
+    /// (denied access, unknown blobs, ...) fail the push instead of being ignored.
+    async fn push_tag(&self, image: &str, tag: &str, on_progress: &(dyn Fn(&LayerProgress) + Sync)) -> Result<Option<String>, DockerError> {
+        let credentials = RegistryCredentials {
+            username: reg_username(),
+            password: reg_password(),
+            serveraddress: reg_url(),
+        }.header_value();
+
+        let request = Request::post(format!("/images/{image}/push?tag={tag}"))
+            .header("X-Registry-Auth", credentials.as_str())
+            .body(Body::empty())
+            .map_err(|e| DockerError::registry("push", image, shiplift::Error::Http(e)))?;
+
+        let response = daemon::request(request).await?;
+        let status = response.status();
+        if status == hyper::StatusCode::NOT_FOUND {
+            return Err(DockerError::ImageNotFound(image.to_string()));
+        } else if !status.is_success() {
+            let body = hyper::body::to_bytes(response.into_body()).await.unwrap_or_default();
+            return Err(DockerError::RegistryRejected {
+                action: "push",
+                image: image.to_string(),
+                message: format!("daemon responded with {status}: {}", String::from_utf8_lossy(&body).trim()),
+            });
+        }
+
+        let mut digest = None;
+        daemon::read_progress_stream(response.into_body(), |message| {
+            if let Some(error) = message.error_message() {
+                return Err(DockerError::RegistryRejected { action: "push", image: image.to_string(), message: error });
+            }
+
+            if let Some(summary) = message.aux {
+                debug!("Pushed tag {tag} of {image} ({:?})", summary.digest);
+                if summary.tag.as_deref() == Some(tag) {
+                    digest = summary.digest;
+                }
+                return Ok(());
+            }
+
+            match (message.id, message.status) {
+                (Some(layer), Some(status)) => {
+                    let progress = LayerProgress {
+                        layer,
+                        status,
+                        current: message.progress_detail.as_ref().and_then(|detail| detail.current),
+                        total: message.progress_detail.as_ref().and_then(|detail| detail.total),
+                    };
+
+                    if progress.current.is_some() {
+                        trace!("{image} layer {}: {} {:?}/{:?}", progress.layer, progress.status, progress.current, progress.total);
+                    } else {
+                        debug!("{image} layer {}: {}", progress.layer, progress.status);
+                    }
+                    on_progress(&progress);
+                },
+                (None, Some(status)) => debug!("{status}"),
+                _ => (),
+            }
+
+            Ok(())
+        }).await?;
+
+        Ok(digest)
+    }
+}
+
+#[async_trait]
+impl ImageBuilder for DockerBuilder {
+    fn name(&self) -> &'static str {
+        "docker"
+    }
+
+    /// shiplift sends the whole context folder to the daemon, ignored files included
+    fn applies_dockerignore(&self) -> bool {
+        false
+    }
+
+    /// The cache is the `latest` tag of the image in the registry, pulled before building and pushed along with the build
+    async fn build(&self, context: &Path, image: &str, version: &str, config: &BuildConfig) -> Result<(), DockerError> {
+        let cache_image = format!("{image}:{LATEST_TAG}");
+        let import_cache = registry_cache_enabled() && !config.no_cache;
+        if import_cache {
+            match self.pull(image, LATEST_TAG).await {
+                Ok(_) => debug!("Importing build cache of {image} from {cache_image}"),
+                Err(e) => debug!("No build cache of {image} in the registry, building from scratch: {e}"),
+            }
+        }
+
+        let mut build_options = BuildOptions::builder(context.to_string_lossy().to_string());
+        build_options
+            .tag(image) // FIXME --> Investigate why certain registries will reject this tag while others will accept it... leads to issues with invalid reference image?
+            .dockerfile(config.dockerfile())
+            .nocache(config.no_cache)
+            .rm(true);
+
+        let mut args: HashMap<&str, &str> = config.args.iter().map(|(name, value)| (name.as_str(), value.as_str())).collect();
+        if registry_cache_enabled() {
+            args.insert(INLINE_CACHE_ARG, "1");
+        }
+        if !args.is_empty() {
+            build_options.buildargs(&args);
+        }
+        if import_cache {
+            build_options.cachefrom(&[cache_image.as_str()]);
+        }
+        if let Some(target) = &config.target {
+            build_options.target(target);
+        }
+        if !config.labels.is_empty() {
+            let labels: HashMap<&str, &str> = config.labels.iter().map(|(name, value)| (name.as_str(), value.as_str())).collect();
+            build_options.labels(&labels);
+        }
+        let build_options = build_options.build();
+
+        let mut log_tail: VecDeque<String> = VecDeque::with_capacity(BUILD_LOG_TAIL_LINES);
+        let mut stream = self.docker.images().build(&build_options);
+        while let Some(build_result) = stream.next().await {
+            match build_result {
+                Ok(output) => {
+                    match &output {
+                        ImageBuildChunk::Update {stream} => {
+                            trace!("{:?}", stream);
+                            for line in stream.lines().filter(|line| !line.trim().is_empty()) {
+                                if log_tail.len() == BUILD_LOG_TAIL_LINES {
+                                    log_tail.pop_front();
+                                }
+                                log_tail.push_back(line.to_string());
+                            }
+                        },
+                        ImageBuildChunk::Error {error, ..} => {
+                            error!("Error building {image}");
+                            debug!("Trace: {:?}", error);
+                            return Err(DockerError::BuildFailed {
+                                image: image.to_string(),
+                                message: error.to_string(),
+                                log_tail: log_tail.into(),
+                            });
+                        },
+                        ImageBuildChunk::Digest {aux} => {
+                            info!("Image digest: {:?}", aux);
+                        }
+                        ImageBuildChunk::PullStatus { .. } => {
+                            trace!("{:?}", output);
+                        }
+                    }
+                },
+                Err(e) => {
+                    error!("Error building docker image");
+                    debug!("Docker image build error: {:?}", e);
+                    return Err(DockerError::daemon(format!("build {image}"), e));
+                },
+            }
+        }
+
+        let tag_options = TagOptions::builder().repo(image).tag(version).build();
+        if let Err(e) = self.docker.images().get(image).tag(&tag_options).await {
+            error!("Error tagging {image} as version {version}");
+            debug!("Trace: {:?}", e);
+            return Err(DockerError::daemon(format!("tag {image}:{version}"), e));
+        }
+
+        // Old versions only take up space, the registry keeps them all
+        if let Err(e) = prune_image_versions(&self.docker, image, version).await {
+            warn!("Failed to prune old versions of {image}: {e}");
+        }
+
+        Ok(())
+    }
+
+    /// Only the `version` and `latest` tags are pushed, one after the other, not the other versions kept locally
+    async fn push(&self, image: &str, version: &str, on_progress: &(dyn Fn(&LayerProgress) + Sync)) -> Result<Option<String>, DockerError> {
+        let digest = self.push_tag(image, version, on_progress).await?;
+        self.push_tag(image, LATEST_TAG, on_progress).await?;
+
+        Ok(digest)
+    }
+
+    async fn pull(&self, image: &str, tag: &str) -> Result<Option<String>, DockerError> {
+        let auth = shiplift::RegistryAuth::builder()
+            .username(reg_username())
+            .password(reg_password())
+            .server_address(reg_url())
+            .build();
+        let pull_options = PullOptions::builder().auth(auth).image(image).tag(tag).build();
+
+        let mut digest = None;
+        let mut stream = self.docker.images().pull(&pull_options);
+        while let Some(data) = stream.next().await {
+            match data {
+                Ok(output) => {
+                    match &output {
+                        ImageBuildChunk::Update {stream} => {
+                            trace!("{:?}", stream);
+                        },
+                        ImageBuildChunk::Error {error, ..} => {
+                            error!("Error pulling {image}:{tag}");
+                            debug!("Trace: {:?}", error);
+                            return Err(DockerError::RegistryRejected { action: "pull", image: image.to_string(), message: error.to_string() });
+                        },
+                        ImageBuildChunk::Digest {aux} => {
+                            info!("Image digest: {:?}", aux);
+                        },
+                        ImageBuildChunk::PullStatus { status, .. } => {
+                            if let Some(reported) = status.strip_prefix("Digest: ") {
+                                digest = Some(reported.trim().to_string());
+                            }
+                            trace!("{:?}", output);
+                        }
+                    }
+                },
+                Err(e) => {
+                    error!("Error pulling image");
+                    debug!("Trace: {:?}", e);
+                    return Err(DockerError::registry("pull", image, e));
+                },
+            };
+        }
+
+        // Older daemons don't report the digest while pulling, but the image's repo digests contain it
+        match digest {
+            Some(digest) => Ok(Some(digest)),
+            None => repo_digest(&self.docker, image, tag).await,
+        }
+    }
+
+    async fn copy_from_image(&self, image: &str, helper: &str, path: &Path) -> Result<Vec<u8>, DockerError> {
+        copy_with_daemon(&self.docker, image, helper, path).await
+    }
+
+    async fn remove_helpers(&self, prefix: &str) -> Result<usize, DockerError> {
+        remove_daemon_helpers(&self.docker, prefix).await
+    }
+}
+
+/// Digest the registry knows a local image by, taken from the repo digests (`repo@sha256:...`) of the image
+async fn repo_digest(docker: &Docker, image: &str, tag: &str) -> Result<Option<String>, DockerError> {
+    let details = match docker.images().get(format!("{image}:{tag}")).inspect().await {
+        Ok(details) => details,
+        Err(e) => {
+            error!("Error inspecting pulled image {image}:{tag}");
+            debug!("Trace: {:?}", e);
+            return Err(DockerError::daemon(format!("inspect image {image}:{tag}"), e));
+        },
+    };
+
+    let digest = details.repo_digests
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|repo_digest| repo_digest.split_once('@'))
+        .find(|(repo, _)| *repo == image)
+        .map(|(_, digest)| digest.to_string());
+
+    Ok(digest)
+}
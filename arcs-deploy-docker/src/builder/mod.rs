@@ -0,0 +1,112 @@
+use std::path::Path;
+
+use async_trait::async_trait;
+use shiplift::Docker;
+
+use crate::env::{ image_build_cache, image_builder as image_builder_env, image_builder_command };
+use crate::error::DockerError;
+use crate::logging::*;
+use crate::{ BuildConfig, LayerProgress };
+
+mod command;
+pub use command::CommandBuilder;
+
+mod docker;
+pub use docker::DockerBuilder;
+
+/// Program the rootless backend runs if `IMAGE_BUILDER_COMMAND` isn't set
+const DEFAULT_BUILDER_COMMAND: &str = "buildah";
+
+/// Whether builds import their cache from the registry and export it back, on unless `IMAGE_BUILD_CACHE` is `false`
+///
+/// Without it, a fresh host builds every layer from scratch even though the registry has the previous build.
+pub(crate) fn registry_cache_enabled() -> bool {
+    !matches!(image_build_cache().map(str::to_lowercase).as_deref(), Some("false" | "0" | "off" | "no"))
+}
+
+/// Something that builds challenge images and moves them to and from the remote registry
+///
+/// Every backend tags a build with both `latest` and its version, and authenticates with the registry using the
+/// `DOCKER_REGISTRY_USERNAME` and `DOCKER_REGISTRY_PASSWORD` environment variables.
+#[async_trait]
+pub trait ImageBuilder: Send + Sync {
+    /// Name of the backend, for logs
+    fn name(&self) -> &'static str;
+
+    /// Whether the backend leaves the files matched by the context's `.dockerignore` out of the build
+    fn applies_dockerignore(&self) -> bool;
+
+    /// Builds `image` from the build `context`, tagged as `latest` and `version`
+    ///
+    /// Unless the build has `no_cache` set, layers of the previous build are taken from the registry if
+    /// [`registry_cache_enabled`][registry_cache_enabled].
+    ///
+    /// ## Returns
+    /// - `Ok(())` - Image built and tagged
+    /// - `Err(DockerError)` - [`BuildFailed`][DockerError::BuildFailed] if the Dockerfile failed, the backend's error otherwise
+    async fn build(&self, context: &Path, image: &str, version: &str, config: &BuildConfig) -> Result<(), DockerError>;
+
+    /// Pushes the `latest` and `version` tags of `image`, calling `on_progress` on every progress update of a layer
+    ///
+    /// ## Returns
+    /// - `Ok(Option<String>)` - Image pushed, along with the digest the registry stored `version` under if it is known
+    /// - `Err(DockerError)` - The push failed
+    async fn push(&self, image: &str, version: &str, on_progress: &(dyn Fn(&LayerProgress) + Sync)) -> Result<Option<String>, DockerError>;
+
+    /// Pulls the `tag` of `image`
+    ///
+    /// ## Returns
+    /// - `Ok(Option<String>)` - Image pulled, along with its digest if it is known
+    /// - `Err(DockerError)` - The pull failed
+    async fn pull(&self, image: &str, tag: &str) -> Result<Option<String>, DockerError>;
+
+    /// Copies `path` out of the latest pulled `image` through a helper container named `helper`, without running the image
+    ///
+    /// Relative paths are relative to the image's working directory. The helper container is removed afterwards.
+    ///
+    /// ## Returns
+    /// - `Ok(Vec<u8>)` - Tar archive with the file or folder as its top level entry
+    /// - `Err(DockerError)` - [`ContainerFileMissing`][DockerError::ContainerFileMissing] if the path doesn't exist in the
+    ///   image, the backend's error otherwise
+    async fn copy_from_image(&self, image: &str, helper: &str, path: &Path) -> Result<Vec<u8>, DockerError>;
+
+    /// Force removes every helper container whose name starts with `prefix`
+    ///
+    /// ## Returns
+    /// - `Ok(usize)` - Number of containers removed
+    /// - `Err(DockerError)` - The containers couldn't be listed or removed
+    async fn remove_helpers(&self, prefix: &str) -> Result<usize, DockerError>;
+}
+
+/// Backends [`image_builder`][image_builder] can pick from, set with `IMAGE_BUILDER`
+///
+/// ## Variants
+/// - `Docker` - Builds through the Docker daemon, the default
+/// - `Command` - Runs a rootless, buildah compatible builder (`buildah`, `podman`, ...), see `IMAGE_BUILDER_COMMAND`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuilderBackend {
+    Docker,
+    Command,
+}
+
+/// Backend set with `IMAGE_BUILDER` (`docker`, or `buildah`/`rootless`), the Docker daemon if it isn't set or unknown
+pub fn builder_backend() -> BuilderBackend {
+    match image_builder_env().map(str::to_lowercase).as_deref() {
+        None | Some("docker") => BuilderBackend::Docker,
+        Some("buildah") | Some("rootless") | Some("command") => BuilderBackend::Command,
+        Some(other) => {
+            warn!("Unknown IMAGE_BUILDER {other:?}, building with the Docker daemon");
+            BuilderBackend::Docker
+        },
+    }
+}
+
+/// The image builder set with `IMAGE_BUILDER`
+///
+/// `docker` is only used by the Docker daemon backend, the rootless backend never talks to the daemon.
+pub fn image_builder(docker: &Docker) -> Box<dyn ImageBuilder> {
+    match builder_backend() {
+        BuilderBackend::Docker => Box::new(DockerBuilder::new(docker.clone())),
+        BuilderBackend::Command => Box::new(CommandBuilder::new(image_builder_command().unwrap_or(DEFAULT_BUILDER_COMMAND))),
+    }
+}
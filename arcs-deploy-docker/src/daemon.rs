@@ -0,0 +1,206 @@
+use std::path::{ Path, PathBuf };
+use std::pin::Pin;
+
+use hyper::body::HttpBody;
+use hyper::header::{ HOST, HeaderValue };
+use hyper::{ Body, Request, Response };
+use openssl::ssl::{ SslConnector, SslFiletype, SslMethod, SslVerifyMode };
+use serde::{ Deserialize, Serialize };
+use tokio::io::{ AsyncRead, AsyncWrite };
+use tokio::net::{ TcpStream, UnixStream };
+use tokio_openssl::SslStream;
+
+use crate::env::{ docker_cert_path, docker_host, docker_tls_verify };
+use crate::error::DockerError;
+use crate::logging::*;
+
+/// Socket the daemon listens on if `DOCKER_HOST` isn't set, same as shiplift's default
+const DEFAULT_DOCKER_HOST: &str = "unix:///var/run/docker.sock";
+
+/// A single JSON message of the progress stream the daemon sends back while pushing or pulling an image
+///
+/// ## Fields
+/// - `status` - What is happening, e.g. `Pushing`, `Pushed` or `Layer already exists`
+/// - `id` - Layer (or tag) the status is about, missing for messages about the whole image
+/// - `progress_detail` - Bytes transferred so far for the layer
+/// - `error` - Why the push/pull failed, the stream ends after it
+/// - `aux` - Summary of the pushed image, sent once at the end of a successful push
+#[derive(Debug, Deserialize)]
+pub(crate) struct ProgressMessage {
+    pub status: Option<String>,
+    pub id: Option<String>,
+    #[serde(rename = "progressDetail")]
+    pub progress_detail: Option<ProgressDetail>,
+    pub error: Option<String>,
+    #[serde(rename = "errorDetail")]
+    pub error_detail: Option<ErrorDetail>,
+    pub aux: Option<PushSummary>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct ProgressDetail {
+    pub current: Option<u64>,
+    pub total: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct ErrorDetail {
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct PushSummary {
+    #[serde(rename = "Tag")]
+    pub tag: Option<String>,
+    #[serde(rename = "Digest")]
+    pub digest: Option<String>,
+}
+
+impl ProgressMessage {
+    /// The error reported by the daemon, if this message is one
+    pub fn error_message(&self) -> Option<String> {
+        self.error_detail
+            .as_ref()
+            .and_then(|detail| detail.message.clone())
+            .or_else(|| self.error.clone())
+    }
+}
+
+/// Credentials for the remote registry, sent base64 encoded in the `X-Registry-Auth` header
+#[derive(Serialize)]
+pub(crate) struct RegistryCredentials<'a> {
+    pub username: &'a str,
+    pub password: &'a str,
+    pub serveraddress: &'a str,
+}
+
+impl RegistryCredentials<'_> {
+    pub fn header_value(&self) -> String {
+        let json = serde_json::to_string(self).unwrap_or_default();
+        base64::encode_config(json, base64::URL_SAFE)
+    }
+}
+
+/// Sends a single request over an established connection to the daemon
+async fn send_over<S>(stream: S, request: Request<Body>) -> hyper::Result<Response<Body>>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let (mut sender, connection) = hyper::client::conn::handshake(stream).await?;
+
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            debug!("Connection to the Docker daemon closed with an error: {e:?}");
+        }
+    });
+
+    sender.send_request(request).await
+}
+
+/// Wraps an I/O or TLS error of the connection to the daemon
+fn unreachable(e: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> DockerError {
+    DockerError::DaemonUnreachable(shiplift::Error::IO(std::io::Error::new(std::io::ErrorKind::Other, e)))
+}
+
+/// Opens a TLS connection to the daemon at a `tcp://` address, the way shiplift does when `DOCKER_CERT_PATH` is set
+///
+/// The client certificate and key are `cert.pem` and `key.pem` of `DOCKER_CERT_PATH`. The daemon's certificate is only
+/// verified (against `ca.pem`) if `DOCKER_TLS_VERIFY` is set.
+async fn connect_tls(address: &str, cert_path: &Path) -> Result<SslStream<TcpStream>, DockerError> {
+    let verify = docker_tls_verify().is_some_and(|verify| !verify.is_empty());
+
+    let mut connector = SslConnector::builder(SslMethod::tls()).map_err(unreachable)?;
+    connector.set_certificate_file(cert_path.join("cert.pem"), SslFiletype::PEM).map_err(unreachable)?;
+    connector.set_private_key_file(cert_path.join("key.pem"), SslFiletype::PEM).map_err(unreachable)?;
+    if verify {
+        connector.set_ca_file(cert_path.join("ca.pem")).map_err(unreachable)?;
+    } else {
+        connector.set_verify(SslVerifyMode::NONE);
+    }
+
+    let host = address.rsplit_once(':').map_or(address, |(host, _port)| host);
+    let ssl = connector
+        .build()
+        .configure()
+        .map_err(unreachable)?
+        .verify_hostname(verify)
+        .into_ssl(host)
+        .map_err(unreachable)?;
+
+    let tcp = TcpStream::connect(address).await.map_err(unreachable)?;
+    let mut stream = SslStream::new(ssl, tcp).map_err(unreachable)?;
+    Pin::new(&mut stream).connect().await.map_err(unreachable)?;
+
+    Ok(stream)
+}
+
+/// Sends a request straight to the daemon at `DOCKER_HOST`, for the endpoints whose response shiplift doesn't stream
+///
+/// `unix://` sockets and `tcp://` addresses are supported, the latter over TLS if `DOCKER_CERT_PATH` is set (see
+/// [`connect_tls`][connect_tls]).
+///
+/// ## Returns
+/// - `Ok(Response<Body>)` - The daemon's response, whatever its status
+/// - `Err(DockerError)` - [`DaemonUnreachable`][DockerError::DaemonUnreachable] if the daemon couldn't be reached
+pub(crate) async fn request(mut request: Request<Body>) -> Result<Response<Body>, DockerError> {
+    let host = docker_host().unwrap_or(DEFAULT_DOCKER_HOST);
+    request.headers_mut().insert(HOST, HeaderValue::from_static("docker"));
+
+    let response = if let Some(path) = host.strip_prefix("unix://") {
+        let stream = UnixStream::connect(path).await.map_err(unreachable)?;
+        send_over(stream, request).await
+    } else if let Some(address) = host.strip_prefix("tcp://") {
+        match docker_cert_path() {
+            Some(cert_path) => send_over(connect_tls(address, &PathBuf::from(cert_path)).await?, request).await,
+            None => send_over(TcpStream::connect(address).await.map_err(unreachable)?, request).await,
+        }
+    } else {
+        error!("Unsupported DOCKER_HOST {host:?}, expected a unix:// or tcp:// address");
+        return Err(unreachable(format!("Unsupported DOCKER_HOST {host}")));
+    };
+
+    response.map_err(|e| DockerError::DaemonUnreachable(shiplift::Error::Hyper(e)))
+}
+
+/// Reads the newline delimited JSON messages of a streamed response, calling `on_message` for each one
+///
+/// ## Returns
+/// - `Ok(())` - The stream ended
+/// - `Err(DockerError)` - The connection broke off, or `on_message` returned an error
+pub(crate) async fn read_progress_stream(
+    mut body: Body,
+    mut on_message: impl FnMut(ProgressMessage) -> Result<(), DockerError>,
+) -> Result<(), DockerError> {
+    let mut buffer: Vec<u8> = vec![];
+
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk.map_err(|e| DockerError::DaemonUnreachable(shiplift::Error::Hyper(e)))?;
+        buffer.extend_from_slice(&chunk);
+
+        // Messages can be split across chunks, so only complete lines are parsed
+        while let Some(newline) = buffer.iter().position(|&byte| byte == b'\n') {
+            let line: Vec<u8> = buffer.drain(..=newline).collect();
+            parse_line(&line, &mut on_message)?;
+        }
+    }
+
+    parse_line(&buffer, &mut on_message)
+}
+
+fn parse_line(
+    line: &[u8],
+    on_message: &mut impl FnMut(ProgressMessage) -> Result<(), DockerError>,
+) -> Result<(), DockerError> {
+    if line.iter().all(u8::is_ascii_whitespace) {
+        return Ok(());
+    }
+
+    match serde_json::from_slice::<ProgressMessage>(line) {
+        Ok(message) => on_message(message),
+        Err(e) => {
+            warn!("Ignoring unreadable progress message from the Docker daemon: {e}");
+            debug!("Message: {}", String::from_utf8_lossy(line));
+            Ok(())
+        },
+    }
+}
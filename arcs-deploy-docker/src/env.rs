@@ -4,6 +4,16 @@ env_var_req!(DOCKER_REGISTRY_USERNAME -> REG_USERNAME);
 env_var_req!(DOCKER_REGISTRY_PASSWORD -> REG_PASSWORD);
 env_var_req!(DOCKER_REGISTRY_URL -> REG_URL);
 env_var_req!(CHALL_FOLDER -> CHALL_FOLDER_DEFAULT);
+env_var_opt!(DOCKER_HOST);
+env_var_opt!(DOCKER_CERT_PATH);
+env_var_opt!(DOCKER_TLS_VERIFY);
+env_var_opt!(IMAGE_RETAINED_VERSIONS);
+env_var_opt!(IMAGE_BUILDER);
+env_var_opt!(IMAGE_BUILDER_COMMAND);
+env_var_opt!(IMAGE_BUILD_CACHE);
+env_var_opt!(BUILD_LINT);
+env_var_opt!(MAX_BUILD_CONTEXT_MB);
+env_var_opt!(ALLOWED_BASE_IMAGES);
 
 
 assert_req_env!(check_env_vars: REG_USERNAME, REG_PASSWORD, REG_URL, CHALL_FOLDER_DEFAULT);
\ No newline at end of file
@@ -0,0 +1,103 @@
+use std::path::PathBuf;
+
+use crate::lint::{ LintFinding, LintSeverity };
+
+use arcs_retry::Retryable;
+use thiserror::Error;
+
+/// Errors that can occur while working with the Docker daemon and the remote registry
+///
+/// ## Variants
+/// - `DaemonUnreachable` - The Docker daemon couldn't be reached at all
+/// - `Daemon` - The Docker daemon rejected a request
+/// - `BuildFailed` - A step of the challenge's Dockerfile failed, along with the last lines of the build's output
+/// - `LintFailed` - The Dockerfile or the build context failed the checks made before building
+/// - `Registry` - The remote registry couldn't be reached or rejected a push/pull
+/// - `RegistryRejected` - The daemon reported an error from the registry partway through a push/pull, e.g. denied access
+/// - `DigestMismatch` - The pulled image isn't the one that was pushed
+/// - `ImageNotFound` - The image doesn't exist locally
+/// - `ContainerFileMissing` - The path to copy out of an image doesn't exist in it
+/// - `ContainerArchive` - The archive of a path copied out of an image couldn't be unpacked
+/// - `ChallFolder` - The challenge folder couldn't be read
+/// - `BuilderUnavailable` - The rootless image builder's command couldn't be run
+/// - `BuilderFailed` - The rootless image builder's command ran, but failed
+#[derive(Debug, Error)]
+pub enum DockerError {
+    #[error("Failed to connect to the Docker daemon, ensure Docker is running")]
+    DaemonUnreachable(#[source] shiplift::Error),
+    #[error("Docker failed to {action}")]
+    Daemon { action: String, #[source] source: shiplift::Error },
+    #[error("Dockerfile of {image} failed to build: {message}")]
+    BuildFailed { image: String, message: String, log_tail: Vec<String> },
+    #[error("Build context of {image} failed the pre-build checks: {}", lint_summary(.findings))]
+    LintFailed { image: String, findings: Vec<LintFinding> },
+    #[error("Registry failed to {action} {image}")]
+    Registry { action: &'static str, image: String, #[source] source: shiplift::Error },
+    #[error("Registry failed to {action} {image}: {message}")]
+    RegistryRejected { action: &'static str, image: String, message: String },
+    #[error("Pulled {image} with digest {actual:?}, expected {expected}")]
+    DigestMismatch { image: String, expected: String, actual: Option<String> },
+    #[error("Image {0} does not exist")]
+    ImageNotFound(String),
+    #[error("{path:?} does not exist in image {image}")]
+    ContainerFileMissing { image: String, path: PathBuf },
+    #[error("Failed to unpack {path:?} copied out of image {image}")]
+    ContainerArchive { image: String, path: PathBuf, #[source] source: std::io::Error },
+    #[error("Failed to read challenge folder {path:?}")]
+    ChallFolder { path: PathBuf, #[source] source: std::io::Error },
+    #[error("Failed to run image builder {program:?}, ensure it is installed")]
+    BuilderUnavailable { program: String, #[source] source: std::io::Error },
+    #[error("Image builder {program:?} failed to {action}: {message}")]
+    BuilderFailed { program: String, action: String, message: String },
+}
+
+/// Messages of the findings that failed a build, joined
+fn lint_summary(findings: &[LintFinding]) -> String {
+    findings
+        .iter()
+        .filter(|finding| finding.severity == LintSeverity::Error)
+        .map(|finding| finding.message.as_str())
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+impl DockerError {
+    /// Wraps a failed request to the daemon, telling an unreachable daemon apart from a rejected request
+    pub(crate) fn daemon(action: impl Into<String>, source: shiplift::Error) -> Self {
+        match source {
+            shiplift::Error::Hyper(_) | shiplift::Error::IO(_) => Self::DaemonUnreachable(source),
+            source => Self::Daemon { action: action.into(), source },
+        }
+    }
+
+    /// Wraps a failed push or pull, which the daemon relays from the registry
+    pub(crate) fn registry(action: &'static str, image: impl Into<String>, source: shiplift::Error) -> Self {
+        match source {
+            shiplift::Error::Hyper(_) | shiplift::Error::IO(_) => Self::DaemonUnreachable(source),
+            source => Self::Registry { action, image: image.into(), source },
+        }
+    }
+}
+
+impl Retryable for DockerError {
+    /// The daemon or the registry couldn't be reached, or the registry refused a push/pull for a reason other than
+    /// missing access or a missing image
+    fn is_retryable(&self) -> bool {
+        match self {
+            Self::DaemonUnreachable(_) | Self::Registry { .. } => true,
+            Self::RegistryRejected { message, .. } => {
+                let message = message.to_lowercase();
+                !["denied", "unauthorized", "authentication required", "not found", "manifest unknown", "name unknown"]
+                    .iter()
+                    .any(|permanent| message.contains(permanent))
+            },
+            _ => false,
+        }
+    }
+}
+
+impl From<DockerError> for String {
+    fn from(err: DockerError) -> Self {
+        err.to_string()
+    }
+}
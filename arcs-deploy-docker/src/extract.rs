@@ -0,0 +1,251 @@
+use std::collections::BTreeSet;
+use std::io::{ Cursor, Read };
+use std::path::{ Path, PathBuf };
+use std::sync::{ Mutex, PoisonError };
+
+use futures::StreamExt;
+use hyper::StatusCode;
+use shiplift::{ ContainerListOptions, ContainerOptions, Docker };
+use shiplift::container::RmContainerOptions;
+use tar::{ Archive, EntryType };
+
+use crate::error::DockerError;
+use crate::logging::*;
+
+/// Names of the helper containers [`fetch_container_file`][crate::fetch_container_file] is using right now
+static ACTIVE_HELPERS: Mutex<BTreeSet<String>> = Mutex::new(BTreeSet::new());
+
+/// Marks a helper container name as in use until dropped, so garbage collection leaves the container alone
+///
+/// Taken before the container is created, since it already shows up in the daemon while being created.
+pub(crate) struct ActiveHelper {
+    name: String,
+}
+
+impl ActiveHelper {
+    pub fn new(name: &str) -> Self {
+        ACTIVE_HELPERS.lock().unwrap_or_else(PoisonError::into_inner).insert(name.to_string());
+        Self { name: name.to_string() }
+    }
+}
+
+impl Drop for ActiveHelper {
+    fn drop(&mut self) {
+        ACTIVE_HELPERS.lock().unwrap_or_else(PoisonError::into_inner).remove(&self.name);
+    }
+}
+
+/// Whether [`fetch_container_file`][crate::fetch_container_file] is using the helper container with this name right now
+pub(crate) fn helper_active(name: &str) -> bool {
+    ACTIVE_HELPERS.lock().unwrap_or_else(PoisonError::into_inner).contains(name)
+}
+
+/// Helper container that is force removed when dropped, unless [`remove`][ContainerGuard::remove] already did
+///
+/// Dropping it outside of a Tokio runtime leaks the container, [`delete_file_containers`][crate::delete_file_containers]
+/// catches those.
+pub(crate) struct ContainerGuard {
+    docker: Docker,
+    id: String,
+    removed: bool,
+}
+
+impl ContainerGuard {
+    pub fn new(docker: &Docker, id: String) -> Self {
+        Self { docker: docker.clone(), id, removed: false }
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Force removes the container
+    pub async fn remove(mut self) -> Result<(), DockerError> {
+        self.removed = true;
+        remove_container(&self.docker, &self.id).await
+    }
+}
+
+impl Drop for ContainerGuard {
+    fn drop(&mut self) {
+        if self.removed {
+            return;
+        }
+
+        let (docker, id) = (self.docker.clone(), self.id.clone());
+        match tokio::runtime::Handle::try_current() {
+            Ok(runtime) => {
+                debug!("Removing helper container {id} in the background");
+                runtime.spawn(async move {
+                    let _ = remove_container(&docker, &id).await;
+                });
+            },
+            Err(_) => warn!("Leaking helper container {id}, no runtime to remove it with"),
+        }
+    }
+}
+
+/// Force removes a container, a container that doesn't exist counts as removed
+pub(crate) async fn remove_container(docker: &Docker, name: &str) -> Result<(), DockerError> {
+    let options = RmContainerOptions::builder().force(true).build();
+    match docker.containers().get(name).remove(options).await {
+        Ok(_) => {
+            info!("Container {name} deleted successfully");
+            Ok(())
+        },
+        Err(shiplift::Error::Fault { code, .. }) if code == StatusCode::NOT_FOUND => {
+            trace!("Container {name} already gone");
+            Ok(())
+        },
+        Err(e) => {
+            error!("Error deleting container {name}");
+            debug!("Trace: {:?}", e);
+            Err(DockerError::daemon(format!("delete container {name}"), e))
+        },
+    }
+}
+
+/// Copies `path` out of `image` through a helper container of the Docker daemon named `helper`, which is created but never
+/// started
+///
+/// The helper container is removed whatever happens, even if the returned future is dropped halfway.
+///
+/// ## Returns
+/// - `Ok(Vec<u8>)` - Tar archive with the file or folder as its top level entry
+/// - `Err(DockerError)` - [`ContainerFileMissing`][DockerError::ContainerFileMissing] if the path doesn't exist in the image,
+///   the daemon's error otherwise
+pub(crate) async fn copy_with_daemon(docker: &Docker, image: &str, helper: &str, path: &Path) -> Result<Vec<u8>, DockerError> {
+    // Never started, the command only has to exist for images without one
+    let container_options = ContainerOptions::builder(image)
+        .name(helper)
+        .cmd(vec!["true"])
+        .build();
+
+    let container = match docker.containers().create(&container_options).await {
+        Ok(info) => {
+            info!("Container {helper:?} created with id {}", info.id);
+            ContainerGuard::new(docker, info.id)
+        },
+        Err(e) => {
+            error!("Error creating container {helper:?}");
+            debug!("Trace: {:?}", e);
+            return Err(DockerError::daemon(format!("create container {helper}"), e));
+        },
+    };
+
+    // Relative paths are relative to the image's working directory, which the archive API doesn't know about
+    let working_dir = match docker.containers().get(container.id()).inspect().await {
+        Ok(details) => PathBuf::from(details.config.working_dir),
+        Err(e) => {
+            debug!("Couldn't inspect container {helper:?}, resolving {path:?} from / : {e:?}");
+            PathBuf::new()
+        },
+    };
+    let absolute_path = Path::new("/").join(working_dir).join(path);
+    let archive = container_archive(docker, image, container.id(), &absolute_path).await;
+
+    trace!("Cleaning up container {helper:?}");
+    if let Err(e) = container.remove().await {
+        warn!("Failed to remove helper container {helper:?}, it is removed along with the challenge: {e}");
+    }
+
+    archive
+}
+
+/// Force removes every container of the Docker daemon whose name starts with `prefix`
+///
+/// ## Returns
+/// - `Ok(usize)` - Number of containers removed
+/// - `Err(DockerError)` - Error trace if the containers couldn't be listed or removed
+pub(crate) async fn remove_daemon_helpers(docker: &Docker, prefix: &str) -> Result<usize, DockerError> {
+    let containers = match docker.containers().list(&ContainerListOptions::builder().all().build()).await {
+        Ok(containers) => containers,
+        Err(e) => {
+            error!("Error listing containers");
+            debug!("Trace: {:?}", e);
+            return Err(DockerError::daemon("list containers", e));
+        },
+    };
+
+    let stray_names = containers
+        .iter()
+        .flat_map(|container| container.names.iter())
+        .map(|name| name.trim_start_matches('/'))
+        .filter(|name| name.starts_with(prefix));
+
+    let mut removed = 0;
+    for name in stray_names {
+        info!("Removing helper container {name}");
+        remove_container(docker, name).await?;
+        removed += 1;
+    }
+
+    Ok(removed)
+}
+
+/// Tar archive of `path` inside a container, streamed through shiplift's [`copy_from`][shiplift::Container::copy_from]
+///
+/// The container doesn't have to be running.
+///
+/// ## Returns
+/// - `Ok(Vec<u8>)` - The archive, with `path` as its top level entry
+/// - `Err(DockerError)` - [`ContainerFileMissing`][DockerError::ContainerFileMissing] if the path doesn't exist, the daemon's
+///   error otherwise
+pub(crate) async fn container_archive(docker: &Docker, image: &str, container_id: &str, path: &Path) -> Result<Vec<u8>, DockerError> {
+    let container = docker.containers().get(container_id);
+    let mut chunks = Box::pin(container.copy_from(path));
+
+    let mut archive = vec![];
+    while let Some(chunk) = chunks.next().await {
+        match chunk {
+            Ok(chunk) => archive.extend_from_slice(&chunk),
+            Err(shiplift::Error::Fault { code, .. }) if code == StatusCode::NOT_FOUND => {
+                return Err(DockerError::ContainerFileMissing { image: image.to_string(), path: path.to_path_buf() });
+            },
+            Err(e) => return Err(DockerError::daemon(format!("copy {path:?} out of container {container_id}"), e)),
+        }
+    }
+
+    Ok(archive)
+}
+
+/// Contents of the single regular file in `archive`, or the archive itself if it holds a folder
+///
+/// Binary files are returned as they are. Anything else (a symlink, a device, ...) can't be extracted.
+///
+/// ## Returns
+/// - `Ok(Vec<u8>)` - The file's contents, or the tar archive of the folder
+/// - `Err(DockerError)` - [`ContainerArchive`][DockerError::ContainerArchive] if the archive couldn't be read or holds
+///   something else
+pub(crate) fn unpack_archive(image: &str, path: &Path, archive: Vec<u8>) -> Result<Vec<u8>, DockerError> {
+    let archive_err = |source| DockerError::ContainerArchive { image: image.to_string(), path: path.to_path_buf(), source };
+    let unsupported = |message: String| archive_err(std::io::Error::new(std::io::ErrorKind::InvalidData, message));
+
+    // The file's contents, `None` if the archive holds a folder
+    let file_data = {
+        let mut tar = Archive::new(Cursor::new(&archive));
+        let mut entries = tar.entries().map_err(archive_err)?;
+        let Some(entry) = entries.next() else {
+            return Err(unsupported("archive is empty".to_string()));
+        };
+        let mut entry = entry.map_err(archive_err)?;
+
+        match entry.header().entry_type() {
+            EntryType::Regular | EntryType::Continuous => {
+                let mut data = Vec::with_capacity(entry.size() as usize);
+                entry.read_to_end(&mut data).map_err(archive_err)?;
+                Some(data)
+            },
+            EntryType::Directory => None,
+            other => return Err(unsupported(format!("{other:?} entries can't be extracted"))),
+        }
+    };
+
+    match file_data {
+        Some(data) => Ok(data),
+        None => {
+            debug!("{path:?} in {image} is a folder, returning its archive");
+            Ok(archive)
+        },
+    }
+}
@@ -0,0 +1,116 @@
+use std::collections::BTreeMap;
+
+use hyper::StatusCode;
+use shiplift::{ ContainerListOptions, Docker };
+
+use crate::env::reg_url;
+use crate::error::DockerError;
+use crate::extract::{ helper_active, remove_container };
+use crate::logging::*;
+use crate::{ HELPER_CONTAINER_PREFIX, retrieve_images };
+
+/// Tag the daemon reports for images that have none
+const UNTAGGED: &str = "<none>:<none>";
+
+/// Every image in the local daemon that has no tag left, e.g. ones replaced by a rebuild under the same tag
+///
+/// ## Returns
+/// - `Ok(Vec<String>)` - IDs of the untagged images
+/// - `Err(DockerError)` - The images couldn't be listed
+pub async fn dangling_images(docker: &Docker) -> Result<Vec<String>, DockerError> {
+    let images = retrieve_images(docker).await?;
+
+    let dangling = images
+        .into_iter()
+        .filter(|info| info.repo_tags.iter().flatten().all(|repo_tag| repo_tag == UNTAGGED))
+        .map(|info| info.id)
+        .collect();
+
+    Ok(dangling)
+}
+
+/// Every challenge image in the local daemon, i.e. every image tagged under `DOCKER_REGISTRY_URL`
+///
+/// ## Returns
+/// - `Ok(BTreeMap<String, Vec<String>>)` - Tags of each image, keyed by the image's path in the registry (the challenge
+///   folder, followed by the build path of the deploy target if it has one)
+/// - `Err(DockerError)` - The images couldn't be listed
+pub async fn registry_images(docker: &Docker) -> Result<BTreeMap<String, Vec<String>>, DockerError> {
+    let images = retrieve_images(docker).await?;
+    let registry_prefix = format!("{}/", reg_url().trim_end_matches('/'));
+
+    let mut chall_images: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for repo_tag in images.iter().flat_map(|info| info.repo_tags.iter().flatten()) {
+        let Some((path, _tag)) = repo_tag.strip_prefix(&registry_prefix).and_then(|image| image.rsplit_once(':')) else {
+            continue;
+        };
+
+        chall_images.entry(path.to_string()).or_default().push(repo_tag.clone());
+    }
+
+    Ok(chall_images)
+}
+
+/// Every `file.*` helper container [`fetch_container_file`][crate::fetch_container_file] left behind, e.g. because the
+/// server was stopped halfway
+///
+/// Helper containers of copies that are still going on are left out.
+///
+/// ## Returns
+/// - `Ok(Vec<String>)` - Names of the orphaned helper containers
+/// - `Err(DockerError)` - The containers couldn't be listed
+pub async fn orphaned_helper_containers(docker: &Docker) -> Result<Vec<String>, DockerError> {
+    let containers = match docker.containers().list(&ContainerListOptions::builder().all().build()).await {
+        Ok(containers) => containers,
+        Err(e) => {
+            error!("Error listing containers");
+            debug!("Trace: {:?}", e);
+            return Err(DockerError::daemon("list containers", e));
+        },
+    };
+
+    let orphaned = containers
+        .iter()
+        .flat_map(|container| container.names.iter())
+        .map(|name| name.trim_start_matches('/'))
+        .filter(|name| name.starts_with(HELPER_CONTAINER_PREFIX) && !helper_active(name))
+        .map(str::to_string)
+        .collect();
+
+    Ok(orphaned)
+}
+
+/// Force removes a helper container found by [`orphaned_helper_containers`][orphaned_helper_containers]
+pub async fn remove_helper_container(docker: &Docker, name: &str) -> Result<(), DockerError> {
+    remove_container(docker, name).await
+}
+
+/// Removes an image (by ID) or a single tag of an image from the local daemon
+///
+/// The daemon deletes the image itself once its last tag is removed. Images that a container still uses are kept.
+///
+/// ## Returns
+/// - `Ok(true)` - The image or tag was removed, or was already gone
+/// - `Ok(false)` - A container still uses the image
+/// - `Err(DockerError)` - The daemon refused to remove it for another reason
+pub async fn remove_image(docker: &Docker, reference: &str) -> Result<bool, DockerError> {
+    match docker.images().get(reference).delete().await {
+        Ok(_) => {
+            debug!("Removed image {reference}");
+            Ok(true)
+        },
+        Err(shiplift::Error::Fault { code, .. }) if code == StatusCode::NOT_FOUND => {
+            trace!("Image {reference} already gone");
+            Ok(true)
+        },
+        Err(shiplift::Error::Fault { code, .. }) if code == StatusCode::CONFLICT => {
+            debug!("Keeping image {reference}, a container still uses it");
+            Ok(false)
+        },
+        Err(e) => {
+            error!("Error removing image {reference}");
+            debug!("Trace: {:?}", e);
+            Err(DockerError::daemon(format!("remove image {reference}"), e))
+        },
+    }
+}
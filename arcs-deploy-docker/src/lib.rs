@@ -1,17 +1,38 @@
 use env::chall_folder_default;
 use shiplift::container::ContainerInfo;
-use shiplift::image::ImageBuildChunk;
+use std::collections::BTreeMap;
 use std::path::Path;
-use std::fs::read_dir;
 
-use shiplift::{Docker, image::{PushOptions, PullOptions, BuildOptions, ImageInfo}};
+use shiplift::{Docker, image::ImageInfo};
+use serde::Serialize;
+use uuid::Uuid;
 
 use std::path::PathBuf;
 
 mod env;
 pub use env::check_env_vars;
 
-use futures::stream::StreamExt;
+mod error;
+pub use error::DockerError;
+
+mod daemon;
+
+mod extract;
+use extract::{ ActiveHelper, unpack_archive };
+
+mod version;
+use version::{ LATEST_TAG, content_hash, version_tag };
+
+mod lint;
+pub use lint::{ LintFinding, LintSeverity, lint_build };
+
+mod gc;
+pub use gc::{ dangling_images, orphaned_helper_containers, registry_images, remove_helper_container, remove_image };
+
+mod builder;
+pub use builder::{ BuilderBackend, CommandBuilder, DockerBuilder, ImageBuilder, builder_backend, image_builder };
+
+use arcs_retry::retry;
 #[allow(unused_macros)]
 pub mod logging {
     use arcs_logging_rs::with_target;
@@ -20,14 +41,26 @@ pub mod logging {
 
 use logging::*;
 
-use crate::env::{reg_url, reg_username, reg_password};
+use crate::env::reg_url;
+
+/// Dockerfile built if a target doesn't set one, relative to the build context
+const DEFAULT_DOCKERFILE: &str = "Dockerfile";
+
+/// Start of the name of every helper container [`fetch_container_file`][fetch_container_file] creates
+pub(crate) const HELPER_CONTAINER_PREFIX: &str = "file.";
+
+/// Number of lines of a failed build's output kept in [`DockerError::BuildFailed`]
+pub(crate) const BUILD_LOG_TAIL_LINES: usize = 30;
 
 /// Creates the [`Docker`][Docker] client for use throughout the deployment process
 /// 
+/// If images are built with the rootless builder (see `IMAGE_BUILDER`), the daemon isn't needed to deploy containers, so
+/// the client is returned even if the daemon can't be reached.
+/// 
 /// ## Returns
 /// - `Ok(Docker)` - Docker client
-/// - `Err(String)` - Error trace
-pub async fn docker_login() -> Result<Docker, String> {
+/// - `Err(DockerError)` - The daemon couldn't be reached
+pub async fn docker_login() -> Result<Docker, DockerError> {
     let docker = Docker::new();
     match docker.version().await {
         Ok(_ver) => {
@@ -39,58 +72,115 @@ pub async fn docker_login() -> Result<Docker, String> {
             if err.to_string().contains("error trying to connect") {
                 warn!("Ensure Docker is running");
             }
+
+            if builder_backend() == BuilderBackend::Command {
+                warn!("Docker daemon unreachable, continuing with the rootless image builder");
+                return Ok(docker);
+            }
             
-            Err(err.to_string())
+            Err(DockerError::daemon("report its version", err))
         }, 
     }
 }
 
 /// Retrieves all Docker images on the system
-pub async fn retrieve_images(docker: &Docker) -> Result<Vec<ImageInfo>, String> {
+pub async fn retrieve_images(docker: &Docker) -> Result<Vec<ImageInfo>, DockerError> {
     match docker.images().list(&Default::default()).await {
         Ok(images) => {
             Ok(images)
         },
         Err(e) => {
             error!("Error occurred when retrieving images... {:?}", e);
-            Err(e.to_string())
+            Err(DockerError::daemon("list images", e))
         },
     }
 }
 
 /// Retrieve all Docker containers on the system
-pub async fn retrieve_containers(docker: &Docker) -> Result<Vec<ContainerInfo>, String> {
+pub async fn retrieve_containers(docker: &Docker) -> Result<Vec<ContainerInfo>, DockerError> {
     match docker.containers().list(&Default::default()).await {
         Ok(containers) => {
             Ok(containers)
         },
         Err(e) => {
             error!("Error occurred when retrieving containers... {:?}", e);
-            Err(e.to_string())
+            Err(DockerError::daemon("list containers", e))
         },
     }
 }
 
 
 
-// TODO --> fix error propagation, make them return not strings and an actual error type 
-// todo --> update documentation for this function
-/// Builds a Docker image from the Dockerfile contained in the folder with a given `chall_name`
+/// How an image is built, passed to the [`ImageBuilder`][ImageBuilder]
+/// 
+/// ## Fields
+/// - `dockerfile` - Path of the Dockerfile, relative to the build context, `Dockerfile` if not set
+/// - `args` - Build arguments (`--build-arg`) by name
+/// - `flag_arg` - Name of the build argument in `args` that is set to the challenge's flag, if there is one
+/// - `target` - Stage of a multi-stage Dockerfile to build, the last one if not set
+/// - `labels` - Labels added to the image
+/// - `no_cache` - Whether to build every layer from scratch
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BuildConfig {
+    pub dockerfile: Option<PathBuf>,
+    pub args: BTreeMap<String, String>,
+    pub flag_arg: Option<String>,
+    pub target: Option<String>,
+    pub labels: BTreeMap<String, String>,
+    pub no_cache: bool,
+}
+
+impl BuildConfig {
+    /// Path of the Dockerfile, relative to the build context
+    pub fn dockerfile(&self) -> String {
+        self.dockerfile
+            .as_deref()
+            .map(|path| path.to_string_lossy().to_string())
+            .unwrap_or_else(|| DEFAULT_DOCKERFILE.to_string())
+    }
+}
+
+/// Image made by [`build_image`][build_image]
+/// 
+/// ## Fields
+/// - `version` - Version tag of the image
+/// - `warnings` - Findings of the pre-build checks that didn't fail the build
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BuiltImage {
+    pub version: String,
+    pub warnings: Vec<LintFinding>,
+}
+
+/// Builds a Docker image for the challenge in the folder `chall_folder_name`
+/// 
+/// The build context is the challenge folder, or its `inner_path` subfolder if given, and the Dockerfile is the one set in
+/// `config` (`Dockerfile` at the root of the build context by default). Rebuilding an existing challenge is left to the
+/// builder's cache, unless `config` disables it.
 /// 
-/// Currently assumes Dockerfile is in the root of the challenge folder provided
+/// Errors aren't skipped over, they are logged and returned to the caller.
 /// 
-/// If a challenge already exists, Docker deals with rebuilding and whatnot. If an error occurs while building, logs the error and skips to the next challenge.
+/// Besides `latest`, the image is tagged with an immutable version made of the challenge repo's `commit` and a hash of the
+/// build context.
+/// 
+/// The Dockerfile and the build context are checked with [`lint_build`][lint_build] first, errors refuse the build.
 /// 
 /// ## Parameters
-/// - `docker` : [`Docker`][Docker]
-///     - Docker client to build the challenge with
-/// - `list_chall_names` : `Vec<&str>`
-///     - List of challenge names to build an image for
+/// - `builder` : [`ImageBuilder`][ImageBuilder]
+///     - Backend to build the challenge with, see [`image_builder`][image_builder]
+/// - `chall_folder_name` : `&str`
+///     - Name of the challenge folder to build an image for
+/// - `inner_path` : `Option<&Path>`
+///     - Subfolder of the challenge folder the Dockerfile is in
+/// - `commit` : `Option<&str>`
+///     - Commit of the challenge repo the build is made from
+/// - `config` : [`BuildConfig`][BuildConfig]
+///     - Dockerfile, build arguments, stage and labels of the build
 /// 
 /// ## Returns
-/// - `Ok(())` - Image(s) built successfully
-/// - `Err(String)` - Error trace
-pub async fn build_image(docker: &Docker, chall_folder_name : &str, inner_path: Option<&Path>) -> Result<(), String> {
+/// - `Ok(BuiltImage)` - Version tag of the built image, along with the warnings of the pre-build checks
+/// - `Err(DockerError)` - [`LintFailed`][DockerError::LintFailed] if the pre-build checks failed,
+///   [`BuildFailed`][DockerError::BuildFailed] if the Dockerfile failed, the builder's error otherwise
+pub async fn build_image(builder: &dyn ImageBuilder, chall_folder_name : &str, inner_path: Option<&Path>, commit: Option<&str>, config: &BuildConfig) -> Result<BuiltImage, DockerError> {
     let challenge_folder = chall_folder_default();
     let registry_url = reg_url();
 
@@ -107,165 +197,78 @@ pub async fn build_image(docker: &Docker, chall_folder_name : &str, inner_path:
         full_registry_path = PathBuf::from_iter([Path::new(registry_url), Path::new(chall_folder_name)]);
     }
 
-    let build_options = BuildOptions::builder(challenge_path.to_string_lossy().to_string())
-        .tag(full_registry_path.to_string_lossy()) // FIXME --> Investigate why certain registries will reject this tag while others will accept it... leads to issues with invalid reference image?
-        .dockerfile("Dockerfile")
-        .rm(true)
-        .build();
-        
-    let mut stream = docker.images().build(&build_options);
-    while let Some(build_result) = stream.next().await {
-        match build_result {
-            Ok(output) => {
-                match &output {
-                    ImageBuildChunk::Update {stream} => {
-                        trace!("{:?}", stream);
-                    },
-                    ImageBuildChunk::Error {error, ..} => {
-                        error!("Error building {:?}", chall_folder_name); // if this is a subfolder error, just says challname
-                        debug!("Trace: {:?}", error);
-                        warn!("Skipping challenge {:?}, check logs for details...", chall_folder_name);
-                        return Err(error.to_string());
-                    }, 
-                    ImageBuildChunk::Digest {aux} => {
-                        info!("Image digest: {:?}", aux);
-                    }
-                    ImageBuildChunk::PullStatus { .. } => {
-                        trace!("{:?}", output);
-                    }
-                }
-            },
-            Err(e) => {
-                error!("Error building docker image");
-                debug!("Docker image build error: {:?}", e);
-                return Err(e.to_string());
-            },
-        }
+    let image = full_registry_path.to_string_lossy().to_string();
+
+    let (errors, warnings): (Vec<_>, Vec<_>) = lint_build(&challenge_path, config, builder.applies_dockerignore())?
+        .into_iter()
+        .partition(|finding| finding.severity == LintSeverity::Error);
+    for warning in &warnings {
+        warn!("{image}: {}", warning.message);
+    }
+    if !errors.is_empty() {
+        error!("{image} failed {} pre-build check(s)", errors.len());
+        warn!("Skipping challenge {:?}, check logs for details...", chall_folder_name);
+        return Err(DockerError::LintFailed { image, findings: errors });
     }
-    
-    info!("{:?} image has been built", chall_folder_name); // if this is a subfolder error, just says challname
 
-    Ok(())
+    let version = version_tag(commit, &content_hash(&challenge_path, config)?);
+    debug!("Building {image} as version {version} with the {} builder", builder.name());
+
+    if let Err(e) = builder.build(&challenge_path, &image, &version, config).await {
+        warn!("Skipping challenge {:?}, check logs for details...", chall_folder_name); // if this is a subfolder error, just says challname
+        return Err(e);
+    }
+
+    info!("{:?} image has been built", chall_folder_name); // if this is a subfolder error, just says challname
+    Ok(BuiltImage { version, warnings })
 }
 
-// TODO --> add support for admin bot challenges (or challs w multiple dockerfiles) (most likely admin bot connection will be dealt w/ k8s side)
-// also probably try and figure out a better way of doing this
-/// Fetches the name of all folders in the `CHALL_FOLDER` environment variable that contain a Dockerfile in their root
-/// 
+/// Quality of life function that builds every image in `images`, one after the other, stopping at the first failure
+///
+/// Each image is given as the challenge folder and the build path of the deploy target inside of it, as listed by the
+/// challenge index of the deploy server.
+///
 /// ## Returns
-/// - `Ok(Vec<String>)` - List of challenges with Dockerfiles in root
-/// - `Err(String)` - Error trace (likely a missing environment variable or failure to read a directory)
-pub fn fetch_chall_folder_names() -> Result<Vec<String>, String> {
-    let local_repo_path = PathBuf::from(chall_folder_default());
-
-    let mut chall_names : Vec<String> = Vec::new();
-    match read_dir(&local_repo_path) {
-        Ok(local_repo) => {
-            for entry in local_repo {
-                let path = match entry {
-                    Ok(entry) => entry.path(),
-                    Err(err) => {
-                        error!("Error reading directory");
-                        debug!("Trace: {}", err);
-                        return Err("Error reading directory".to_owned());
-                    }
-                };
-
-                if path.is_dir() {
-                    let container_chall_folder = match path.read_dir() {
-                        Ok(container_chall_folder) => container_chall_folder,
-                        Err(err) => {
-                            error!("Error reading directory");
-                            debug!("Trace: {}", err);
-                            return Err("Error reading directory".to_owned());
-                        }
-                    };
-
-                    for sub_entry in container_chall_folder {
-                        let sub_entry = match sub_entry {
-                            Ok(sub_entry) => sub_entry,
-                            Err(err) => {
-                                error!("Error traversing directory");
-                                debug!("Trace: {}", err);
-                                return Err("Error traversing directory".to_owned());
-                            }
-                        };
-
-                        let pathname = sub_entry.file_name();
-                        // TODO --> add case for admin bot stuff here, most likely will end up having specific naming conventions for admin bot challs
-
-                        if pathname.eq("Dockerfile") {
-                            let chall_name = match path.file_name() {
-                                None => {
-                                    error!("Error reading challenge name");
-                                    warn!("Ensure pathnames do not end in '/' or '..'");
-                                    debug!("Reading folder name returned None.");
-                                    return Err("Error reading challenge name".to_owned());
-                                },
-                                Some(name) => {
-                                    name.to_string_lossy().to_string()
-                                }
-                            };
-
-                            info!("Found chall: {:?}", chall_name);
-                            chall_names.push(chall_name);
-                        }
-                    };
-                }
-            }
-            Ok(chall_names)
-        },
-        Err(err) => {
-            error!("Error fetching challenge folder directory");
-            debug!("Trace: {}", err);
-            debug!("Path provided: {}", local_repo_path.to_string_lossy());
-            Err(err.to_string())
-        }
+/// - `Ok(String)` - Every image was built
+/// - `Err(DockerError)` - The first image that failed to build
+pub async fn build_all_images(builder: &dyn ImageBuilder, images: &[(String, Option<PathBuf>)]) -> Result<String, DockerError> {
+    info!("Attempting to build all {} challenge image(s)...", images.len());
+    for (chall, build_path) in images {
+        info!("Building {:?} ({:?})", chall, build_path);
+        build_image(builder, chall, build_path.as_deref(), None, &BuildConfig::default()).await?;
     }
+    info!("Successfully built all images.");
+    Ok("Successfully built all images.".to_string())
 }
 
-// TODO --> Improve using yaml, iterate through all dirs, if Deploy field specified, follow path and deploy
-/// Quality of life function that builds all images with Dockerfiles that it finds in the `CHALL_FOLDER` direectory
-pub async fn build_all_images(docker : &Docker) -> Result<String, String> {
-    match fetch_chall_folder_names() {
-        Ok(names) => {
-            let available_challs_to_deploy : Vec<&str> = names.iter().map(AsRef::as_ref).collect();
-            info!("Attempting to build all challenges...");
-            for chall in &available_challs_to_deploy {
-                info!("Building {:?}", chall);
-                build_image(docker, chall, None).await?;
-            }
-            info!("Successfully built all images.");
-            Ok("Successfully built all images.".to_string())
-        },
-        Err(err) => {
-            error!("Error fetching folder names");
-            debug!("Trace: {}", err);
-            Err(err)
-        }
-    }
+/// Progress of a single layer of an image being pushed, as reported by the Docker daemon
+///
+/// ## Fields
+/// - `layer` - ID of the layer
+/// - `status` - What is happening to the layer, e.g. `Pushing`, `Pushed` or `Layer already exists`
+/// - `current` - Bytes of the layer pushed so far, if the daemon reported it
+/// - `total` - Size of the layer in bytes, if the daemon reported it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LayerProgress {
+    pub layer: String,
+    pub status: String,
+    pub current: Option<u64>,
+    pub total: Option<u64>,
 }
 
-// TODO --> Write own push function that impl stream to better update users on progress
 /// Pushes image to remote registry specified by `DOCKER_REGISTRY_URL` env var
 /// 
 /// Authenticates with the `DOCKER_REGISTRY_USERNAME` and `DOCKER_REGISTRY_PASSWORD` environment variables
 /// 
+/// Registry errors reported partway through the push (denied access, unknown blobs, ...) fail the push instead of being
+/// ignored. `on_progress` is called on every progress update of a layer.
+/// 
 /// ## Returns
-/// - `Ok(())` - Image successfully pushed
-/// - `Err(String)` - Error occurred while pushing
-pub async fn push_image(docker: &Docker, name: &str, inner_path: Option<&Path>) -> Result<(), String> {
-    let registry_username = reg_username();
-    let registry_password = reg_password();
-    let registry_url = reg_url();
-
-    let auth = shiplift::RegistryAuth::builder()
-        .username(registry_username)
-        .password(registry_password)
-        .server_address(registry_url)
-        .build();
-
-    let mut complete_url = PathBuf::from(registry_url);
+/// - `Ok(Option<String>)` - Image successfully pushed, along with the digest the registry stored `version` under if the
+///   builder reported one
+/// - `Err(DockerError)` - Error occurred while pushing
+pub async fn push_image(builder: &dyn ImageBuilder, name: &str, inner_path: Option<&Path>, version: &str, on_progress: impl Fn(&LayerProgress) + Sync) -> Result<Option<String>, DockerError> {
+    let mut complete_url = PathBuf::from(reg_url());
     
     complete_url.push(name);
     
@@ -276,42 +279,34 @@ pub async fn push_image(docker: &Docker, name: &str, inner_path: Option<&Path>)
         info!("Pushing image: {}...", name);
     }
     
-    // Push does not impl stream so have to deal with less data for pushing containers
-    // TODO -- write own function using docker API to push containers
-    match docker.images().push(&complete_url.to_string_lossy(), &PushOptions::builder().auth(auth).build()).await {
-        Ok(stream) => {
-            stream
+    let image = complete_url.to_string_lossy().to_string();
+
+    match retry("registry push", &image, || builder.push(&image, version, &on_progress)).await {
+        Ok(digest) => {
+            info!("Pushed image: {} ({})", name, digest.as_deref().unwrap_or("no digest reported"));
+            Ok(digest)
         },
         Err(e) => {
             error!("Error pushing image");
             error!("Trace: {:?}", e);
-            return Err(e.to_string());
+            Err(e)
         },
-    };
-
-    info!("Pushed image: {}", name);
-    Ok(())
+    }
 }
 
 /// Pulls image from remote registry specified by `DOCKER_REGISTRY_URL` env var
 /// 
 /// Authenticates with the `DOCKER_REGISTRY_USERNAME` and `DOCKER_REGISTRY_PASSWORD` environment variables
 /// 
+/// Pulls the `version` tag of the image if given, `latest` otherwise. If `expected_digest` is given (the digest
+/// [`push_image`][push_image] returned), the pulled image has to have that digest, so a stale image left in the registry by
+/// an earlier push can't be deployed by mistake.
+/// 
 /// ## Returns
-/// - `Ok(())` - Image successfully pulled
-/// - `Err(String)` - Error occurred while pulling
-pub async fn pull_image(docker: &Docker, name: &str, inner_path: Option<&Path>) -> Result<(), String>{
-    let registry_username = reg_username();
-    let registry_password = reg_password();
-    let registry_url = reg_url();
-
-    let auth = shiplift::RegistryAuth::builder()
-        .username(registry_username)
-        .password(registry_password)
-        .server_address(registry_url)
-        .build();
-
-    let mut complete_url = PathBuf::from(registry_url);
+/// - `Ok(Option<String>)` - Image successfully pulled, along with its digest if the builder reported one
+/// - `Err(DockerError)` - Error occurred while pulling, or the pulled image isn't the expected one
+pub async fn pull_image(builder: &dyn ImageBuilder, name: &str, inner_path: Option<&Path>, version: Option<&str>, expected_digest: Option<&str>) -> Result<Option<String>, DockerError>{
+    let mut complete_url = PathBuf::from(reg_url());
     complete_url.push(name);
 
     if let Some(path) = inner_path {
@@ -321,37 +316,20 @@ pub async fn pull_image(docker: &Docker, name: &str, inner_path: Option<&Path>)
         info!("Attempting to pull image: {}", name);
     }
 
-    let mut stream = docker.images().pull(&PullOptions::builder().auth(auth).image(complete_url.to_string_lossy()).build());
-    while let Some(data) = stream.next().await {
-        match data {
-            Ok(output) => {
-                match &output {
-                    ImageBuildChunk::Update {stream} => {
-                        trace!("{:?}", stream);
-                    },
-                    ImageBuildChunk::Error {error, ..} => {
-                        error!("Error building {:?}", name);
-                        debug!("Trace: {:?}", error);
-                        warn!("Skipping challenge {:?}, check logs for details...", name);
-                    }, 
-                    ImageBuildChunk::Digest {aux} => {
-                        info!("Image digest: {:?}", aux);
-                    },
-                    ImageBuildChunk::PullStatus { .. } => {
-                        trace!("{:?}", output);
-                    }
-                }
-            },
-            Err(e) => {
-                error!("Error pulling image");
-                debug!("Trace: {:?}", e);
-                return Err(e.to_string());
-            },
-        };
+    let image = complete_url.to_string_lossy().to_string();
+    let tag = version.unwrap_or(LATEST_TAG);
+
+    let digest = retry("registry pull", &image, || builder.pull(&image, tag)).await?;
+
+    if let Some(expected) = expected_digest {
+        if digest.as_deref() != Some(expected) {
+            error!("Pulled {image} with digest {digest:?}, but {expected} was pushed");
+            return Err(DockerError::DigestMismatch { image, expected: expected.to_string(), actual: digest });
+        }
     }
 
-    info!("Successfully pulled image: {}", name);
-    Ok(())
+    info!("Successfully pulled image: {} ({})", name, digest.as_deref().unwrap_or("no digest reported"));
+    Ok(digest)
 }
 
 /// Deletes a local Docker image
@@ -360,8 +338,8 @@ pub async fn pull_image(docker: &Docker, name: &str, inner_path: Option<&Path>)
 /// 
 /// ## Returns
 /// - `Ok(())` - Image successfully deleted
-/// - `Err(String)` - Error occurred while deleting
-pub async fn delete_image(docker: &Docker, name: &str, inner_path: Option<&Path>) -> Result<(), String> {
+/// - `Err(DockerError)` - Error occurred while deleting
+pub async fn delete_image(docker: &Docker, name: &str, inner_path: Option<&Path>) -> Result<(), DockerError> {
     info!("Deleting image: {}", name);
 
     let registry_url = reg_url();
@@ -378,7 +356,7 @@ pub async fn delete_image(docker: &Docker, name: &str, inner_path: Option<&Path>
             warn!("Image '{}' not found", full_challenge_name.to_string_lossy());
             debug!("Trace: {:?}", e);
             warn!("Skipping deletion of image: {}", name);
-            return Err(e.to_string());
+            return Ok(());
         }    
     };
 
@@ -390,152 +368,83 @@ pub async fn delete_image(docker: &Docker, name: &str, inner_path: Option<&Path>
         Err(e) => {
             warn!("Error deleting image");
             error!("Trace: {:?}", e);
-            Err(e.to_string())
+            Err(DockerError::daemon(format!("delete image {}", full_challenge_name.to_string_lossy()), e))
         }
     }
 }
 
-// TODO --> make this nicer, feels really hacky atm
-pub async fn fetch_container_file(docker: &Docker, image: &str, file_path: &Path) -> Result<Vec<u8>, String> {
-    pub async fn cleanup(name: &str, docker: &Docker) -> Result<(), String> {
-        let options = shiplift::container::RmContainerOptions::builder().force(true).build();
-        match docker.containers().get(name).remove(options).await {
-            Ok(_) => {
-                info!("Container {name} deleted successfully");
-                Ok(())
-            },
-            Err(e) => {
-                error!("Error deleting container {name}");
-                debug!("Trace: {:?}", e);
-                Err(e.to_string())
-            }
-        }
-    }
+/// Name of the helper container that [`fetch_container_file`][fetch_container_file] copies `file_path` out of `image` with
+/// 
+/// Every call gets its own name, so concurrent copies of the same file don't remove each other's helper container
+fn container_nameize(image: &str, file_path: &Path) -> String {
+    format!("{}.{}", helper_name_prefix(image, file_path), Uuid::new_v4().simple())
+}
 
-    pub fn container_nameize(image: &str, file_path: &Path) -> String {
-        let unescaped = format!("{image}//getfile-{}", file_path.display());
+/// Prefix shared by the names of every helper container copying `file_path` (or anything under it if it's empty) out of `image`
+fn helper_name_prefix(image: &str, file_path: &Path) -> String {
+    let unescaped = format!("{image}//getfile-{}", file_path.display());
+
+    let unescaped_iter = unescaped
+        .chars()
+        .map(|c| {
+            match c {
+                '/' | '-' | '_' | '.' => '_',
+                'a'..='z' | 'A'..='Z' | '0'..='9' => c.to_ascii_lowercase(),
+                _ => '_'
+            }
+        });
 
-        let unescaped_iter = unescaped
-            .chars()
-            .map(|c| {
-                match c {
-                    '/' | '-' | '_' | '.' => '_',
-                    'a'..='z' | 'A'..='Z' | '0'..='9' => c.to_ascii_lowercase(),
-                    _ => '_'
-                }
-            });
+    HELPER_CONTAINER_PREFIX.chars().chain(unescaped_iter).collect::<String>()
+}
 
-        "file.".chars().chain(unescaped_iter).collect::<String>()
-    }
+/// Force removes every `file.*` helper container left behind by [`fetch_container_file`][fetch_container_file] for an image,
+/// through the builder set by `IMAGE_BUILDER`
+/// 
+/// ## Returns
+/// - `Ok(usize)` - Number of containers removed
+/// - `Err(DockerError)` - Error trace if the containers couldn't be listed or removed
+pub async fn delete_file_containers(docker: &Docker, image: &str) -> Result<usize, DockerError> {
+    let prefix = helper_name_prefix(image, Path::new(""));
+    image_builder(docker).remove_helpers(&prefix).await
+}
 
-    warn!("IMAGE REQUESTING FILE INSIDE CONTAINER RUNNING...");
+/// Copies a file (or a folder) out of the latest `image` of a challenge, for uploading it as a static file
+/// 
+/// The image is pulled and a helper container is created from it with the builder set by `IMAGE_BUILDER`, but never
+/// started, so images without a shell (distroless, `scratch`, ...) work too. The helper container is removed once the path
+/// is copied out of it.
+/// 
+/// ## Returns
+/// - `Ok(Vec<u8>)` - Contents of the file as they are, or a tar archive of the folder
+/// - `Err(DockerError)` - [`ContainerFileMissing`][DockerError::ContainerFileMissing] if the path doesn't exist in the image,
+///   the builder's error otherwise
+pub async fn fetch_container_file(docker: &Docker, image: &str, file_path: &Path) -> Result<Vec<u8>, DockerError> {
+    let builder = image_builder(docker);
 
-    // Pull container
     trace!("Pulling image {}", image);
-    pull_image(docker, image, None).await?;
+    pull_image(builder.as_ref(), image, None, None, None).await?;
 
-
-    // Create container
-    trace!("Creating container for image {image:?}");
-
-    use shiplift::ContainerOptions;
     let mut full_image_path = PathBuf::from(reg_url());
     full_image_path.push(image);
     let full_image_path = full_image_path.to_string_lossy();
 
-    let container_name = container_nameize(
-        image,
-        file_path,
-    );
-    let container_options = ContainerOptions::builder(&full_image_path)
-        .name(&container_name)
-        .cmd(vec![
-            "/bin/bash",
-            "-c",
-            "sleep infinity",
-        ])
-        .build();
-
-    let container_create_result = docker.containers().create(&container_options).await;
-    let created_container_id = match container_create_result {
-        Ok(info) => {
-            info!("Container {container_name:?} created successfully");
-            info.id
-        },
-        Err(e) => {
-            error!("Error creating container {container_name:?}");
-            debug!("Trace: {:?}", e);
-            return Err(e.to_string());
-        }
-    };
-    info!("Container {container_name:?} created with id {created_container_id}");
+    let container_name = container_nameize(image, file_path);
+    let _active = ActiveHelper::new(&container_name);
 
+    let contents = match builder.copy_from_image(&full_image_path, &container_name, file_path).await {
+        Ok(archive) => unpack_archive(image, file_path, archive),
+        Err(e) => Err(e),
+    };
 
-    // Start container to be able to exec commands
-    trace!("Starting container {container_name:?}");
-    match docker.containers().get(&created_container_id).start().await {
-        Ok(_) => {
-            info!("Container {container_name:?} started successfully");
+    match contents {
+        Ok(contents) => {
+            info!("Copied {file_path:?} out of {image} ({} bytes)", contents.len());
+            Ok(contents)
         },
         Err(e) => {
-            error!("Error starting container {container_name:?}");
+            error!("Error copying {file_path:?} out of {image}");
             debug!("Trace: {:?}", e);
-            return Err(e.to_string());
-        }
-    }
-
-    use shiplift::ExecContainerOptions;
-    trace!("Executing file get command in container {container_name:?}");
-
-    let file_path = file_path.to_string_lossy();
-    let exec_options = ExecContainerOptions::builder()
-        .attach_stdout(true)
-        .cmd(vec!["cat", &file_path])
-        .build();
-    let mut exec_stream = docker
-        .containers()
-        .get(&container_name)
-        .exec(&exec_options);
-
-    let mut stdout = Vec::new();
-    let mut stderr = Vec::new();
-
-    while let Some(exec_result) = exec_stream.next().await {
-        match exec_result {
-            Ok(exec_result) => {
-                match exec_result {
-                    shiplift::tty::TtyChunk::StdOut(chunk) => {
-                        stdout.extend_from_slice(&chunk);
-                    },
-                    shiplift::tty::TtyChunk::StdErr(chunk) => {
-                        stderr.extend_from_slice(&chunk);
-                    },
-                    _ => {},
-                }
-            },
-            Err(e) => {
-                // Delete the container to not leak resources
-                trace!("Cleaning up container {container_name:?}");
-                let _ = cleanup(&created_container_id, docker).await;
-
-                error!("Error executing command in container");
-                debug!("Trace: {:?}", e);
-                return Err(e.to_string());
-            },
-        }
-    }
-    info!("Got file contents from container {container_name:?}");
-
-    // Delete the container to not leak resources
-    trace!("Cleaning up container {container_name:?}");
-    cleanup(&created_container_id, docker).await?;
-
-    if stderr.len() > 0 {
-        error!("Error executing command in container");
-        error!("Stderr contents:\n{}", String::from_utf8_lossy(&stderr));
-        return Err("Error executing command in container".to_string());
+            Err(e)
+        },
     }
-
-
-    Ok(stdout)
 }
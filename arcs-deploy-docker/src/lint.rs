@@ -0,0 +1,463 @@
+use std::collections::BTreeSet;
+use std::fs::read_to_string;
+use std::io;
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::BuildConfig;
+use crate::env::{ allowed_base_images, build_lint, max_build_context_mb };
+use crate::error::DockerError;
+use crate::logging::*;
+use crate::version::{ LATEST_TAG, context_files };
+
+/// Largest build context allowed if `MAX_BUILD_CONTEXT_MB` isn't set
+const DEFAULT_MAX_CONTEXT_MB: u64 = 100;
+
+/// File listing the paths left out of the build context, same as `docker build`
+const DOCKERIGNORE: &str = ".dockerignore";
+
+/// How serious a [`LintFinding`][LintFinding] is
+///
+/// ## Variants
+/// - `Warning` - Reported along with the deployment, the build goes on
+/// - `Error` - The build is refused if `BUILD_LINT` is `strict`, otherwise reported like a warning
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LintSeverity {
+    Warning,
+    Error,
+}
+
+/// Something wrong with a Dockerfile or its build context, found before building
+///
+/// ## Fields
+/// - `rule` - Check that found it, e.g. `context_size` or `solve_file_copied`
+/// - `severity` - Whether it fails the build
+/// - `message` - What is wrong, for the challenge author
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LintFinding {
+    pub rule: &'static str,
+    pub severity: LintSeverity,
+    pub message: String,
+}
+
+impl LintFinding {
+    fn new(rule: &'static str, severity: LintSeverity, message: impl Into<String>) -> Self {
+        Self { rule, severity, message: message.into() }
+    }
+}
+
+/// What happens to the findings of the lint, set with `BUILD_LINT`
+///
+/// ## Variants
+/// - `Strict` - Errors fail the build
+/// - `Warn` - Every finding is only a warning, the default
+/// - `Off` - Nothing is checked
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LintMode {
+    Strict,
+    Warn,
+    Off,
+}
+
+fn lint_mode() -> LintMode {
+    match build_lint().map(str::to_lowercase).as_deref() {
+        Some("off" | "false" | "0") => LintMode::Off,
+        Some("strict") => LintMode::Strict,
+        _ => LintMode::Warn,
+    }
+}
+
+/// Largest build context allowed in bytes, set in megabytes with `MAX_BUILD_CONTEXT_MB`
+fn max_context_bytes() -> u64 {
+    max_build_context_mb()
+        .and_then(|mb| mb.parse().ok())
+        .unwrap_or(DEFAULT_MAX_CONTEXT_MB)
+        * 1024 * 1024
+}
+
+/// Prefixes base images have to start with, set as a comma separated list with `ALLOWED_BASE_IMAGES`, any if empty
+fn allowed_bases() -> Vec<&'static str> {
+    allowed_base_images()
+        .map(|bases| bases.split(',').map(str::trim).filter(|base| !base.is_empty()).collect())
+        .unwrap_or_default()
+}
+
+/// Path pattern of a `.dockerignore` or a `COPY` source, relative to the build context
+///
+/// Segments can use `*` and `?` wildcards, and `**` matches any number of segments. A pattern matching a folder matches
+/// everything in it.
+#[derive(Debug)]
+struct PathPattern {
+    segments: Vec<String>,
+    negated: bool,
+}
+
+impl PathPattern {
+    fn parse(pattern: &str) -> Self {
+        let (negated, pattern) = match pattern.strip_prefix('!') {
+            Some(pattern) => (true, pattern),
+            None => (false, pattern),
+        };
+
+        let segments = pattern
+            .trim()
+            .split('/')
+            .filter(|segment| !segment.is_empty() && *segment != ".")
+            .map(str::to_string)
+            .collect();
+
+        Self { segments, negated }
+    }
+
+    fn matches(&self, path: &[&str]) -> bool {
+        // An empty pattern is the whole context
+        self.segments.is_empty() || (1..=path.len()).any(|len| match_segments(&self.segments, &path[..len]))
+    }
+}
+
+fn match_segments(pattern: &[String], path: &[&str]) -> bool {
+    match (pattern.first(), path.first()) {
+        (None, None) => true,
+        (Some(segment), _) if segment == "**" => {
+            match_segments(&pattern[1..], path) || (!path.is_empty() && match_segments(pattern, &path[1..]))
+        },
+        (Some(segment), Some(name)) => match_wildcard(segment, name) && match_segments(&pattern[1..], &path[1..]),
+        _ => false,
+    }
+}
+
+/// Whether `text` matches a single segment `pattern` with `*` and `?` wildcards
+fn match_wildcard(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+
+    // matches[j] - whether the pattern so far matches the first j characters of the text
+    let mut matches = vec![false; text.len() + 1];
+    matches[0] = true;
+
+    for &c in &pattern {
+        let mut next = vec![false; text.len() + 1];
+        for j in 0..=text.len() {
+            next[j] = match c {
+                '*' => matches[j] || (j > 0 && next[j - 1]),
+                '?' => j > 0 && matches[j - 1],
+                c => j > 0 && matches[j - 1] && text[j - 1] == c,
+            };
+        }
+        matches = next;
+    }
+
+    matches[text.len()]
+}
+
+/// Patterns of the `.dockerignore` of a build context, empty if it has none
+#[derive(Default)]
+struct DockerIgnore {
+    patterns: Vec<PathPattern>,
+}
+
+impl DockerIgnore {
+    fn load(context: &Path) -> Self {
+        Self::parse(&read_to_string(context.join(DOCKERIGNORE)).unwrap_or_default())
+    }
+
+    fn parse(dockerignore: &str) -> Self {
+        let patterns = dockerignore
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(PathPattern::parse)
+            .collect();
+
+        Self { patterns }
+    }
+
+    /// Whether a file is left out of the build context, the last matching pattern wins
+    fn is_ignored(&self, path: &[&str]) -> bool {
+        self.patterns
+            .iter()
+            .filter(|pattern| pattern.matches(path))
+            .last()
+            .is_some_and(|pattern| !pattern.negated)
+    }
+}
+
+/// Instructions of a Dockerfile, with line continuations joined and comments removed
+fn instructions(dockerfile: &str) -> Vec<(String, Vec<String>)> {
+    let mut instructions = vec![];
+    let mut current = String::new();
+
+    for line in dockerfile.lines() {
+        let line = line.trim();
+        if line.starts_with('#') {
+            continue;
+        }
+
+        if let Some(continued) = line.strip_suffix('\\') {
+            current.push_str(continued);
+            current.push(' ');
+            continue;
+        }
+        current.push_str(line);
+
+        let mut words = current.split_whitespace().map(str::to_string);
+        if let Some(instruction) = words.next() {
+            instructions.push((instruction.to_uppercase(), words.collect()));
+        }
+        current.clear();
+    }
+
+    instructions
+}
+
+/// Sources of a `COPY`/`ADD` instruction, `None` if it copies from another stage or image
+fn copy_sources(args: &[String]) -> Option<Vec<String>> {
+    if args.iter().any(|arg| arg.starts_with("--from")) {
+        return None;
+    }
+
+    let args: Vec<&String> = args.iter().filter(|arg| !arg.starts_with("--")).collect();
+    let joined = args.iter().map(|arg| arg.as_str()).collect::<Vec<_>>().join(" ");
+
+    // Exec form, `COPY ["src", "dest"]`
+    let mut paths = match serde_json::from_str::<Vec<String>>(&joined) {
+        Ok(paths) => paths,
+        Err(_) => args.into_iter().cloned().collect(),
+    };
+    paths.pop();
+
+    Some(paths.into_iter().filter(|path| !path.contains("://")).collect())
+}
+
+/// Checks the base images of a Dockerfile
+fn lint_base_images(instructions: &[(String, Vec<String>)], findings: &mut Vec<LintFinding>) {
+    let allowed = allowed_bases();
+    let mut stages: BTreeSet<String> = BTreeSet::new();
+
+    for (_, args) in instructions.iter().filter(|(instruction, _)| instruction == "FROM") {
+        let mut words = args.iter().filter(|arg| !arg.starts_with("--"));
+        let Some(image) = words.next() else { continue };
+        let stage = match (words.next(), words.next()) {
+            (Some(keyword), Some(stage)) if keyword.eq_ignore_ascii_case("as") => Some(stage.to_lowercase()),
+            _ => None,
+        };
+
+        // Earlier stages, `scratch` and images set by build arguments can't be checked
+        let checkable = !stages.contains(&image.to_lowercase()) && image != "scratch" && !image.contains('$');
+        stages.extend(stage);
+        if !checkable {
+            continue;
+        }
+
+        if !allowed.is_empty() && !allowed.iter().any(|base| image.starts_with(base)) {
+            findings.push(LintFinding::new(
+                "disallowed_base_image",
+                LintSeverity::Error,
+                format!("Base image {image} isn't allowed, it has to start with one of: {}", allowed.join(", ")),
+            ));
+        }
+
+        let name = image.rsplit('/').next().unwrap_or(image);
+        let tag = name.split_once(':').map(|(_, tag)| tag);
+        if !image.contains('@') && tag.map_or(true, |tag| tag == LATEST_TAG) {
+            findings.push(LintFinding::new(
+                "unpinned_base_image",
+                LintSeverity::Warning,
+                format!("Base image {image} uses the `latest` tag, pin a version so rebuilds don't change it"),
+            ));
+        }
+    }
+}
+
+/// Checks the files of the build context, and the ones the Dockerfile copies into the image
+///
+/// Files matched by the `.dockerignore` are only skipped if the builder applies it (`apply_dockerignore`).
+fn lint_context(context: &Path, instructions: &[(String, Vec<String>)], apply_dockerignore: bool, findings: &mut Vec<LintFinding>) -> io::Result<()> {
+    let ignore = if apply_dockerignore { DockerIgnore::load(context) } else { DockerIgnore::default() };
+    let remedy = if apply_dockerignore {
+        "add it to the .dockerignore"
+    } else {
+        "move it out of the build context (the Docker daemon builder doesn't apply the .dockerignore)"
+    };
+    let copied: Vec<PathPattern> = instructions
+        .iter()
+        .filter(|(instruction, _)| instruction == "COPY" || instruction == "ADD")
+        .filter_map(|(_, args)| copy_sources(args))
+        .flatten()
+        .map(|source| PathPattern::parse(&source))
+        .collect();
+
+    let mut total_bytes = 0;
+    let mut largest: Option<(u64, String)> = None;
+
+    for file in context_files(context)? {
+        let display = file.to_string_lossy().to_string();
+        let segments: Vec<&str> = display.split('/').collect();
+        if ignore.is_ignored(&segments) {
+            continue;
+        }
+
+        // A symlink that points nowhere has no size
+        let size = std::fs::metadata(context.join(&file)).map(|metadata| metadata.len()).unwrap_or_default();
+        total_bytes += size;
+        if largest.as_ref().map_or(true, |(largest_size, _)| size > *largest_size) {
+            largest = Some((size, display.clone()));
+        }
+
+        let file_name = segments.last().copied().unwrap_or_default().to_lowercase();
+        if file_name == "core" || file_name.strip_prefix("core.").is_some_and(|pid| pid.chars().all(|c| c.is_ascii_digit())) {
+            findings.push(LintFinding::new(
+                "core_dump",
+                LintSeverity::Warning,
+                format!("{display} looks like a core dump, delete it or {remedy}"),
+            ));
+        }
+
+        if !copied.iter().any(|source| source.matches(&segments)) {
+            continue;
+        }
+        if ["solve", "solution", "exploit"].iter().any(|prefix| file_name.starts_with(prefix)) {
+            findings.push(LintFinding::new(
+                "solve_file_copied",
+                LintSeverity::Error,
+                format!("{display} looks like a solve script and is copied into the image, {remedy}"),
+            ));
+        } else if file_name.starts_with("flag") {
+            findings.push(LintFinding::new(
+                "flag_file_copied",
+                LintSeverity::Warning,
+                format!("{display} looks like a flag and is copied into the image, prefer the flag build argument"),
+            ));
+        }
+    }
+
+    let max_bytes = max_context_bytes();
+    if total_bytes > max_bytes {
+        let largest = largest.map(|(size, file)| format!(", the largest file is {file} ({} MB)", size / 1024 / 1024)).unwrap_or_default();
+        findings.push(LintFinding::new(
+            "context_size",
+            LintSeverity::Error,
+            format!("Build context is {} MB, more than the {} MB allowed{largest}", total_bytes / 1024 / 1024, max_bytes / 1024 / 1024),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Checks a Dockerfile and its build context before building, see `BUILD_LINT`
+///
+/// Files left out by the `.dockerignore` aren't checked if the builder applies it (`apply_dockerignore`, see
+/// [`ImageBuilder::applies_dockerignore`][crate::ImageBuilder::applies_dockerignore]), since they never reach the builder.
+/// Otherwise every file of the context is. The checks are:
+/// - `context_size` - The context is bigger than `MAX_BUILD_CONTEXT_MB`
+/// - `core_dump` - The context contains a core dump
+/// - `solve_file_copied` / `flag_file_copied` - A solve script or a flag file is copied into the image
+/// - `disallowed_base_image` - A base image doesn't start with one of `ALLOWED_BASE_IMAGES`
+/// - `unpinned_base_image` - A base image has no tag, or the `latest` one
+///
+/// ## Returns
+/// - `Ok(Vec<LintFinding>)` - Everything found, errors only if `BUILD_LINT` is `strict`
+/// - `Err(DockerError)` - [`ChallFolder`][DockerError::ChallFolder] if the build context couldn't be read
+pub fn lint_build(context: &Path, config: &BuildConfig, apply_dockerignore: bool) -> Result<Vec<LintFinding>, DockerError> {
+    let mode = lint_mode();
+    if mode == LintMode::Off {
+        return Ok(vec![]);
+    }
+
+    // A missing Dockerfile is left for the builder to report
+    let instructions = match read_to_string(context.join(config.dockerfile())) {
+        Ok(dockerfile) => instructions(&dockerfile),
+        Err(e) => {
+            debug!("Not linting the Dockerfile of {context:?}: {e}");
+            vec![]
+        },
+    };
+
+    let mut findings = vec![];
+    lint_base_images(&instructions, &mut findings);
+    lint_context(context, &instructions, apply_dockerignore, &mut findings)
+        .map_err(|source| DockerError::ChallFolder { path: context.to_path_buf(), source })?;
+
+    if mode == LintMode::Warn {
+        for finding in &mut findings {
+            finding.severity = LintSeverity::Warning;
+        }
+    }
+
+    Ok(findings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn path(path: &str) -> Vec<&str> {
+        path.split('/').collect()
+    }
+
+    #[test]
+    fn wildcards_match_within_a_segment() {
+        assert!(match_wildcard("*.py", "solve.py"));
+        assert!(match_wildcard("solve?.py", "solve2.py"));
+        assert!(match_wildcard("*", ""));
+        assert!(!match_wildcard("*.py", "solve.pyc"));
+        assert!(!match_wildcard("solve?.py", "solve.py"));
+    }
+
+    #[test]
+    fn patterns_match_folders_and_everything_in_them() {
+        let pattern = PathPattern::parse("./src");
+        assert!(pattern.matches(&path("src")));
+        assert!(pattern.matches(&path("src/main.c")));
+        assert!(!pattern.matches(&path("srcs/main.c")));
+
+        // `COPY . .` copies the whole context
+        assert!(PathPattern::parse(".").matches(&path("solve/solve.py")));
+    }
+
+    #[test]
+    fn double_star_matches_any_number_of_segments() {
+        let pattern = PathPattern::parse("**/solve*");
+        assert!(pattern.matches(&path("solve.py")));
+        assert!(pattern.matches(&path("a/b/solve.py")));
+        assert!(!pattern.matches(&path("a/b/exploit.py")));
+
+        let pattern = PathPattern::parse("src/**/*.c");
+        assert!(pattern.matches(&path("src/main.c")));
+        assert!(pattern.matches(&path("src/a/b/main.c")));
+        assert!(!pattern.matches(&path("lib/main.c")));
+    }
+
+    #[test]
+    fn dockerignore_last_matching_pattern_wins() {
+        let ignore = DockerIgnore::parse("# solves\n\nsolve*\n!solve_template.py\n*.core\n");
+        assert!(ignore.is_ignored(&path("solve.py")));
+        assert!(!ignore.is_ignored(&path("solve_template.py")));
+        assert!(ignore.is_ignored(&path("crash.core")));
+        assert!(!ignore.is_ignored(&path("src/solve.py")));
+        assert!(!ignore.is_ignored(&path("chall.c")));
+
+        assert!(!DockerIgnore::default().is_ignored(&path("solve.py")));
+    }
+
+    #[test]
+    fn copy_sources_drop_the_destination_and_flags() {
+        let args = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+
+        assert_eq!(copy_sources(&args(&["--chown=1000", "src", "flag.txt", "/app/"])), Some(vec!["src".to_string(), "flag.txt".to_string()]));
+        assert_eq!(copy_sources(&args(&["[\"solve.py\",", "\"/app/\"]"])), Some(vec!["solve.py".to_string()]));
+        assert_eq!(copy_sources(&args(&["https://example.com/file", "/app/"])), Some(vec![]));
+        assert_eq!(copy_sources(&args(&["--from=build", "/out", "/app/"])), None);
+    }
+
+    #[test]
+    fn instructions_join_continuations_and_skip_comments() {
+        let dockerfile = "FROM python:3.12 AS base\n# comment\ncopy --chown=1000 \\\n    . /app\n";
+        assert_eq!(instructions(dockerfile), vec![
+            ("FROM".to_string(), vec!["python:3.12".to_string(), "AS".to_string(), "base".to_string()]),
+            ("COPY".to_string(), vec!["--chown=1000".to_string(), ".".to_string(), "/app".to_string()]),
+        ]);
+    }
+}
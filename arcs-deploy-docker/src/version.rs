@@ -0,0 +1,176 @@
+use std::collections::BTreeSet;
+use std::fs::{ metadata, read_dir, read_link };
+use std::io;
+use std::path::{ Path, PathBuf };
+
+use sha2::{ Digest, Sha256 };
+use shiplift::Docker;
+
+use crate::BuildConfig;
+use crate::env::image_retained_versions;
+use crate::error::DockerError;
+use crate::logging::*;
+
+/// Number of version tags of an image kept on the local daemon if `IMAGE_RETAINED_VERSIONS` isn't set
+const DEFAULT_RETAINED_VERSIONS: usize = 5;
+
+/// Number of hex characters of the commit and the content hash used in a version tag
+const VERSION_HASH_LEN: usize = 12;
+
+/// Tag that is moved to the latest build of an image, never pruned
+pub(crate) const LATEST_TAG: &str = "latest";
+
+/// Every file in a build context, relative to the context, in a stable order
+///
+/// Symlinks are followed, so a symlinked folder is walked like any other. A symlink that points nowhere is listed as a
+/// file, and a folder that links back to one of its parents is only walked once.
+pub(crate) fn context_files(context: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut files = vec![];
+    let mut folders = vec![PathBuf::new()];
+    let mut walked: BTreeSet<PathBuf> = BTreeSet::new();
+
+    while let Some(folder) = folders.pop() {
+        let path = context.join(&folder);
+        if !walked.insert(path.canonicalize()?) {
+            continue;
+        }
+
+        for entry in read_dir(&path)? {
+            let entry = entry?;
+            let relative_path = folder.join(entry.file_name());
+
+            let is_dir = match metadata(entry.path()) {
+                Ok(resolved) => resolved.is_dir(),
+                Err(_) => false,
+            };
+
+            if is_dir {
+                folders.push(relative_path);
+            } else {
+                files.push(relative_path);
+            }
+        }
+    }
+
+    files.sort();
+    Ok(files)
+}
+
+/// Contents of a file of a build context, or where it points if it is a symlink that points nowhere
+fn file_contents(path: &Path) -> io::Result<Vec<u8>> {
+    match std::fs::read(path) {
+        Ok(contents) => Ok(contents),
+        Err(e) => match read_link(path) {
+            Ok(target) => Ok(target.to_string_lossy().as_bytes().to_vec()),
+            Err(_) => Err(e),
+        },
+    }
+}
+
+/// Feeds a single named field of the build config to the hasher, so that fields can't run into each other
+fn hash_field(hasher: &mut Sha256, name: &str, value: &str) {
+    hasher.update(name.as_bytes());
+    hasher.update([0]);
+    hasher.update(value.as_bytes());
+    hasher.update([0]);
+}
+
+/// SHA-256 over the paths and contents of every file in a build context and the build's config, as hex
+///
+/// The Dockerfile, build arguments, stage and labels are part of the hash since the same context built with them set
+/// otherwise is another image. The value of the flag argument is left out, so the flag doesn't end up in anything derived
+/// from the hash.
+///
+/// ## Returns
+/// - `Ok(String)` - Hash of the build context
+/// - `Err(DockerError)` - A file of the build context couldn't be read
+pub(crate) fn content_hash(context: &Path, config: &BuildConfig) -> Result<String, DockerError> {
+    let chall_folder_err = |source| DockerError::ChallFolder { path: context.to_path_buf(), source };
+
+    let mut hasher = Sha256::new();
+    for file in context_files(context).map_err(chall_folder_err)? {
+        hasher.update(file.to_string_lossy().as_bytes());
+        hasher.update([0]);
+        hasher.update(file_contents(&context.join(&file)).map_err(chall_folder_err)?);
+        hasher.update([0]);
+    }
+
+    hash_field(&mut hasher, "dockerfile", &config.dockerfile());
+    for (name, value) in &config.args {
+        let value = if config.flag_arg.as_ref() == Some(name) { "" } else { value.as_str() };
+        hash_field(&mut hasher, &format!("arg:{name}"), value);
+    }
+    hash_field(&mut hasher, "target", config.target.as_deref().unwrap_or_default());
+    for (name, value) in &config.labels {
+        hash_field(&mut hasher, &format!("label:{name}"), value);
+    }
+
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Immutable tag of a build, `<commit>-<content hash>` (or just the content hash if the commit isn't known)
+pub(crate) fn version_tag(commit: Option<&str>, content_hash: &str) -> String {
+    let short = |hash: &str| hash.chars().take(VERSION_HASH_LEN).collect::<String>();
+
+    match commit {
+        Some(commit) => format!("{}-{}", short(commit), short(content_hash)),
+        None => short(content_hash),
+    }
+}
+
+/// Number of version tags of an image kept on the local daemon, set with `IMAGE_RETAINED_VERSIONS`
+fn retained_versions() -> usize {
+    image_retained_versions()
+        .and_then(|versions| versions.parse().ok())
+        .unwrap_or(DEFAULT_RETAINED_VERSIONS)
+}
+
+/// Removes the oldest version tags of an image from the local daemon, keeping the number set by `IMAGE_RETAINED_VERSIONS`
+///
+/// `latest` and `current_tag` are never removed. Images that lose their last tag are deleted by the daemon, unless a
+/// container still uses them.
+///
+/// ## Returns
+/// - `Ok(usize)` - Number of tags removed
+/// - `Err(DockerError)` - The local images couldn't be listed
+pub(crate) async fn prune_image_versions(docker: &Docker, image: &str, current_tag: &str) -> Result<usize, DockerError> {
+    let images = match docker.images().list(&Default::default()).await {
+        Ok(images) => images,
+        Err(e) => {
+            error!("Error listing images to prune old versions of {image}");
+            debug!("Trace: {:?}", e);
+            return Err(DockerError::daemon("list images", e));
+        },
+    };
+
+    let repo_prefix = format!("{image}:");
+    let mut versions: Vec<_> = images
+        .iter()
+        .flat_map(|info| info.repo_tags.iter().flatten().map(move |repo_tag| (info.created, repo_tag)))
+        .filter_map(|(created, repo_tag)| Some((created, repo_tag, repo_tag.strip_prefix(&repo_prefix)?)))
+        .filter(|(_, _, tag)| *tag != LATEST_TAG && *tag != current_tag)
+        .collect();
+
+    // Newest first, the current version counts towards the retained ones
+    versions.sort_by(|(a, ..), (b, ..)| b.cmp(a));
+    let retained = retained_versions().saturating_sub(1);
+
+    let mut removed = 0;
+    for (_, repo_tag, _) in versions.into_iter().skip(retained) {
+        match docker.images().get(repo_tag).delete().await {
+            Ok(_) => {
+                debug!("Removed old image version {repo_tag}");
+                removed += 1;
+            },
+            Err(e) => {
+                warn!("Failed to remove old image version {repo_tag}, keeping it");
+                debug!("Trace: {:?}", e);
+            },
+        }
+    }
+
+    if removed > 0 {
+        info!("Pruned {removed} old version(s) of {image}");
+    }
+    Ok(removed)
+}
@@ -0,0 +1,107 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use kube::{ Client, Config };
+use kube::config::{ Kubeconfig, KubeConfigOptions };
+use serde::Deserialize;
+
+use crate::env::clusters_file;
+use crate::K8sError;
+use crate::logging::*;
+
+/// A named cluster that challenges can be deployed to instead of the default one
+///
+/// Profiles are read from the YAML file at `K8S_CLUSTERS_FILE`, keyed by name:
+///
+/// ```yaml
+/// high-resource:
+///   kubeconfig: /etc/arcs/high-resource.kubeconfig
+///   context: high-resource-admin
+///   namespace: challenges
+///   display_address: big.ctf.example.com
+/// ```
+///
+/// ## Fields
+/// - `kubeconfig` - Kubeconfig file to use, the default kubeconfig if not set
+/// - `context` - Context of the kubeconfig to use, its current context if not set
+/// - `namespace` - Namespace every object is created in, the context's namespace if not set
+/// - `display_address` - Address the cluster's node ports are reachable on, the deploy server's display address if not set
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ClusterProfile {
+    pub kubeconfig: Option<PathBuf>,
+    pub context: Option<String>,
+    pub namespace: Option<String>,
+    pub display_address: Option<String>,
+}
+
+/// Reads every configured cluster profile, there are none if `K8S_CLUSTERS_FILE` isn't set
+pub fn cluster_profiles() -> Result<HashMap<String, ClusterProfile>, K8sError> {
+    let Some(path) = clusters_file() else {
+        return Ok(HashMap::new());
+    };
+
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| K8sError::Config(format!("Failed to read cluster profiles @ {path:?}: {e}")))?;
+
+    serde_yaml::from_str(&text).map_err(|e| K8sError::Config(format!("Invalid cluster profiles @ {path:?}: {e}")))
+}
+
+/// Looks up a single cluster profile by name
+pub fn cluster_profile(name: &str) -> Result<ClusterProfile, K8sError> {
+    match cluster_profiles()?.remove(name) {
+        Some(profile) => Ok(profile),
+        None => {
+            error!("No cluster profile named {name:?}");
+            Err(K8sError::Config(format!("No cluster profile named {name:?}")))
+        }
+    }
+}
+
+/// Address the node ports of the given cluster are reachable on, if its profile sets one
+pub fn cluster_display_address(cluster: Option<&str>) -> Option<String> {
+    cluster
+        .and_then(|name| cluster_profile(name).ok())
+        .and_then(|profile| profile.display_address)
+}
+
+/// Creates a client for a named cluster, from its profile's kubeconfig, context and namespace
+pub(crate) async fn profile_client(name: &str) -> Result<Client, K8sError> {
+    let profile = cluster_profile(name)?;
+
+    let options = KubeConfigOptions {
+        context: profile.context.clone(),
+        ..KubeConfigOptions::default()
+    };
+
+    let config = match &profile.kubeconfig {
+        Some(path) => {
+            let kubeconfig = Kubeconfig::read_from(path).map_err(|err| {
+                error!("Error reading kubeconfig {path:?} of cluster {name}");
+                debug!("Trace: {:?}", err);
+                K8sError::Kubeconfig { cluster: name.to_string(), source: err }
+            })?;
+            Config::from_custom_kubeconfig(kubeconfig, &options).await
+        },
+        None => Config::from_kubeconfig(&options).await,
+    };
+
+    let mut config = match config {
+        Ok(config) => config,
+        Err(err) => {
+            error!("Error loading kubeconfig of cluster {name}");
+            debug!("Trace: {:?}", err);
+            return Err(K8sError::Kubeconfig { cluster: name.to_string(), source: err });
+        }
+    };
+
+    if let Some(namespace) = profile.namespace {
+        config.default_namespace = namespace;
+    }
+
+    Client::try_from(config).map_err(|err| {
+        error!("Error creating Kubernetes client for cluster {name}");
+        debug!("Trace: {:?}", err);
+        K8sError::Connection(err)
+    })
+}
@@ -0,0 +1,479 @@
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::path::{ Path, PathBuf };
+
+use serde::{ Deserialize, Serialize };
+use yaml::deploy::structs::{ DeployTarget, DeployTargetType };
+
+use crate::{ image_path, resource_name };
+
+const DEFAULT_CPU_UTILIZATION: u8 = 80;
+const DEFAULT_CPU_REQUEST: &str = "100m";
+
+/// Transport protocol of a port exposed by a deploy target
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NetworkProtocol {
+    #[default]
+    Tcp,
+    Udp,
+}
+
+impl NetworkProtocol {
+    /// Protocol name as Kubernetes expects it in `Service` and container port specs
+    pub fn k8s_name(&self) -> &'static str {
+        match self {
+            NetworkProtocol::Tcp => "TCP",
+            NetworkProtocol::Udp => "UDP",
+        }
+    }
+}
+
+impl Display for NetworkProtocol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NetworkProtocol::Tcp => write!(f, "tcp"),
+            NetworkProtocol::Udp => write!(f, "udp"),
+        }
+    }
+}
+
+/// A single port that a deploy target's container listens on
+/// 
+/// `name` is used as the port name in the generated `Service`/`Deployment`, so it is always a valid
+/// Kubernetes port name (see [`port_name`][port_name]).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PortExposure {
+    pub name: String,
+    pub port: i32,
+    pub protocol: NetworkProtocol,
+}
+
+impl Display for PortExposure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({}/{})", self.name, self.port, self.protocol)
+    }
+}
+
+/// A port of a deployed target, along with the node port it was exposed on by its `Service`
+/// 
+/// `host` is the display address of the cluster the target runs on, if its cluster profile sets one.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExposedPort {
+    pub name: String,
+    pub protocol: NetworkProtocol,
+    pub port: i32,
+    pub node_port: i32,
+    #[serde(default)]
+    pub host: Option<String>,
+}
+
+/// Additional port listed under `ports` in a deploy target's section of the chall.yaml
+/// 
+/// ```yaml
+/// deploy:
+///   web:
+///     expose: 8080/tcp
+///     ports:
+///       - name: debug
+///         port: 9229
+///       - port: 5353
+///         protocol: udp
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+pub struct PortOption {
+    pub name: Option<String>,
+    pub port: u16,
+    #[serde(default)]
+    pub protocol: NetworkProtocol,
+}
+
+/// How the challenge's flag (from the chall.yaml) is handed to a deploy target's containers
+/// 
+/// The flag is stored in a Kubernetes `Secret`, and can be exposed as an environment variable, a mounted file, or both.
+/// 
+/// ```yaml
+/// deploy:
+///   nc:
+///     expose: 1337/tcp
+///     flag:
+///       env: FLAG
+///       file: /home/ctf/flag.txt
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct FlagOption {
+    pub env: Option<String>,
+    pub file: Option<PathBuf>,
+}
+
+/// File from the challenge folder that is mounted into a deploy target's containers through a `ConfigMap`
+/// 
+/// `source` is relative to the challenge folder, `mount` is the absolute path of the file inside the container.
+#[derive(Debug, Clone, Deserialize)]
+pub struct FileOption {
+    pub source: PathBuf,
+    pub mount: PathBuf,
+}
+
+/// Horizontal autoscaling of a deploy target, scaled on the average CPU utilization of its pods
+/// 
+/// `min` defaults to the target's `replicas`, `cpu` is the target utilization in percent of `cpu_request`.
+/// 
+/// ```yaml
+/// deploy:
+///   web:
+///     expose: 8080/tcp
+///     autoscale:
+///       min: 2
+///       max: 8
+///       cpu: 70
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+pub struct AutoscaleOption {
+    pub min: Option<u8>,
+    pub max: u8,
+    pub cpu: Option<u8>,
+    pub cpu_request: Option<String>,
+}
+
+/// How a deploy target's image is built
+/// 
+/// `dockerfile` is relative to the target's build folder. `flag_arg` names a build argument that is set to the
+/// challenge's flag, so the flag only has to live in the chall.yaml.
+/// 
+/// ```yaml
+/// deploy:
+///   web:
+///     expose: 8080/tcp
+///     build:
+///       dockerfile: docker/Dockerfile.prod
+///       args:
+///         NODE_ENV: production
+///       flag_arg: FLAG
+///       target: runtime
+///       labels:
+///         ctf.category: web
+///       no_cache: true
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct BuildOption {
+    pub dockerfile: Option<PathBuf>,
+    pub args: BTreeMap<String, String>,
+    pub flag_arg: Option<String>,
+    pub target: Option<String>,
+    pub labels: BTreeMap<String, String>,
+    pub no_cache: bool,
+}
+
+/// Deploy options of a target that the shared chall.yaml parser doesn't (yet) model
+/// 
+/// These are read from the same `deploy.<target>` section of the chall.yaml as the target itself, and every
+/// key is optional, so chall.yamls that don't use them are unaffected.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct TargetOptions {
+    pub ports: Vec<PortOption>,
+    pub env: BTreeMap<String, String>,
+    pub flag: Option<FlagOption>,
+    pub files: Vec<FileOption>,
+    pub autoscale: Option<AutoscaleOption>,
+    pub cluster: Option<String>,
+    pub build: BuildOption,
+}
+
+/// The challenge's flag, along with where a deploy target wants it
+#[derive(Debug, Clone)]
+pub struct FlagConfig {
+    pub value: String,
+    pub env: Option<String>,
+    pub file: Option<PathBuf>,
+}
+
+/// Bounds and target of a deploy target's `HorizontalPodAutoscaler`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AutoscaleConfig {
+    pub min_replicas: i32,
+    pub max_replicas: i32,
+    pub cpu_utilization: i32,
+    pub cpu_request: String,
+}
+
+/// Key of the given target type in the `deploy` section of the chall.yaml
+pub fn target_key(target_type: DeployTargetType) -> &'static str {
+    match target_type {
+        DeployTargetType::Web => "web",
+        DeployTargetType::Nc => "nc",
+        DeployTargetType::Admin => "admin",
+        DeployTargetType::Static => "static",
+    }
+}
+
+/// Target type with the given key in the `deploy` section of the chall.yaml, the inverse of [`target_key`][target_key]
+pub fn target_type_from_key(key: &str) -> Option<DeployTargetType> {
+    match key {
+        "web" => Some(DeployTargetType::Web),
+        "nc" => Some(DeployTargetType::Nc),
+        "admin" => Some(DeployTargetType::Admin),
+        "static" => Some(DeployTargetType::Static),
+        _ => None,
+    }
+}
+
+/// Subfolder of the challenge folder a deploy target is built from, `None` if it is built from the challenge folder itself
+pub fn target_build_path(target: &DeployTarget) -> Option<PathBuf> {
+    // if built_path defaulted or set to ".", subfolder is None
+    if target.build.to_string_lossy() == "." {
+        None
+    } else {
+        Some(target.build.clone())
+    }
+}
+
+/// Whether the target built from `build_path` would get the same resource name as one of the `other_build_paths` of the
+/// challenge's other targets
+/// 
+/// Targets built from the same folder share their image, but each one still needs Kubernetes resources of its own, so
+/// [`resource_name`][resource_name] appends their target key.
+pub fn shares_build_path<'a>(
+    chall_name: &str,
+    build_path: Option<&Path>,
+    other_build_paths: impl IntoIterator<Item = Option<&'a Path>>,
+) -> bool {
+    let name = resource_name(chall_name, build_path, None);
+    other_build_paths
+        .into_iter()
+        .any(|other_build_path| resource_name(chall_name, other_build_path, None) == name)
+}
+
+fn shares_target_build_path(
+    chall_name: &str,
+    target: &DeployTarget,
+    target_type: DeployTargetType,
+    targets: &[(DeployTarget, DeployTargetType)],
+) -> bool {
+    let other_build_paths: Vec<Option<PathBuf>> = targets
+        .iter()
+        .filter(|(_, other_type)| target_key(*other_type) != target_key(target_type))
+        .map(|(other, _)| target_build_path(other))
+        .collect();
+
+    shares_build_path(chall_name, target_build_path(target).as_deref(), other_build_paths.iter().map(Option::as_deref))
+}
+
+/// Name of the Kubernetes resources of `target`, one of the challenge's `targets` (see [`resource_name`][resource_name])
+pub fn target_resource_name(
+    chall_name: &str,
+    target: &DeployTarget,
+    target_type: DeployTargetType,
+    targets: &[(DeployTarget, DeployTargetType)],
+) -> String {
+    let key = shares_target_build_path(chall_name, target, target_type, targets).then(|| target_key(target_type));
+    resource_name(chall_name, target_build_path(target).as_deref(), key)
+}
+
+pub fn flag_secret_name(resource_name: &str) -> String {
+    format!("{resource_name}-flag")
+}
+
+pub fn files_config_map_name(resource_name: &str) -> String {
+    format!("{resource_name}-files")
+}
+
+pub fn autoscaler_name(resource_name: &str) -> String {
+    format!("{resource_name}-hpa")
+}
+
+/// Turns `raw` into a valid Kubernetes port name (at most 15 lowercase alphanumeric characters or `-`, with no `--`)
+/// 
+/// Returns `None` if nothing usable is left of the name.
+pub fn port_name(raw: &str) -> Option<String> {
+    let mut sanitized = String::new();
+    let mapped = raw
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | '0'..='9' => c,
+            'A'..='Z' => c.to_ascii_lowercase(),
+            _ => '-',
+        })
+        .skip_while(|&c| c == '-');
+    for c in mapped {
+        if sanitized.len() == 15 {
+            break;
+        }
+        if !(c == '-' && sanitized.ends_with('-')) {
+            sanitized.push(c);
+        }
+    }
+
+    let trimmed = sanitized.trim_end_matches('-');
+    trimmed
+        .chars()
+        .any(|c| c.is_ascii_lowercase())
+        .then(|| trimmed.to_string())
+}
+
+/// Everything the k8s crate needs to know to deploy a single deploy target of a challenge
+/// 
+/// Built from the deploy targets that the shared chall.yaml parser returns from `YamlShape::deploy()`,
+/// so the chall.yaml is only ever read and validated in one place.
+/// 
+/// ## Fields
+/// - `chall_name` - Name of the challenge (folder) the target belongs to
+/// - `target_type` - Which deploy target of the challenge this is
+/// - `build_path` - Build subpath of the target, `None` if it is built from the root of the challenge folder
+/// - `shares_build_path` - Whether another target of the challenge is built from the same folder (see [`shares_build_path`][shares_build_path])
+/// - `exposures` - Ports that the target's container listens on, the first one being the target's `expose` port
+/// - `replicas` - Number of pods to run for the target
+/// - `env` - Plain environment variables set in the target's containers
+/// - `flag` - Flag injected into the target's containers from a `Secret`, if requested
+/// - `files` - Files from the challenge folder mounted into the target's containers from a `ConfigMap`
+/// - `autoscale` - Autoscaling of the target's pods, `replicas` is fixed if not set
+/// - `cluster` - Name of the cluster profile the target is deployed to, the default cluster if not set
+/// - `image_digest` - Digest (`sha256:...`) of the pushed image, the target's `Deployment` is pinned to it if set
+/// - `build` - How the target's image is built, with the flag already set as its `flag_arg` build argument
+#[derive(Debug, Clone)]
+pub struct TargetConfig {
+    pub chall_name: String,
+    pub target_type: DeployTargetType,
+    pub build_path: Option<PathBuf>,
+    pub shares_build_path: bool,
+    pub exposures: Vec<PortExposure>,
+    pub replicas: u8,
+    pub env: BTreeMap<String, String>,
+    pub flag: Option<FlagConfig>,
+    pub files: Vec<FileOption>,
+    pub autoscale: Option<AutoscaleConfig>,
+    pub cluster: Option<String>,
+    pub image_digest: Option<String>,
+    pub build: BuildOption,
+}
+
+impl TargetConfig {
+    /// Deploy config of `target`, one of the challenge's `targets` (which decide whether its build path is shared)
+    pub fn new(
+        chall_name: &str,
+        target: &DeployTarget,
+        target_type: DeployTargetType,
+        targets: &[(DeployTarget, DeployTargetType)],
+        options: TargetOptions,
+        flag: &str,
+    ) -> Self {
+        let build_path = target_build_path(target);
+        let shares_build_path = shares_target_build_path(chall_name, target, target_type, targets);
+
+        let protocol = if target.expose.protocol().eq_ignore_ascii_case("udp") {
+            NetworkProtocol::Udp
+        } else {
+            NetworkProtocol::Tcp
+        };
+
+        let mut exposures = vec![PortExposure {
+            name: target_key(target_type).to_string(),
+            port: target.expose.port() as i32,
+            protocol,
+        }];
+
+        for option in options.ports {
+            let port = option.port as i32;
+            let protocol = option.protocol;
+
+            if exposures.iter().any(|exposure| exposure.port == port && exposure.protocol == protocol) {
+                continue;
+            }
+
+            let name = option.name
+                .as_deref()
+                .and_then(port_name)
+                .filter(|name| !exposures.iter().any(|exposure| &exposure.name == name))
+                .unwrap_or_else(|| format!("{protocol}-{port}"));
+
+            exposures.push(PortExposure { name, port, protocol });
+        }
+
+        let mut build = options.build;
+        if let Some(flag_arg) = &build.flag_arg {
+            build.args.insert(flag_arg.clone(), flag.to_string());
+        }
+
+        let flag = options.flag
+            .filter(|flag_option| flag_option.env.is_some() || flag_option.file.is_some())
+            .map(|FlagOption { env, file }| FlagConfig { value: flag.to_string(), env, file });
+
+        let autoscale = options.autoscale.map(|autoscale| {
+            let min_replicas = autoscale.min.unwrap_or(target.replicas).max(1) as i32;
+            AutoscaleConfig {
+                min_replicas,
+                max_replicas: (autoscale.max as i32).max(min_replicas),
+                cpu_utilization: autoscale.cpu.unwrap_or(DEFAULT_CPU_UTILIZATION).clamp(1, 100) as i32,
+                cpu_request: autoscale.cpu_request.unwrap_or_else(|| DEFAULT_CPU_REQUEST.to_string()),
+            }
+        });
+
+        Self {
+            chall_name: chall_name.to_string(),
+            target_type,
+            build_path,
+            shares_build_path,
+            exposures,
+            replicas: target.replicas,
+            env: options.env,
+            flag,
+            files: options.files,
+            autoscale,
+            cluster: options.cluster,
+            image_digest: None,
+            build,
+        }
+    }
+
+    pub fn build_path(&self) -> Option<&Path> {
+        self.build_path.as_deref()
+    }
+
+    /// Name of the Kubernetes resources created for this target (see [`resource_name`][resource_name])
+    pub fn resource_name(&self) -> String {
+        let target = self.shares_build_path.then(|| target_key(self.target_type));
+        resource_name(&self.chall_name, self.build_path(), target)
+    }
+
+    /// Full registry path of the image for this target (see [`image_path`][image_path])
+    pub fn image(&self) -> String {
+        image_path(&self.chall_name, self.build_path())
+    }
+
+    /// Reference the target's containers run, the image pinned to its digest (`image@sha256:...`) if known
+    ///
+    /// Pinning by digest means every pod of the target runs the exact image that was built and pushed, even if the
+    /// image's tags are moved by a later build.
+    pub fn image_reference(&self) -> String {
+        match &self.image_digest {
+            Some(digest) => format!("{}@{digest}", self.image()),
+            None => self.image(),
+        }
+    }
+
+    /// Name of the `Secret` holding the flag of this target
+    pub fn flag_secret_name(&self) -> String {
+        flag_secret_name(&self.resource_name())
+    }
+
+    /// Name of the `ConfigMap` holding the mounted files of this target
+    pub fn files_config_map_name(&self) -> String {
+        files_config_map_name(&self.resource_name())
+    }
+
+    /// Name of the `HorizontalPodAutoscaler` of this target
+    pub fn autoscaler_name(&self) -> String {
+        autoscaler_name(&self.resource_name())
+    }
+
+    /// Number of replicas the target's `Deployment` is created with
+    pub fn initial_replicas(&self) -> i32 {
+        self.autoscale
+            .as_ref()
+            .map(|autoscale| autoscale.min_replicas)
+            .unwrap_or(self.replicas as i32)
+    }
+}
@@ -4,6 +4,8 @@ env_var_req!(DOCKER_REGISTRY_USERNAME -> REG_USERNAME);
 env_var_req!(DOCKER_REGISTRY_PASSWORD -> REG_PASSWORD);
 env_var_req!(DOCKER_REGISTRY_URL -> REG_URL);
 env_var_req!(CHALL_FOLDER -> CHALL_FOLDER_DEFAULT);
+env_var_opt!(K8S_CLUSTERS_FILE -> CLUSTERS_FILE);
+env_var_opt!(DOCKER_REGISTRIES_FILE -> REGISTRIES_FILE);
 
 
 assert_req_env!(check_env_vars: REG_USERNAME, REG_PASSWORD, REG_URL, CHALL_FOLDER_DEFAULT);
@@ -0,0 +1,93 @@
+use arcs_retry::Retryable;
+use thiserror::Error;
+
+/// Errors that can occur while working with a Kubernetes cluster
+///
+/// ## Variants
+/// - `Connection` - No client could be created for the cluster
+/// - `Kubeconfig` - The kubeconfig of a cluster profile couldn't be loaded
+/// - `Config` - A cluster profile or registry file is missing or invalid
+/// - `Api` - The cluster rejected a request or couldn't be reached
+/// - `InvalidObject` - An object generated from the deploy config isn't valid
+/// - `InvalidFile` - A mounted file's source couldn't be resolved
+/// - `NotFound` - An object that has to exist beforehand doesn't
+/// - `MissingNodePorts` - A service came back without the node ports it was created with
+/// - `NotRunning` - The pods of a target didn't come up
+/// - `Watch` - Watching a deployment's rollout failed
+/// - `RolloutFailed` - A deployment's replicas failed to be created
+/// - `DeleteRejected` - The cluster didn't confirm the deletion of an object
+/// - `ScaleRejected` - A target can't be scaled to the requested number of replicas
+#[derive(Debug, Error)]
+pub enum K8sError {
+    #[error("Failed to connect to Kubernetes")]
+    Connection(#[source] kube::Error),
+    #[error("Failed to load kubeconfig of cluster {cluster}")]
+    Kubeconfig { cluster: String, #[source] source: kube::config::KubeconfigError },
+    #[error("{0}")]
+    Config(String),
+    #[error("{context}")]
+    Api { context: String, #[source] source: kube::Error },
+    #[error("Invalid {kind} generated from the deploy config")]
+    InvalidObject { kind: &'static str, #[source] source: serde_json::Error },
+    #[error("{0}")]
+    InvalidFile(String),
+    #[error("{0}")]
+    NotFound(String),
+    #[error("Service of {0} has no node port(s)")]
+    MissingNodePorts(String),
+    #[error("Pods of {name} are not running, check the logs: {}", .reasons.join(", "))]
+    NotRunning { name: String, reasons: Vec<String> },
+    #[error("Failed to watch the rollout of {name}")]
+    Watch { name: String, #[source] source: kube_runtime::watcher::Error },
+    #[error("Deployment {0} failed to roll out")]
+    RolloutFailed(String),
+    #[error("Kubernetes didn't confirm the deletion of {0}")]
+    DeleteRejected(String),
+    #[error("{0}")]
+    ScaleRejected(String),
+}
+
+impl K8sError {
+    pub(crate) fn api(context: impl Into<String>, source: kube::Error) -> Self {
+        Self::Api { context: context.into(), source }
+    }
+
+    pub(crate) fn object(kind: &'static str, source: serde_json::Error) -> Self {
+        Self::InvalidObject { kind, source }
+    }
+
+    /// Whether the cluster couldn't be reached at all, as opposed to rejecting a request
+    pub fn is_unreachable(&self) -> bool {
+        match self {
+            Self::Connection(_) | Self::Kubeconfig { .. } => true,
+            Self::Api { source, .. } => matches!(source, kube::Error::HyperError(_) | kube::Error::Service(_)),
+            _ => false,
+        }
+    }
+
+    /// Whether the objects were created, but the challenge's pods never came up
+    pub fn is_rollout_failure(&self) -> bool {
+        matches!(self, Self::NotRunning { .. } | Self::RolloutFailed(_) | Self::Watch { .. } | Self::MissingNodePorts(_))
+    }
+}
+
+impl Retryable for K8sError {
+    /// The cluster was unreachable or failed the request on its end (5XX)
+    ///
+    /// Conflicts aren't retried, and neither are failures of a rollout, since retrying those would recreate objects that
+    /// were already created.
+    fn is_retryable(&self) -> bool {
+        match self {
+            Self::Connection(_) => true,
+            Self::Api { source: kube::Error::Api(response), .. } => response.code >= 500,
+            Self::Api { .. } => self.is_unreachable(),
+            _ => false,
+        }
+    }
+}
+
+impl From<K8sError> for String {
+    fn from(err: K8sError) -> Self {
+        err.to_string()
+    }
+}
@@ -0,0 +1,195 @@
+use std::collections::BTreeMap;
+
+use k8s_openapi::api::{
+    core::v1::Service,
+    apps::v1::Deployment,
+};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+use kube::{ Client, Api, api::ListParams };
+
+use crate::config::ExposedPort;
+use crate::{ apply_deployment, apply_service, bounded_name, delete_challenge, exposed_ports_from_service, CHALL_LABEL, INSTANCE_TEAM_LABEL, MAX_RESOURCE_NAME_LEN };
+use crate::logging::*;
+use crate::K8sError;
+
+/// Generates the name of the Kubernetes resources of a team's instance of a deployed target
+///
+/// The name is derived from the whole team id so that a team only ever has one instance of each target, and two teams
+/// never share one. Names too long for Kubernetes are shortened with a hash, see [`resource_name`][crate::resource_name].
+pub fn instance_name(base_name: &str, team_id: &str) -> String {
+    let team_suffix: String = team_id
+        .chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+
+    bounded_name(&format!("{base_name}-inst-{team_suffix}"), MAX_RESOURCE_NAME_LEN)
+}
+
+/// Labels of a team's instance, based on the labels of the object it is a copy of
+fn instance_labels(base_labels: Option<&BTreeMap<String, String>>, app: &str, team_id: &str) -> BTreeMap<String, String> {
+    let mut labels = base_labels.cloned().unwrap_or_default();
+    labels.insert("app".to_string(), app.to_string());
+    labels.insert(INSTANCE_TEAM_LABEL.to_string(), team_id.to_string());
+    labels
+}
+
+/// Creates a short-lived copy of a deployed target for a single team
+///
+/// The live [`Deployment`][Deployment] and [`Service`][Service] of `base_name` are copied under `instance_name`, with
+/// a single replica and fresh node ports, so the target has to be deployed before instances of it can be started.
+/// `client` has to be a client of `cluster`, the cluster the target is deployed to.
+///
+/// ## Returns
+/// - `Ok(Vec<ExposedPort>)` - Every port exposed by the instance, with the node port it is reachable on
+/// - `Err(K8sError)` - Error trace if error occurs
+pub async fn create_instance(client: &Client, cluster: Option<&str>, base_name: &str, instance_name: &str, team_id: &str) -> Result<Vec<ExposedPort>, K8sError> {
+    info!("Creating instance {instance_name} of {base_name} for team {team_id}");
+
+    let deployments: Api<Deployment> = Api::default_namespaced(client.clone());
+    let services: Api<Service> = Api::default_namespaced(client.clone());
+
+    let mut deployment = match deployments.get_opt(base_name).await {
+        Ok(Some(deployment)) => deployment,
+        Ok(None) => {
+            error!("Deployment {base_name} doesn't exist, can't create an instance of it");
+            return Err(K8sError::NotFound(format!("Deployment {base_name} doesn't exist, deploy the challenge first")));
+        },
+        Err(err) => {
+            error!("Error fetching deployment {base_name}");
+            debug!("Trace: {:?}", err);
+            return Err(K8sError::api(format!("Error fetching deployment {base_name}"), err));
+        }
+    };
+
+    let base_service_name = format!("{base_name}-service");
+    let mut service = match services.get_opt(&base_service_name).await {
+        Ok(Some(service)) => service,
+        Ok(None) => {
+            error!("Service {base_service_name} doesn't exist, can't create an instance of it");
+            return Err(K8sError::NotFound(format!("Service {base_service_name} doesn't exist, deploy the challenge first")));
+        },
+        Err(err) => {
+            error!("Error fetching service {base_service_name}");
+            debug!("Trace: {:?}", err);
+            return Err(K8sError::api(format!("Error fetching service {base_service_name}"), err));
+        }
+    };
+
+    let pod_labels = instance_labels(deployment.metadata.labels.as_ref(), instance_name, team_id);
+
+    deployment.metadata = ObjectMeta {
+        name: Some(instance_name.to_string()),
+        labels: Some(pod_labels.clone()),
+        ..ObjectMeta::default()
+    };
+    deployment.status = None;
+    if let Some(spec) = deployment.spec.as_mut() {
+        spec.replicas = Some(1);
+        spec.selector.match_labels = Some(BTreeMap::from([("app".to_string(), instance_name.to_string())]));
+        spec.template.metadata.get_or_insert_with(ObjectMeta::default).labels = Some(pod_labels);
+    }
+
+    let instance_service_name = format!("{instance_name}-service");
+    service.metadata = ObjectMeta {
+        name: Some(instance_service_name.clone()),
+        labels: Some(instance_labels(service.metadata.labels.as_ref(), &instance_service_name, team_id)),
+        ..ObjectMeta::default()
+    };
+    service.status = None;
+    if let Some(spec) = service.spec.as_mut() {
+        spec.selector = Some(BTreeMap::from([("app".to_string(), instance_name.to_string())]));
+        spec.cluster_ip = None;
+        spec.cluster_ips = None;
+        for port in spec.ports.iter_mut().flatten() {
+            port.node_port = None;
+        }
+    }
+
+    if let Err(err) = apply_deployment(client, instance_name, &deployment).await {
+        error!("Error creating instance deployment {instance_name}");
+        info!("Trace: {:?}", err);
+        return Err(err);
+    }
+
+    let service = match apply_service(client, instance_name, &service).await {
+        Ok(service) => service,
+        Err(err) => {
+            error!("Error creating instance service {instance_service_name}");
+            info!("Trace: {:?}", err);
+            return Err(err);
+        }
+    };
+
+    let exposed_ports = exposed_ports_from_service(&service, cluster)?;
+    info!("Instance {instance_name} successfully created --> port(s) {exposed_ports:?}");
+
+    Ok(exposed_ports)
+}
+
+/// Deletes the [`Deployment`][Deployment] and [`Service`][Service] of a team's instance
+pub async fn delete_instance(client: &Client, instance_name: &str) -> Result<(), K8sError> {
+    info!("Deleting instance {instance_name}");
+    delete_challenge(client, vec![instance_name]).await
+}
+
+/// Objects of a team's instance found in the cluster
+///
+/// ## Fields
+/// - `name` - Name of the instance's resources
+/// - `chall_label` - Challenge label of the instance, copied from the target it is a copy of
+/// - `team_id` - Team the instance belongs to, as set in its [`INSTANCE_TEAM_LABEL`][INSTANCE_TEAM_LABEL]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct LiveInstance {
+    pub name: String,
+    pub chall_label: String,
+    pub team_id: String,
+}
+
+/// Lists every team instance in the cluster, from the [`Deployment`][Deployment]s and [`Service`][Service]s carrying the
+/// instance label
+///
+/// ## Returns
+/// - `Ok(Vec<LiveInstance>)` - Every instance, once even if only its deployment or its service is left
+/// - `Err(K8sError)` - Error trace if error occurs
+pub async fn live_instances(client: &Client) -> Result<Vec<LiveInstance>, K8sError> {
+    let deployments: Api<Deployment> = Api::default_namespaced(client.clone());
+    let services: Api<Service> = Api::default_namespaced(client.clone());
+    let selector = ListParams::default().labels(INSTANCE_TEAM_LABEL);
+
+    let deployment_list = match deployments.list_metadata(&selector).await {
+        Ok(list) => list.items.into_iter().map(|deployment| deployment.metadata),
+        Err(err) => {
+            error!("Error listing instance deployments");
+            debug!("Trace: {:?}", err);
+            return Err(K8sError::api("Error listing instance deployments", err));
+        }
+    };
+
+    let service_list = match services.list_metadata(&selector).await {
+        Ok(list) => list.items.into_iter().map(|service| service.metadata),
+        Err(err) => {
+            error!("Error listing instance services");
+            debug!("Trace: {:?}", err);
+            return Err(K8sError::api("Error listing instance services", err));
+        }
+    };
+
+    let deployment_instances = deployment_list.filter_map(|metadata| Some((metadata.name.clone()?, metadata)));
+    let service_instances = service_list.filter_map(|metadata| {
+        let name = metadata.name.as_deref()?.strip_suffix("-service")?.to_string();
+        Some((name, metadata))
+    });
+
+    let mut instances: Vec<LiveInstance> = deployment_instances
+        .chain(service_instances)
+        .map(|(name, metadata)| {
+            let label = |key: &str| metadata.labels.as_ref().and_then(|labels| labels.get(key)).cloned().unwrap_or_default();
+            LiveInstance { name, chall_label: label(CHALL_LABEL), team_id: label(INSTANCE_TEAM_LABEL) }
+        })
+        .collect();
+
+    instances.sort();
+    instances.dedup();
+    Ok(instances)
+}
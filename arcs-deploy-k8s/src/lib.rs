@@ -1,22 +1,35 @@
 use env::chall_folder_default;
 use futures::StreamExt;
 use k8s_openapi::api::{
-    core::v1::{ Pod, Service, Secret }, 
+    core::v1::{ Pod, Service, Secret, ConfigMap }, 
     apps::v1::Deployment,
+    autoscaling::v2::HorizontalPodAutoscaler,
 };
 use kube::{
     Client,
     Api,
     Error,
     core::ObjectList,
-    api::{ ListParams, PostParams, DeleteParams },
+    api::{ ListParams, PostParams, DeleteParams, Patch, PatchParams },
 };
 use kube_runtime::{watcher::Config, WatchStreamExt};
-use std::{fs::File, io::Read, path::PathBuf, collections::HashMap};
-pub mod network_protocol;
+use arcs_retry::retry;
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::path::{ Component, Path, PathBuf };
+use std::sync::{ Mutex, MutexGuard, PoisonError };
+use std::sync::atomic::{ AtomicBool, Ordering };
+use std::time::{ Duration, Instant };
+pub mod clusters;
+pub mod config;
+pub mod instance;
+pub mod reconcile;
+pub mod registry;
 mod env;
 
-use network_protocol::*;
+use clusters::cluster_display_address;
+use registry::{ ensure_registry_secrets, registry_secret_names };
+use config::{ ExposedPort, NetworkProtocol, TargetConfig, autoscaler_name, flag_secret_name, files_config_map_name };
 
 #[allow(unused_macros)]
 pub mod logging {
@@ -26,103 +39,128 @@ pub mod logging {
 
 use logging::*;
 
-use crate::env::{reg_username, reg_password, reg_url};
+use crate::env::reg_url;
 pub use env::check_env_vars;
 
+mod error;
+pub use error::K8sError;
+
 // BIG TODOS --> 
 // MERGE DUPLICATE CODE SECTIONS 
 // IMPROVE LOGGING
 // CHECK OUT LOAD BALANCING (not priority)
 // Right now, if it tries creating a deployment but does not have image locally, it doesn't say anything - not a huge deal since pulling will throw problem
 
-// todo --> fix error propagation, make them return not strings and an actual error type 
-/// Retrieves challenge parameters for a given challenge, given a name and containing folder
-/// 
-/// ## Returns
-/// - `Ok(HashMap<&'static str, ChallengeParams>)` - HashMap of challenge names to challenge parameters for each service type
-/// - `Err(String)` - Error trace if error occurs
-fn fetch_challenge_params(name: &str, chall_folder_path: Option<&str>) -> Result<HashMap<&'static str, ChallengeParams>, String> {
-    let chall_folder = get_chall_folder(chall_folder_path);
-    
-    let mut yaml_path = PathBuf::from(chall_folder);
-    yaml_path.push(name);
-    yaml_path.push("chall.yaml");
+lazy_static! {
+    /// Clients shared by every request, keyed by cluster profile (`None` being the default cluster)
+    ///
+    /// Only locked to look up or insert a client, never while talking to a cluster, so a slow or unreachable cluster
+    /// doesn't hold up the others.
+    static ref CLIENTS: Mutex<HashMap<Option<String>, CachedClient>> = Mutex::new(HashMap::new());
+}
 
-    let mut yaml_file = match File::open(&yaml_path) {
-        Ok(file) => file,
-        Err(err) => {
-            error!("Error opening yaml file");
-            debug!("Trace: {:?}", err);
-            return Err(err.to_string());
-        }
-    };
+/// How long the registry pull secrets of a cluster are trusted to be up to date before a shared client checks them again,
+/// so rotated registry credentials reach clusters that are already connected
+const REGISTRY_SECRETS_REFRESH: Duration = Duration::from_secs(5 * 60);
 
-    let mut yaml_string = String::new();
-    match yaml_file.read_to_string(&mut yaml_string) {
-        Ok(_) => (),
-        Err(err) => {
-            error!("Error reading yaml file into buffer");
-            debug!("Trace: {:?}", err);
-            return Err(err.to_string());
-        },
-    };
+/// Client shared by every request to a cluster
+///
+/// ## Fields
+/// - `client` - Kubernetes client of the cluster
+/// - `secrets_checked` - When the cluster's registry pull secrets were last brought up to date
+struct CachedClient {
+    client: Client,
+    secrets_checked: Instant,
+}
 
-    let deserialized: YamlFile = match serde_yaml::from_str(&yaml_string) {
-        Ok(deser) => deser,
-        Err(err) => {
-            error!("Error deserializing yaml file");
-            info!("Trace: {:?}", err);
-            return Err(err.to_string());
-        },
-    };
-    
-    let web = deserialized.deploy.web;
-    let admin = deserialized.deploy.admin;
-    let nc = deserialized.deploy.nc;
-
-    let deploy_service_types: HashMap<&str, ChallengeParams> = [
-        ("web", web),
-        ("admin", admin),
-        ("nc", nc),
-    ]
-        .into_iter()
-        .filter_map(|(name, data)| data.map(|data| (name, data)))
-        .collect();
+fn cached_clients() -> MutexGuard<'static, HashMap<Option<String>, CachedClient>> {
+    CLIENTS.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Looks up the shared client of a cluster, along with whether its registry pull secrets are due for a check
+///
+/// A due check is claimed right away, so concurrent callers don't all make it.
+fn cached_client(key: &Option<String>) -> Option<(Client, bool)> {
+    let mut clients = cached_clients();
+    let cached = clients.get_mut(key)?;
 
-    Ok(deploy_service_types)
+    let refresh = cached.secrets_checked.elapsed() >= REGISTRY_SECRETS_REFRESH;
+    if refresh {
+        cached.secrets_checked = Instant::now();
+    }
+
+    Some((cached.client.clone(), refresh))
 }
 
-/// Creates the Kubernetes client to be used for all Kubernetes related functions. 
+/// Returns the Kubernetes client to be used for all Kubernetes related functions. 
 /// 
 /// ## Returns
 /// - `Ok(Client)` - Kubernetes client
-/// - `Err(String)` - Error trace if error occurs
-pub async fn create_client() -> Result<Client, String> {
-    match Client::try_default().await {
-        Ok(client) => {
-            info!("Successfully connected to Kubernetes");
-            match generate_registry_secret(&client).await {
-                Ok(_) => {
-                    info!("Successfully created Docker registry secret");
-                    Ok(client)
-                },
-                Err(err) => {
-                    error!("Error creating registry secret");
-                    warn!("Ensure Kubernetes cluster is running");
-                    debug!("Trace: {:?}", err);
-                    Err(err)
-                }
+/// - `Err(K8sError)` - Error trace if error occurs
+pub async fn create_client() -> Result<Client, K8sError> {
+    create_cluster_client(None).await
+}
+
+/// Returns the Kubernetes client of a named cluster profile (see [`ClusterProfile`][clusters::ClusterProfile]),
+/// or of the default cluster if `cluster` is `None`
+/// 
+/// The client is created (and the cluster's registry pull secrets brought up to date) the first time a cluster
+/// is used, later calls share that client. The pull secrets of a shared client are brought up to date again every
+/// [`REGISTRY_SECRETS_REFRESH`][REGISTRY_SECRETS_REFRESH], a failure to do so is only logged.
+/// 
+/// ## Returns
+/// - `Ok(Client)` - Kubernetes client, with the profile's namespace as its default namespace
+/// - `Err(K8sError)` - Error trace if error occurs
+pub async fn create_cluster_client(cluster: Option<&str>) -> Result<Client, K8sError> {
+    let key = cluster.map(str::to_string);
+    let display_name = cluster.unwrap_or("default cluster");
+
+    if let Some((client, refresh_secrets)) = cached_client(&key) {
+        if refresh_secrets {
+            debug!("Refreshing registry secrets of {display_name}");
+            if let Err(err) = ensure_registry_secrets(&client).await {
+                warn!("Failed to refresh registry secrets of {display_name}, retrying in {REGISTRY_SECRETS_REFRESH:?}: {err}");
             }
-        },
-        Err(err) => {
-            error!("Error creating Kubernetes client");
-            debug!("Trace: {:?}", err);
-            Err(err.to_string())
         }
+        return Ok(client);
+    }
+
+    let client = match cluster {
+        Some(name) => clusters::profile_client(name).await,
+        None => retry("k8s connect", "default cluster", || async {
+            Client::try_default().await.map_err(|err| {
+                error!("Error creating Kubernetes client");
+                debug!("Trace: {:?}", err);
+                K8sError::Connection(err)
+            })
+        }).await,
+    }?;
+
+    info!("Successfully connected to Kubernetes ({display_name})");
+    if let Err(err) = retry("k8s apply", "registry secrets", || ensure_registry_secrets(&client)).await {
+        error!("Error updating registry secrets");
+        warn!("Ensure Kubernetes cluster is running");
+        return Err(err);
+    }
+
+    // Another caller may have connected to the same cluster in the meantime, everyone shares whichever client came first
+    let client = cached_clients()
+        .entry(key)
+        .or_insert(CachedClient { client, secrets_checked: Instant::now() })
+        .client
+        .clone();
+    Ok(client)
+}
+
+/// Returns the client to use for the given cluster, reusing `default` for the default cluster
+pub async fn client_for_cluster(default: &Client, cluster: Option<&str>) -> Result<Client, K8sError> {
+    match cluster {
+        Some(_) => create_cluster_client(cluster).await,
+        None => Ok(default.clone()),
     }
 }
 
-pub async fn get_pods(client : &Client) -> Result<ObjectList<Pod>, String> {
+pub async fn get_pods(client : &Client) -> Result<ObjectList<Pod>, K8sError> {
     let pods: Api<Pod> = Api::default_namespaced(client.clone());
     match pods.list(&ListParams::default()).await {
         Ok(pods) => {
@@ -131,192 +169,392 @@ pub async fn get_pods(client : &Client) -> Result<ObjectList<Pod>, String> {
         Err(err) => {
             error!("Error retrieving pods");
             info!("Trace: {:?}", err);
-            Err(err.to_string())
+            Err(K8sError::api("Error retrieving pods", err))
         }
     }
 }
 
-// TODO --> Add support for admin bot stuff
 // TODO --> Return list of challenges with their respective addresses to access (look into load balancer ingresses and such)
 // TODO --> Load balancing
 // TODO --> if pods are in a crashloopbackoff or imagepullbackoff, or basically anything that isn't running by the end of it, and restarts > 3 or something, mark as failed because chall not up
 
-/// Sets up a full Kubernetes deployment for every challenge in `name_list`. 
+/// Label carried by every Kubernetes object created for a challenge, with [`chall_label_value`][chall_label_value] of the challenge name as its value
+pub const CHALL_LABEL: &str = "arcs-deploy/chall";
+
+/// Label carried by the Kubernetes objects of per-team challenge instances, with the team id as its value
+pub const INSTANCE_TEAM_LABEL: &str = "arcs-deploy/instance-team";
+
+/// Longest name [`resource_name`][resource_name] generates, leaving room for the `-service` suffix within the 63
+/// characters a `Service` name can have
+pub const MAX_RESOURCE_NAME_LEN: usize = 63 - "-service".len();
+
+/// Number of hex digits of the hash [`bounded_name`][bounded_name] appends
+const NAME_HASH_LEN: usize = 12;
+
+/// Turns a challenge name into a valid Kubernetes label value (at most 63 characters)
+/// 
+/// Different challenge names always get different values, see [`resource_name`][resource_name].
+pub fn chall_label_value(chall_name: &str) -> String {
+    resource_name(chall_name, None, None)
+}
+
+/// Hex FNV-1a hash of `name`, which unlike `DefaultHasher` stays the same across Rust versions
+fn name_hash(name: &str) -> String {
+    let hash = name
+        .bytes()
+        .fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3));
+
+    format!("{hash:016x}")[..NAME_HASH_LEN].to_string()
+}
+
+/// Turns `name` into a valid Kubernetes name of at most `max_len` characters
+/// 
+/// Names that are already valid and short enough are kept as they are. Anything else is lowercased, has every invalid
+/// character replaced with a `-` and is truncated, then gets a hash of the original name appended, so two different names
+/// never end up the same (`foo_bar` -> `foo-bar-<hash>`, while `foo-bar` stays `foo-bar`).
+pub(crate) fn bounded_name(name: &str, max_len: usize) -> String {
+    let sanitized: String = name
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | '0'..='9' | '-' => c,
+            'A'..='Z' => c.to_ascii_lowercase(),
+            _ => '-',
+        })
+        .collect();
+    let sanitized = sanitized.trim_matches('-');
+
+    if sanitized == name && name.len() <= max_len {
+        return sanitized.to_string();
+    }
+
+    let prefix: String = sanitized.chars().take(max_len.saturating_sub(NAME_HASH_LEN + 1)).collect();
+    match prefix.trim_end_matches('-') {
+        "" => name_hash(name),
+        prefix => format!("{prefix}-{}", name_hash(name)),
+    }
+}
+
+/// Generates the name used for the Kubernetes resources of a single deploy target of a challenge
+/// 
+/// Targets built from the challenge root use the bare challenge name, while targets with a build subpath
+/// have every path segment appended (`chall` + `admin/bot` -> `chall-admin-bot`). Targets that share their build path
+/// with another target of the challenge also get their `target` key appended (`chall-web`, `chall-nc`), see
+/// [`shares_build_path`][crate::config::shares_build_path]. Names that aren't valid Kubernetes resource names or are
+/// longer than [`MAX_RESOURCE_NAME_LEN`][MAX_RESOURCE_NAME_LEN] are made valid by [`bounded_name`][bounded_name], which
+/// appends a hash so they stay unique.
+pub fn resource_name(name: &str, inner_path: Option<&Path>, target: Option<&str>) -> String {
+    let segments = std::iter::once(name.to_string())
+        .chain(
+            inner_path
+                .into_iter()
+                .flat_map(Path::components)
+                .filter_map(|component| match component {
+                    Component::Normal(segment) => Some(segment.to_string_lossy().to_string()),
+                    _ => None,
+                })
+        )
+        .chain(target.map(str::to_string));
+
+    let joined = segments.collect::<Vec<_>>().join("-");
+    bounded_name(&joined, MAX_RESOURCE_NAME_LEN)
+}
+
+/// Generates the full registry path of the image for a given deploy target of a challenge
+/// 
+/// Matches the tag that `arcs-deploy-docker` gives the image when building and pushing it (`<registry>/<name>[/<inner_path>]`)
+pub fn image_path(name: &str, inner_path: Option<&Path>) -> String {
+    let mut path_on_registry = PathBuf::from(reg_url());
+    path_on_registry.push(name);
+
+    if let Some(inner_path) = inner_path {
+        path_on_registry.push(inner_path);
+    }
+
+    path_on_registry.to_string_lossy().to_string()
+}
+
+/// Sets up a full Kubernetes deployment for a single deploy target of a challenge. 
 /// 
-/// `chall_folder_path` is the base challenge directory where all challenges are contained in.
+/// Every target gets its own [`Deployment`][Deployment] and [`Service`][Service], named by [`resource_name`][resource_name]
+/// and running the image built from the target's build subpath (see [`image_path`][image_path]).
 /// 
 /// ## Parameters
 /// - `client` : `Client` 
 ///     - Kubernetes client
-/// - `name_list` : `Vec<&str>`
-///     - List of challenge names to deploy
-/// - `chall_folder_path` - `Option<&str>`
-///     - Path to the base challenge directory, if `None`, defaults to environment variable `CHALL_FOLDER_PATH`
+/// - `config` : `&TargetConfig`
+///     - Deploy configuration of the target, built from the challenge's parsed chall.yaml
 /// 
 /// ## Returns
-/// - `Ok(Vec<i32>)` - Vector with all corresponding exposed port numbers of each challenge deployed
-/// - `Err(String)` - Error trace if error occurs
-pub async fn create_challenge(client : &Client, name_list : Vec<&str>, chall_folder_path: Option<&str>) -> Result<Vec<i32>, String> {    
-    let mut port_list = Vec::new();
-    for name in name_list {
-        info!("Creating challenge {:?}", name);
-    
-        if let Err(err) = create_deployment(client, name, chall_folder_path).await {
-            error!("Error creating deployment");
+/// - `Ok(Vec<ExposedPort>)` - Every port exposed by the deployed target, with the node port it is reachable on
+/// - `Err(K8sError)` - Error trace if error occurs
+pub async fn create_challenge(client: &Client, config: &TargetConfig) -> Result<Vec<ExposedPort>, K8sError> {
+    let name = &config.chall_name;
+    let resource_name = config.resource_name();
+
+    info!("Creating challenge {:?} ({:?} target as {resource_name:?})", name, config.target_type);
+
+    // The flag secret and mounted files have to exist before the pods referencing them are scheduled
+    if let Err(err) = retry("k8s apply", format!("flag secret of {resource_name}"), || create_flag_secret(client, config)).await {
+        error!("Error creating flag secret");
+        info!("Trace: {:?}", err);
+        return Err(err);
+    }
+
+    if let Err(err) = retry("k8s apply", format!("files of {resource_name}"), || create_files_config_map(client, config)).await {
+        error!("Error creating files config map");
+        info!("Trace: {:?}", err);
+        return Err(err);
+    }
+
+    // Retried step by step on its own, see `apply_deployment`
+    if let Err(err) = create_deployment(client, config).await {
+        error!("Error creating deployment");
+        info!("Trace: {:?}", err);
+        return Err(err);
+    }
+
+    if let Err(err) = retry("k8s apply", format!("autoscaler of {resource_name}"), || create_autoscaler(client, config)).await {
+        error!("Error creating autoscaler");
+        info!("Trace: {:?}", err);
+        return Err(err);
+    }
+
+    let service = match retry("k8s apply", format!("service of {resource_name}"), || create_service(client, config)).await {
+        Ok(service) => service,
+        Err(err) => {
+            error!("Error creating service");
             info!("Trace: {:?}", err);
             return Err(err);
         }
+    };
+    // basically all this does is returns the ports that the service is listening on externally
+    let service_ports = match service.spec.and_then(|spec| spec.ports) {
+        Some(ports) => ports,
+        None => {
+            error!("Error retrieving service ports");
+            return Err(K8sError::MissingNodePorts(resource_name));
+        }
+    };
 
-        let service = match create_service(client, name, chall_folder_path).await {
-            Ok(service) => service,
-            Err(err) => {
-                error!("Error creating service");
-                info!("Trace: {:?}", err);
-                return Err(err);
-            }
-        };
-        // basically all this does is returns the port that the service is listening on externally
-        let service_port = match service.spec {
-            Some(status) => {
-                match status.ports {
-                    Some(ports) => {
-                        match ports[0].node_port {
-                            Some(port) => port,
-                            None => {
-                                error!("No service node_port found");
-                                return Err("Error retrieving service node_port".to_string());
-                            }
-                        }
-                    },
-                    None => {
-                        error!("Error retrieving service ports");
-                        return Err("Error retrieving service ports".to_string());
-                    }
-                }
-            },
+    let host = cluster_display_address(config.cluster.as_deref());
+    let mut exposed_ports = Vec::with_capacity(config.exposures.len());
+    for exposure in &config.exposures {
+        let node_port = service_ports
+            .iter()
+            .find(|service_port| service_port.name.as_deref() == Some(exposure.name.as_str()))
+            .and_then(|service_port| service_port.node_port);
+
+        match node_port {
+            Some(node_port) => exposed_ports.push(ExposedPort {
+                name: exposure.name.clone(),
+                protocol: exposure.protocol,
+                port: exposure.port,
+                node_port,
+                host: host.clone(),
+            }),
             None => {
-                error!("No service spec found");
-                return Err("No service spec found".to_string());
+                error!("No service node_port found for port {exposure}");
+                return Err(K8sError::MissingNodePorts(format!("{resource_name} (port {})", exposure.name)));
             }
-        };
+        }
+    }
 
-        // just checks to see if the pods are actually running
-        // TODO --> If a chall has multiple pods, possibly make this run only once if a pod errors out
-        match get_pods(client).await {
-            Ok(pods) => {
-                // Not really sure if the best approach here is looking at # of failed or # of succeeded..
-                // TODO - get min replicas, if succeeded pods >= min replicas, chall is fine
-                let succeeded_pods : Vec<Pod> = pods
-                    .into_iter()
-                    .filter(|pod| {
-                        let pod_name = match pod.metadata.name.as_ref() {
-                            Some(name) => name,
-                            None => {
-                                error!("No pod name found");
-                                return false;
-                            }
-                        };
-                        
-                        if !pod_name.starts_with(name) {
+    // just checks to see if the pods are actually running
+    // TODO --> If a chall has multiple pods, possibly make this run only once if a pod errors out
+    match retry("k8s api", format!("pods of {resource_name}"), || get_pods(client)).await {
+        Ok(pods) => {
+            // Not really sure if the best approach here is looking at # of failed or # of succeeded..
+            // TODO - get min replicas, if succeeded pods >= min replicas, chall is fine
+            let (succeeded_pods, failed_pods) : (Vec<Pod>, Vec<Pod>) = pods
+                .into_iter()
+                .filter(|pod| {
+                    let pod_name = match pod.metadata.name.as_ref() {
+                        Some(name) => name,
+                        None => {
+                            error!("No pod name found");
                             return false;
-                        } else {
-                            info!("Pod Found: {:?}", pod_name);
                         }
+                    };
 
-                        let podstatus = match pod.status.as_ref() {
-                            Some(status) => status,
-                            None => {
-                                error!("No pod status found");
-                                return false;
-                            }
-                        };
-
-                        let phase = match podstatus.phase.as_ref() {
-                            Some(phase) => phase,
-                            None => {
-                                error!("Pod phase not found");
-                                return false;
-                            }
-                        };
+                    // Pods of other targets of the same challenge share the name prefix, so match on the app label instead
+                    let pod_app = pod.metadata.labels.as_ref().and_then(|labels| labels.get("app"));
+                    if pod_app != Some(&resource_name) {
+                        false
+                    } else {
+                        info!("Pod Found: {:?}", pod_name);
+                        true
+                    }
+                })
+                .partition(|pod| {
+                    let podstatus = match pod.status.as_ref() {
+                        Some(status) => status,
+                        None => {
+                            error!("No pod status found");
+                            return false;
+                        }
+                    };
 
-                        if phase != "Running" {
-                            error!("Pods are not running... check the logs");
+                    let phase = match podstatus.phase.as_ref() {
+                        Some(phase) => phase,
+                        None => {
+                            error!("Pod phase not found");
                             return false;
-                        } else {
-                            info!("Pod is found and is actually running.");
-                            return true;
                         }
-                    })
-                    .collect();
-
-                // if let Some(minpods) = service.status.map(| spec | spec.conditions ).flatten() {
-                //     info!("Min Pods: {:?}", minpods);
-                // }
-                
-                if succeeded_pods.is_empty() {
-                    error!("Pods are not running... check the logs");
-                    return Err("Pods are not running... check the logs".to_string());
-                }
-            },
-            Err(err) => {
-                error!("Error retrieving pods");
-                info!("Trace: {:?}", err);
-                return Err(err);
+                    };
+
+                    if phase != "Running" {
+                        error!("Pods are not running... check the logs");
+                        false
+                    } else {
+                        info!("Pod is found and is actually running.");
+                        true
+                    }
+                });
+
+            // if let Some(minpods) = service.status.map(| spec | spec.conditions ).flatten() {
+            //     info!("Min Pods: {:?}", minpods);
+            // }
+            
+            if succeeded_pods.is_empty() {
+                error!("Pods are not running... check the logs");
+                return Err(K8sError::NotRunning { name: resource_name, reasons: pod_failure_reasons(&failed_pods) });
             }
+        },
+        Err(err) => {
+            error!("Error retrieving pods");
+            info!("Trace: {:?}", err);
+            return Err(err);
         }
+    }
+
+    info!("Challenge {name} ({resource_name}) successfully created --> port(s) {exposed_ports:?}");
+    Ok(exposed_ports)
+}
 
-        info!("Challenge {name} successfully created --> port {service_port}");
-        port_list.push(service_port);
+/// Why the given pods aren't running, from the waiting/terminated state of their containers and their failed conditions
+/// 
+/// ## Returns
+/// - `Vec<String>` - One `<pod>: <reason> (<message>)` entry per reason, e.g. `ImagePullBackOff` or `CrashLoopBackOff`
+pub fn pod_failure_reasons(pods: &[Pod]) -> Vec<String> {
+    let mut reasons = vec![];
+
+    for pod in pods {
+        let pod_name = pod.metadata.name.as_deref().unwrap_or("<unnamed pod>");
+        let Some(status) = pod.status.as_ref() else {
+            reasons.push(format!("{pod_name}: no status reported"));
+            continue;
+        };
+
+        let container_states = status.init_container_statuses.iter().flatten()
+            .chain(status.container_statuses.iter().flatten())
+            .filter_map(|container| container.state.as_ref());
+
+        for state in container_states {
+            if let Some(waiting) = &state.waiting {
+                let reason = waiting.reason.as_deref().unwrap_or("Waiting");
+                reasons.push(match &waiting.message {
+                    Some(message) => format!("{pod_name}: {reason} ({message})"),
+                    None => format!("{pod_name}: {reason}"),
+                });
+            }
+            if let Some(terminated) = &state.terminated {
+                let reason = terminated.reason.as_deref().unwrap_or("Terminated");
+                reasons.push(format!("{pod_name}: {reason} (exit code {})", terminated.exit_code));
+            }
+        }
+
+        let failed_conditions = status.conditions.iter().flatten()
+            .filter(|condition| condition.status == "False")
+            .filter_map(|condition| Some((condition.reason.as_deref()?, condition.message.as_deref())));
+
+        for (reason, message) in failed_conditions {
+            reasons.push(match message {
+                Some(message) => format!("{pod_name}: {reason} ({message})"),
+                None => format!("{pod_name}: {reason}"),
+            });
+        }
+
+        if let (Some(reason), Some(phase)) = (status.reason.as_deref(), status.phase.as_deref()) {
+            reasons.push(format!("{pod_name}: {reason} (phase {phase})"));
+        }
     }
-    Ok(port_list)
+
+    reasons.dedup();
+    reasons
+}
+
+/// Reads the exposed ports back out of a [`Service`][Service] created by this crate, running on the cluster `cluster`
+pub(crate) fn exposed_ports_from_service(service: &Service, cluster: Option<&str>) -> Result<Vec<ExposedPort>, K8sError> {
+    let host = cluster_display_address(cluster);
+
+    let service_name = service.metadata.name.clone().unwrap_or_default();
+
+    let Some(service_ports) = service.spec.as_ref().and_then(|spec| spec.ports.as_ref()) else {
+        error!("Error retrieving service ports");
+        return Err(K8sError::MissingNodePorts(service_name));
+    };
+
+    service_ports
+        .iter()
+        .map(|service_port| {
+            let Some(node_port) = service_port.node_port else {
+                error!("No service node_port found for port {:?}", service_port.name);
+                return Err(K8sError::MissingNodePorts(format!("{service_name} (port {:?})", service_port.name)));
+            };
+
+            let protocol = match service_port.protocol.as_deref() {
+                Some("UDP") => NetworkProtocol::Udp,
+                _ => NetworkProtocol::Tcp,
+            };
+
+            Ok(ExposedPort {
+                name: service_port.name.clone().unwrap_or_default(),
+                protocol,
+                port: service_port.port,
+                node_port,
+                host: host.clone(),
+            })
+        })
+        .collect()
 }
 
 // TODO --> Add a check to see if there is more than 1 replica, and if so, set up a loadBalancer for that chall instead of a nodePort - may not be necessary
 
-/// Creates a Kubernetes [`Service`][Service] with name `<ChallengeName>-service` for a given challenge
+/// Creates a Kubernetes [`Service`][Service] with name `<ResourceName>-service` for a given deploy target
 /// 
 /// This function serves mostly as a wrapper around [`create_schema_service`][create_schema_service], which generates the schema for the [`Service`][Service].
 /// If a service already exists, deletes the existing one and recreates it.
 /// 
 /// ## Returns
 /// - `Ok(Service)` - Kubernetes [`Service`][Service] object
-/// - `Err(String)` - Error trace if error occurs
-async fn create_service(client: &Client, name : &str, chall_folder_path: Option<&str>) -> Result<Service, String> {
-    let services: Api<Service> = Api::default_namespaced(client.clone());
-    let service_name = format!("{}-service", name);
+/// - `Err(K8sError)` - Error trace if error occurs
+async fn create_service(client: &Client, config: &TargetConfig) -> Result<Service, K8sError> {
+    let resource_name = config.resource_name();
+    let data_service = create_schema_service(&resource_name, config).await?;
 
-    let chall_params = match fetch_challenge_params(name, chall_folder_path) {
-        Ok(chall_params) => chall_params,
-        Err(err) => {
-            error!("Error fetching challenge params");
-            info!("Trace: {:?}", err);
-            return Err(err);
-        }
-    };
+    apply_service(client, &resource_name, &data_service).await
+}
 
-    // TODO --> admin bot branch
-    let data_service : Service;
-    if let Some(params) = chall_params.get("web").or_else(|| chall_params.get("nc")) {
-        data_service = create_schema_service(name, params).await?;
-    } else {
-        return Err("Error creating service schema, check yaml".to_string());
-    }
+/// Creates the given [`Service`][Service] for the resource `resource_name`, deleting the existing one first if there is one
+pub(crate) async fn apply_service(client: &Client, resource_name: &str, data_service: &Service) -> Result<Service, K8sError> {
+    let services: Api<Service> = Api::default_namespaced(client.clone());
+    let service_name = format!("{}-service", resource_name);
 
-    match service_exists(client, name).await {
+    match service_exists(client, resource_name).await {
         Ok(status) => {
             if status {
                 warn!("Service already exists, deleting");
-                if let Err(err) = delete_service(client, name).await {
-                    return Err(err);
-                }
+                delete_service(client, resource_name).await?;
             }
         }, 
         Err(err) => {
-            return Err(err.to_string());
+            error!("Error checking if service exists");
+            debug!("Trace: {:?}", err);
+            return Err(K8sError::api("Error checking if service exists", err));
         } 
     };
 
-    match services.create(&PostParams::default(), &data_service).await {
+    match services.create(&PostParams::default(), data_service).await {
         Ok(service_instance) => {
             info!("Service {} created", service_name);
             Ok(service_instance)
@@ -324,113 +562,288 @@ async fn create_service(client: &Client, name : &str, chall_folder_path: Option<
         Err(err) => {
             error!("Error creating service");
             info!("Trace: {:?}", err);
-            Err(err.to_string())
+            Err(K8sError::api("Error creating service", err))
         }
     }
 }
 
-/// Generates Kubernetes `Secret` that allows it to authenticate with the remote Docker registry to pull images
+/// Creates the [`HorizontalPodAutoscaler`][HorizontalPodAutoscaler] of a deploy target, if it is autoscaled
 /// 
-/// Secret name generated is `container-registry-credentials` and is stored in the default namespace
+/// Any existing autoscaler of the target is deleted first, so turning autoscaling off in the chall.yaml removes it on redeploy.
 /// 
 /// ## Returns
-/// - `Ok(Secret)` - Kubernetes [`Secret`][Secret] object
-/// - `Err(String)` - Error trace if error occurs
-async fn generate_registry_secret(client: &Client) -> Result<Secret, String>{
-    info!("Generating remote Docker registry secret...");
-    let secrets: Api<Secret> = Api::default_namespaced(client.clone());
+/// - `Ok(Some(HorizontalPodAutoscaler))` - The created autoscaler
+/// - `Ok(None)` - The target isn't autoscaled
+/// - `Err(K8sError)` - Error trace if error occurs
+async fn create_autoscaler(client: &Client, config: &TargetConfig) -> Result<Option<HorizontalPodAutoscaler>, K8sError> {
+    let autoscalers: Api<HorizontalPodAutoscaler> = Api::default_namespaced(client.clone());
+    let autoscaler_name = config.autoscaler_name();
 
-    match secret_exists(client, "container-registry-credentials").await {
-        Ok(status) => {
-            if status {
-                warn!("Registry secret already exists, deleting");
-                if let Err(err) = delete_secret(client, "container-registry-credentials").await {
-                    return Err(err);
+    match autoscaler_exists(client, &autoscaler_name).await {
+        Ok(true) => {
+            warn!("Autoscaler {autoscaler_name} already exists, deleting");
+            delete_autoscaler(client, &autoscaler_name).await?;
+        },
+        Ok(false) => (),
+        Err(err) => {
+            error!("Error checking if autoscaler exists");
+            debug!("Trace: {:?}", err);
+            return Err(K8sError::api("Error checking if autoscaler exists", err));
+        }
+    };
+
+    let Some(autoscale) = &config.autoscale else {
+        return Ok(None);
+    };
+
+    let autoscaler: HorizontalPodAutoscaler = match serde_json::from_value(serde_json::json!({
+        "apiVersion": "autoscaling/v2",
+        "kind": "HorizontalPodAutoscaler",
+        "metadata": {
+            "name": autoscaler_name,
+            "labels": {
+                "app": config.resource_name(),
+                CHALL_LABEL: chall_label_value(&config.chall_name)
+            }
+        },
+        "spec": {
+            "scaleTargetRef": {
+                "apiVersion": "apps/v1",
+                "kind": "Deployment",
+                "name": config.resource_name()
+            },
+            "minReplicas": autoscale.min_replicas,
+            "maxReplicas": autoscale.max_replicas,
+            "metrics": [
+                {
+                    "type": "Resource",
+                    "resource": {
+                        "name": "cpu",
+                        "target": {
+                            "type": "Utilization",
+                            "averageUtilization": autoscale.cpu_utilization
+                        }
+                    }
                 }
+            ]
+        }
+    })) {
+        Ok(autoscaler) => autoscaler,
+        Err(err) => {
+            error!("Error generating json for autoscaler");
+            debug!("Trace: {:?}", err);
+            return Err(K8sError::object("autoscaler", err));
+        }
+    };
+
+    match autoscalers.create(&PostParams::default(), &autoscaler).await {
+        Ok(autoscaler) => {
+            info!("Autoscaler {autoscaler_name} created ({}-{} replicas)", autoscale.min_replicas, autoscale.max_replicas);
+            Ok(Some(autoscaler))
+        },
+        Err(err) => {
+            error!("Error creating autoscaler {autoscaler_name}");
+            debug!("Trace: {:?}", err);
+            Err(K8sError::api(format!("Error creating autoscaler {autoscaler_name}"), err))
+        }
+    }
+}
+
+/// Creates the `Secret` holding the flag of a deploy target, named `<ResourceName>-flag`
+/// 
+/// If the secret already exists, it is deleted and recreated. If the target doesn't ask for the flag,
+/// any secret left over from a previous deployment is deleted instead.
+/// 
+/// ## Returns
+/// - `Ok(Option<Secret>)` - Kubernetes [`Secret`][Secret] object, `None` if the target doesn't need one
+/// - `Err(K8sError)` - Error trace if error occurs
+async fn create_flag_secret(client: &Client, config: &TargetConfig) -> Result<Option<Secret>, K8sError> {
+    let secrets: Api<Secret> = Api::default_namespaced(client.clone());
+    let secret_name = config.flag_secret_name();
+
+    match secret_exists(client, &secret_name).await {
+        Ok(true) => {
+            warn!("Flag secret {secret_name} already exists, deleting");
+            delete_secret(client, &secret_name).await?;
+        },
+        Ok(false) => (),
+        Err(err) => {
+            error!("Error checking if flag secret exists");
+            debug!("Trace: {:?}", err);
+            return Err(K8sError::api("Error checking if flag secret exists", err));
+        }
+    };
+
+    let Some(flag) = &config.flag else {
+        return Ok(None);
+    };
+
+    let secret: Secret = match serde_json::from_value(serde_json::json!({
+        "apiVersion": "v1",
+        "kind": "Secret",
+        "metadata": {
+            "name": secret_name,
+            "labels": {
+                "app": config.resource_name(),
+                CHALL_LABEL: chall_label_value(&config.chall_name)
             }
         },
+        "stringData": {
+            "flag": flag.value
+        },
+        "type": "Opaque"
+    })) {
+        Ok(secret) => secret,
         Err(err) => {
-            error!("Error checking if Docker registry secret exists");
+            error!("Error generating json for flag secret");
             debug!("Trace: {:?}", err);
-            return Err(err.to_string());
+            return Err(K8sError::object("flag secret", err));
         }
     };
 
-    let registry_username = reg_username();
-    let registry_password = reg_password();
-    let registry_url = reg_url();
-    
-    use base64::{Engine as _, engine::general_purpose::STANDARD as base64encoderator};
+    match secrets.create(&PostParams::default(), &secret).await {
+        Ok(secret) => {
+            info!("Flag secret {secret_name} created");
+            Ok(Some(secret))
+        },
+        Err(err) => {
+            error!("Error creating flag secret {secret_name}");
+            debug!("Trace: {:?}", err);
+            Err(K8sError::api(format!("Error creating flag secret {secret_name}"), err))
+        }
+    }
+}
 
-    let mut encoded = String::with_capacity((registry_username.len() + registry_password.len()) * 4 / 3 + 4);
+/// Key of the `n`th mounted file in the files `ConfigMap` of a deploy target
+fn config_map_file_key(idx: usize) -> String {
+    format!("file-{idx}")
+}
 
-    base64encoderator.encode_string(format!("{}:{}", registry_username, registry_password), &mut encoded);
+/// Creates the `ConfigMap` holding the files mounted into a deploy target's containers, named `<ResourceName>-files`
+/// 
+/// File sources are read relative to the challenge folder. Files that aren't valid UTF-8 are stored as binary data.
+/// If the config map already exists, it is deleted and recreated. If the target doesn't mount any files,
+/// any config map left over from a previous deployment is deleted instead.
+/// 
+/// ## Returns
+/// - `Ok(Option<ConfigMap>)` - Kubernetes [`ConfigMap`][ConfigMap] object, `None` if the target doesn't need one
+/// - `Err(K8sError)` - Error trace if error occurs
+async fn create_files_config_map(client: &Client, config: &TargetConfig) -> Result<Option<ConfigMap>, K8sError> {
+    let config_maps: Api<ConfigMap> = Api::default_namespaced(client.clone());
+    let config_map_name = config.files_config_map_name();
 
-    let dockerconfigdata : String = "{\"auths\":{\"".to_owned() + registry_url + "\":{\"username\":\"" + registry_username + "\",\"password\":\"" + registry_password + "\",\"auth\":\"" + &encoded + "\"}}}";
-    let mut base_encoded_dockerconfigdata : String = String::with_capacity(dockerconfigdata.len() * 4 / 3 + 4);
-    
-    base64encoderator.encode_string(dockerconfigdata, &mut base_encoded_dockerconfigdata);
+    match config_map_exists(client, &config_map_name).await {
+        Ok(true) => {
+            warn!("Files config map {config_map_name} already exists, deleting");
+            delete_config_map(client, &config_map_name).await?;
+        },
+        Ok(false) => (),
+        Err(err) => {
+            error!("Error checking if files config map exists");
+            debug!("Trace: {:?}", err);
+            return Err(K8sError::api("Error checking if files config map exists", err));
+        }
+    };
 
-    let secret : Result<Secret, String> = match serde_json::from_value(serde_json::json!({
-            "apiVersion": "v1",
-            "data": {
-                ".dockerconfigjson": base_encoded_dockerconfigdata
-            },
-            "kind": "Secret",
-            "metadata": {
-                "name": "container-registry-credentials",
-                "namespace": "default"
+    if config.files.is_empty() {
+        return Ok(None);
+    }
+
+    let chall_folder = PathBuf::from_iter([get_chall_folder(None), config.chall_name.clone()]);
+
+    let mut data = serde_json::Map::new();
+    let mut binary_data = serde_json::Map::new();
+    for (idx, file) in config.files.iter().enumerate() {
+        let escapes_chall_folder = file.source
+            .components()
+            .any(|component| !matches!(component, Component::Normal(_) | Component::CurDir));
+        if escapes_chall_folder {
+            error!("Mounted file source {:?} must be a relative path inside of the challenge folder", file.source);
+            return Err(K8sError::InvalidFile(format!("Mounted file source {:?} must be a relative path inside of the challenge folder", file.source)));
+        }
+
+        let contents = match std::fs::read(chall_folder.join(&file.source)) {
+            Ok(contents) => contents,
+            Err(err) => {
+                error!("Error reading mounted file {:?}", file.source);
+                debug!("Trace: {:?}", err);
+                return Err(K8sError::InvalidFile(format!("Failed to read mounted file {:?}: {err}", file.source)));
+            }
+        };
+
+        let key = config_map_file_key(idx);
+        match String::from_utf8(contents) {
+            Ok(text) => { data.insert(key, text.into()); },
+            Err(err) => {
+                use base64::{Engine as _, engine::general_purpose::STANDARD as base64encoderator};
+                binary_data.insert(key, base64encoderator.encode(err.into_bytes()).into());
             },
-            "type": "kubernetes.io/dockerconfigjson"
         }
-    )) {
-        Ok(data_deploy) => {
-            Ok(data_deploy)
+    }
+
+    let config_map: ConfigMap = match serde_json::from_value(serde_json::json!({
+        "apiVersion": "v1",
+        "kind": "ConfigMap",
+        "metadata": {
+            "name": config_map_name,
+            "labels": {
+                "app": config.resource_name(),
+                CHALL_LABEL: chall_label_value(&config.chall_name)
+            }
         },
+        "data": data,
+        "binaryData": binary_data
+    })) {
+        Ok(config_map) => config_map,
         Err(err) => {
-            error!("Error generating json for Docker registry secret");
-            debug!("TRACE: {:?}", err);
-            Err(err.to_string())
+            error!("Error generating json for files config map");
+            debug!("Trace: {:?}", err);
+            return Err(K8sError::object("files config map", err));
         }
     };
 
-    match secrets.create(&Default::default(), &secret?).await {
-        Ok(secret) => {
-            Ok(secret)
+    match config_maps.create(&PostParams::default(), &config_map).await {
+        Ok(config_map) => {
+            info!("Files config map {config_map_name} created");
+            Ok(Some(config_map))
         },
         Err(err) => {
-            error!("Error creating secret with json data");
+            error!("Error creating files config map {config_map_name}");
             debug!("Trace: {:?}", err);
-            Err(err.to_string())
+            Err(K8sError::api(format!("Error creating files config map {config_map_name}"), err))
         }
     }
 }
 
 // TODO - migrate schema to a separate file for organizational purposes
-/// Generates Service object with name `<ChallengeName>-service` from the current service schema for a given challenge
+/// Generates Service object with name `<ResourceName>-service` from the current service schema for a given deploy target
 /// 
 /// ## Returns 
 /// - `Ok(Service)` - Kubernetes [`Service`][Service] object
-/// - `Err(String)` - Error trace if error occurs
-async fn create_schema_service(name: &str, params: &ChallengeParams) -> Result<Service, String> {
+/// - `Err(K8sError)` - Error trace if error occurs
+async fn create_schema_service(name: &str, config: &TargetConfig) -> Result<Service, K8sError> {
     let service_name = format!("{}-service", name);
+    let service_ports: Vec<_> = config.exposures
+        .iter()
+        .map(|exposure| serde_json::json!({
+            "name": exposure.name,
+            "port": exposure.port,
+            "targetPort": exposure.port,
+            "protocol": exposure.protocol.k8s_name()
+        }))
+        .collect();
     match serde_json::from_value(serde_json::json!({
         "apiVersion": "v1",
         "kind": "Service",
         "metadata": {
             "name": service_name,
             "labels": {
-                "app": service_name
+                "app": service_name,
+                CHALL_LABEL: chall_label_value(&config.chall_name)
             }
         },
         "spec": {
-            "ports": [
-                {
-                    "port": params.expose.port(),
-                    "targetPort": params.expose.port(),
-                    "protocol": params.expose.protocol()
-                }
-            ],
+            "ports": service_ports,
             "externalIPs": [
             ],
             "selector": {
@@ -443,63 +856,73 @@ async fn create_schema_service(name: &str, params: &ChallengeParams) -> Result<S
         Err(err) => {
             error!("Error creating schema for service");
             debug!("Trace: {:?}", err);
-            Err(err.to_string())
+            Err(K8sError::object("service", err))
         }
     }
 }
 
 // TODO - migrate schema to a separate file for organizational purposes
-/// Generates a Kubernetes [`Deployment`][Deployment] object for a given challenge
+/// Generates a Kubernetes [`Deployment`][Deployment] object for a given deploy target
 /// 
 /// If a deployment already exists, it will be deleted and recreated
 /// 
 /// ## Returns
 /// - `Ok(Deployment)` - Kubernetes [`Deployment`][Deployment] object
-/// - `Err(String)` - Error trace if error occurs
-async fn create_deployment(client: &Client, name: &str, chall_folder_path: Option<&str>) -> Result<Deployment, String> {
-    let deployments: Api<Deployment> = Api::default_namespaced(client.clone());
+/// - `Err(K8sError)` - Error trace if error occurs
+async fn create_deployment(client: &Client, config: &TargetConfig) -> Result<Deployment, K8sError> {
+    let resource_name = config.resource_name();
+    let name = resource_name.as_str();
 
     info!("Creating deployment");
-    let chall_params = match fetch_challenge_params(name, chall_folder_path) {
-        Ok(chall_params) => chall_params,
-        Err(err) => {
-            error!("Error fetching challenge params");
-            info!("Trace: {:?}", err);
-            return Err(err);
+    let data_deploy = create_schema_deployment(name, config)?;
+
+    apply_deployment(client, name, &data_deploy).await
+}
+
+/// Creates the given [`Deployment`][Deployment] named `name` and waits for it to become available
+/// 
+/// If a deployment with the same name already exists, it will be deleted and recreated. Removing the old deployment and
+/// creating the new one are retried on their own, watching the rollout isn't: once the deployment exists, a failed rollout
+/// is reported instead of deleting and recreating the deployment again.
+pub(crate) async fn apply_deployment(client: &Client, name: &str, data_deploy: &Deployment) -> Result<Deployment, K8sError> {
+    let deployments: Api<Deployment> = Api::default_namespaced(client.clone());
+
+    let removed = retry("k8s apply", format!("old deployment {name}"), || async {
+        let exists = deploy_exists(client, name).await.map_err(|err| {
+            error!("Error checking if deployment exists");
+            debug!("Trace: {:?}", err);
+            K8sError::api("Error checking if deployment exists", err)
+        })?;
+
+        if exists {
+            warn!("Deployment already exists, deleting");
+            delete_deployment(client, name).await?;
         }
-    };
+        Ok::<(), K8sError>(())
+    }).await;
 
-    let data_deploy: Deployment;
-    // goes and checks each subsection for yaml, if web chall, creates schema for web, if admin, creates schema for admin, etc.
-    // can add custom ones in the future if need be here
-    if let Some(params) = chall_params.get("web") {
-        data_deploy = create_schema_deployment(name, params)?;
-    } else if let Some(params) = chall_params.get("nc") {
-        data_deploy = create_schema_deployment(name, params)?;
-    } else if let Some(_params) = chall_params.get("admin") {
-        todo!("Admin bots not yet supported");
-    } else {
-        error!("Error creating service schema, check yaml and ensure either \"web\", \"nc\" or \"admin\" are specified");
-        return Err("Error creating service schema, check yaml".to_string());
+    if let Err(err) = removed {
+        error!("Error deleting deployment");
+        debug!("Trace: {:?}", err);
+        return Err(err);
     }
 
-    match deploy_exists(client, name).await {
-        Ok(status) => {
-            if status {
-                warn!("Deployment already exists, deleting");
-                if let Err(err) = delete_deployment(client, name).await {
-                    error!("Error deleting deployment");
-                    debug!("Trace: {:?}", err);
-                    return Err(err);
-                }
-            }
-        }, 
-        Err(err) => {
-            return Err(err.to_string());
-        } 
-    };
+    // A retry that finds the deployment already there means the previous attempt went through, but its response was lost
+    let attempted = AtomicBool::new(false);
+    let created = retry("k8s apply", format!("deployment {name}"), || async {
+        let retried = attempted.swap(true, Ordering::Relaxed);
+        let result = match deployments.create(&PostParams::default(), data_deploy).await {
+            Err(Error::Api(response)) if retried && response.code == 409 => {
+                debug!("Deployment {name} was created by an earlier attempt");
+                deployments.get(name).await
+            },
+            result => result,
+        };
+        result.map_err(|err| K8sError::api(format!("Error creating deployment {}", name), err))
+    }).await;
+
     // TODO --> make it wait for deployment to be ready?
-    match deployments.create(&PostParams::default(), &data_deploy).await {
+    match created {
         Ok(deployment_instance) => {
             info!("Deployment {} created", name);
             let watcher_config = Config {
@@ -530,7 +953,7 @@ async fn create_deployment(client: &Client, name: &str, chall_folder_path: Optio
                                     info!("Deployment in progress...");
                                 } else if type_of_status == "ReplicaFailure" {
                                     error!("Error occurred while deploying");
-                                    return Err("Error occurred while deploying".to_string());
+                                    return Err(K8sError::RolloutFailed(name.to_string()));
                                 }
                             }
                         }
@@ -538,7 +961,7 @@ async fn create_deployment(client: &Client, name: &str, chall_folder_path: Optio
                     Err(err) => {
                         error!("Error watching deployment");
                         debug!("Trace: {:?}", err);
-                        return Err(err.to_string());
+                        return Err(K8sError::Watch { name: name.to_string(), source: err });
                     }
                 }
             }
@@ -548,23 +971,102 @@ async fn create_deployment(client: &Client, name: &str, chall_folder_path: Optio
         Err(err) => {
             error!("Error creating deployment {}", name);
             info!("Trace: {:?}", err);
-            Err(err.to_string())
+            Err(err)
         }
     }
 
 }
 
-/// Generates a Kubernetes [`Deployment`][Deployment] object from the current deployment schema for a given challenge
+/// Generates a Kubernetes [`Deployment`][Deployment] object from the current deployment schema for a given deploy target
 /// 
 /// ## Returns
 /// - `Ok(Deployment)` - Kubernetes [`Deployment`][Deployment] object
-/// - `Err(String)` - Error trace if error occurs
-fn create_schema_deployment(name: &str, chall_params: &ChallengeParams) -> Result<Deployment, String>{
-    let registry_url = reg_url();
+/// - `Err(K8sError)` - Error trace if error occurs
+fn create_schema_deployment(name: &str, config: &TargetConfig) -> Result<Deployment, K8sError>{
+    let container_ports: Vec<_> = config.exposures
+        .iter()
+        .map(|exposure| serde_json::json!({
+            "name": exposure.name,
+            "containerPort": exposure.port,
+            "protocol": exposure.protocol.k8s_name()
+        }))
+        .collect();
+
+    let mut env: Vec<_> = config.env
+        .iter()
+        .map(|(name, value)| serde_json::json!({ "name": name, "value": value }))
+        .collect();
+    let mut volume_mounts = vec![];
+    let mut volumes = vec![];
+
+    if let Some(flag) = &config.flag {
+        if let Some(env_name) = &flag.env {
+            env.push(serde_json::json!({
+                "name": env_name,
+                "valueFrom": {
+                    "secretKeyRef": {
+                        "name": config.flag_secret_name(),
+                        "key": "flag"
+                    }
+                }
+            }));
+        }
+        if let Some(file) = &flag.file {
+            volume_mounts.push(serde_json::json!({
+                "name": "flag",
+                "mountPath": file,
+                "subPath": "flag",
+                "readOnly": true
+            }));
+            volumes.push(serde_json::json!({
+                "name": "flag",
+                "secret": {
+                    "secretName": config.flag_secret_name()
+                }
+            }));
+        }
+    }
+
+    if !config.files.is_empty() {
+        for (idx, file) in config.files.iter().enumerate() {
+            volume_mounts.push(serde_json::json!({
+                "name": "files",
+                "mountPath": file.mount,
+                "subPath": config_map_file_key(idx),
+                "readOnly": true
+            }));
+        }
+        volumes.push(serde_json::json!({
+            "name": "files",
+            "configMap": {
+                "name": config.files_config_map_name()
+            }
+        }));
+    }
+
+    let mut container = serde_json::json!({
+        "name": name,
+        "image": config.image_reference(),
+        // An image pinned by digest can't change, only the mutable tag has to be pulled every time
+        "imagePullPolicy": if config.image_digest.is_some() { "IfNotPresent" } else { "Always" },
+        "ports": container_ports,
+        "env": env,
+        "volumeMounts": volume_mounts
+    });
+
+    // CPU utilization is relative to the requested CPU, so autoscaled containers have to request some
+    if let Some(autoscale) = &config.autoscale {
+        container["resources"] = serde_json::json!({
+            "requests": {
+                "cpu": autoscale.cpu_request
+            }
+        });
+    }
 
-    let mut path_on_registry = PathBuf::new();
-            path_on_registry.push(registry_url);
-            path_on_registry.push(name);
+    let image_pull_secrets: Vec<_> = registry_secret_names()
+        .into_iter()
+        .map(|name| serde_json::json!({ "name": name }))
+        .collect();
 
     match serde_json::from_value(serde_json::json!({
         "apiVersion": "apps/v1",
@@ -572,11 +1074,12 @@ fn create_schema_deployment(name: &str, chall_params: &ChallengeParams) -> Resul
         "metadata": {
             "name": name,
             "labels": {
-                "app": name
+                "app": name,
+                CHALL_LABEL: chall_label_value(&config.chall_name)
             }
         },
         "spec": {
-            "replicas": chall_params.replicas,
+            "replicas": config.initial_replicas(),
             "selector": {
                 "matchLabels": {
                     "app": name
@@ -585,28 +1088,14 @@ fn create_schema_deployment(name: &str, chall_params: &ChallengeParams) -> Resul
             "template": {
                 "metadata": {
                     "labels": {
-                        "app": name
+                        "app": name,
+                        CHALL_LABEL: chall_label_value(&config.chall_name)
                     }
                 },
                 "spec": {
-                    "containers": [
-                            {
-                                "name": name,
-                                "image": path_on_registry.to_str().unwrap(),
-                                "imagePullPolicy": "Always",
-                                "ports": [
-                                    {
-                                        "containerPort": chall_params.expose.port(),
-                                        "protocol": chall_params.expose.protocol()
-                                    },
-                                ]
-                            }
-                        ],
-                    "imagePullSecrets": [
-                            {
-                                "name": "container-registry-credentials"
-                            }
-                        ]
+                    "containers": [ container ],
+                    "volumes": volumes,
+                    "imagePullSecrets": image_pull_secrets
                     }
                 }
             }
@@ -618,21 +1107,25 @@ fn create_schema_deployment(name: &str, chall_params: &ChallengeParams) -> Resul
         Err(err) => {
             error!("Error creating deployment schema");
             debug!("Trace: {:?}", err);
-            Err(err.to_string())
+            Err(K8sError::object("deployment", err))
         }
     }
 }
 
 // TODO --> Merge delete deployment and service into one function, secret might not be as easy but possible
-pub async fn delete_deployment(client : &Client, name : &str) -> Result<(), String> {
+pub async fn delete_deployment(client : &Client, name : &str) -> Result<(), K8sError> {
     info!("Deleting deployment {name}");
     let deployments: Api<Deployment> = Api::default_namespaced(client.clone());
-    deployments.delete(name, &DeleteParams::default()).await.unwrap();
+    if let Err(err) = deployments.delete(name, &DeleteParams::default()).await {
+        error!("Error deleting deployment {name}");
+        debug!("Trace: {:?}", err);
+        return Err(K8sError::api(format!("Error deleting deployment {name}"), err));
+    }
     info!("Successfully deleted deployment {name}");
     Ok(())
 }
 
-pub async fn delete_service(client: &Client, name : &str) -> Result<(), String> {
+pub async fn delete_service(client: &Client, name : &str) -> Result<(), K8sError> {
     info!("Deleting service {name}");
     let services: Api<Service> = Api::default_namespaced(client.clone());
     match services.delete(format!("{name}-service").as_str(), &DeleteParams::default()).await {
@@ -643,12 +1136,12 @@ pub async fn delete_service(client: &Client, name : &str) -> Result<(), String>
         Err(err) => {
             error!("Error deleting service {name}");
             debug!("Trace: {:?}", err);
-            Err(err.to_string())
+            Err(K8sError::api(format!("Error deleting service {name}"), err))
         }
     }
 }
 
-pub async fn delete_secret(client: &Client, name : &str) -> Result<String, String> {
+pub async fn delete_secret(client: &Client, name : &str) -> Result<String, K8sError> {
     info!("Deleting Kubernetes secret \"{}\"...", name);
     let secrets: Api<Secret> = Api::default_namespaced(client.clone());
     let status = match secrets.delete(name, &DeleteParams::default()).await {
@@ -658,7 +1151,7 @@ pub async fn delete_secret(client: &Client, name : &str) -> Result<String, Strin
         Err(err) => {
             error!("Error deleting secret");
             debug!("Trace: {:?}", err);
-            return Err(err.to_string());
+            return Err(K8sError::api("Error deleting secret", err));
         }
     };
         
@@ -670,17 +1163,97 @@ pub async fn delete_secret(client: &Client, name : &str) -> Result<String, Strin
             } else {
                 error!("Error deleting secret {:?}", name);
                 debug!("{:?}", status);
-                Err("Error deleting secret".to_string())
+                Err(K8sError::DeleteRejected(format!("secret {name}")))
             }
         },
         None => {
             error!("Error deleting secret {:?}", name);
-            Err("Error deleting secret".to_string())
+            Err(K8sError::DeleteRejected(format!("secret {name}")))
+        }
+    }
+}
+
+pub async fn delete_config_map(client: &Client, name : &str) -> Result<(), K8sError> {
+    info!("Deleting Kubernetes config map \"{}\"...", name);
+    let config_maps: Api<ConfigMap> = Api::default_namespaced(client.clone());
+    match config_maps.delete(name, &DeleteParams::default()).await {
+        Ok(_) => {
+            info!("Successfully deleted config map {:?}", name);
+            Ok(())
+        },
+        Err(err) => {
+            error!("Error deleting config map {:?}", name);
+            debug!("Trace: {:?}", err);
+            Err(K8sError::api(format!("Error deleting config map {:?}", name), err))
         }
     }
 }
 
-pub async fn delete_challenge(client : &Client, name_list : Vec<&str>) -> Result<(), String> {
+pub async fn delete_autoscaler(client: &Client, name : &str) -> Result<(), K8sError> {
+    info!("Deleting Kubernetes autoscaler \"{}\"...", name);
+    let autoscalers: Api<HorizontalPodAutoscaler> = Api::default_namespaced(client.clone());
+    match autoscalers.delete(name, &DeleteParams::default()).await {
+        Ok(_) => {
+            info!("Successfully deleted autoscaler {:?}", name);
+            Ok(())
+        },
+        Err(err) => {
+            error!("Error deleting autoscaler {:?}", name);
+            debug!("Trace: {:?}", err);
+            Err(K8sError::api(format!("Error deleting autoscaler {:?}", name), err))
+        }
+    }
+}
+
+/// Sets the number of replicas of a live deploy target, without redeploying it
+/// 
+/// If the target is autoscaled, its autoscaler's minimum is raised (or lowered) to `replicas` instead, since the
+/// autoscaler would otherwise scale the deployment straight back. Autoscalers can't go below one replica.
+/// 
+/// ## Returns
+/// - `Ok(true)` - The target's autoscaler was updated
+/// - `Ok(false)` - The target's deployment was scaled
+/// - `Err(K8sError)` - [`ScaleRejected`][K8sError::ScaleRejected] if an autoscaled target would be scaled to 0, error
+///   trace if another error occurs
+pub async fn scale_target(client: &Client, resource_name: &str, replicas: i32) -> Result<bool, K8sError> {
+    let autoscalers: Api<HorizontalPodAutoscaler> = Api::default_namespaced(client.clone());
+    let autoscaler_name = autoscaler_name(resource_name);
+
+    let autoscaler = match autoscalers.get_opt(&autoscaler_name).await {
+        Ok(autoscaler) => autoscaler,
+        Err(err) => {
+            error!("Error fetching autoscaler {autoscaler_name}");
+            debug!("Trace: {:?}", err);
+            return Err(K8sError::api(format!("Error fetching autoscaler {autoscaler_name}"), err));
+        }
+    };
+
+    let Some(autoscaler) = autoscaler else {
+        reconcile::scale_deployment(client, resource_name, replicas).await?;
+        return Ok(false);
+    };
+
+    if replicas < 1 {
+        return Err(K8sError::ScaleRejected(format!("{resource_name} is autoscaled, it can't be scaled to {replicas} replicas")));
+    }
+
+    let max_replicas = autoscaler.spec.map(|spec| spec.max_replicas).unwrap_or(replicas).max(replicas);
+    let patch = serde_json::json!({ "spec": { "minReplicas": replicas, "maxReplicas": max_replicas } });
+
+    match autoscalers.patch(&autoscaler_name, &PatchParams::default(), &Patch::Merge(&patch)).await {
+        Ok(_) => {
+            info!("Autoscaler {autoscaler_name} now scales between {replicas} and {max_replicas} replicas");
+            Ok(true)
+        },
+        Err(err) => {
+            error!("Error patching autoscaler {autoscaler_name}");
+            debug!("Trace: {:?}", err);
+            Err(K8sError::api(format!("Error patching autoscaler {autoscaler_name}"), err))
+        }
+    }
+}
+
+pub async fn delete_challenge(client : &Client, name_list : Vec<&str>) -> Result<(), K8sError> {
     for name in name_list {
         info!("Deleting challenge {:?}", name);
 
@@ -689,7 +1262,7 @@ pub async fn delete_challenge(client : &Client, name_list : Vec<&str>) -> Result
             Err(err) => {
                 error!("Error checking if deployment exists");
                 info!("Trace: {:?}", err);
-                return Err(err.to_string());
+                return Err(K8sError::api("Error checking if deployment exists", err));
             }
         };
         
@@ -698,7 +1271,7 @@ pub async fn delete_challenge(client : &Client, name_list : Vec<&str>) -> Result
             Err(err) => {
                 error!("Error checking if service exists");
                 info!("Trace: {:?}", err);
-                return Err(err.to_string());
+                return Err(K8sError::api("Error checking if service exists", err));
             }
         };
     
@@ -713,6 +1286,39 @@ pub async fn delete_challenge(client : &Client, name_list : Vec<&str>) -> Result
         } else {
             warn!("Skipping...service {name}-service does not exist"); 
         }
+
+        let flag_secret_name = flag_secret_name(name);
+        match secret_exists(client, &flag_secret_name).await {
+            Ok(true) => { delete_secret(client, &flag_secret_name).await?; },
+            Ok(false) => trace!("No flag secret {flag_secret_name} to delete"),
+            Err(err) => {
+                error!("Error checking if flag secret exists");
+                info!("Trace: {:?}", err);
+                return Err(K8sError::api("Error checking if flag secret exists", err));
+            }
+        }
+
+        let autoscaler_name = autoscaler_name(name);
+        match autoscaler_exists(client, &autoscaler_name).await {
+            Ok(true) => delete_autoscaler(client, &autoscaler_name).await?,
+            Ok(false) => trace!("No autoscaler {autoscaler_name} to delete"),
+            Err(err) => {
+                error!("Error checking if autoscaler exists");
+                info!("Trace: {:?}", err);
+                return Err(K8sError::api("Error checking if autoscaler exists", err));
+            }
+        }
+
+        let files_config_map_name = files_config_map_name(name);
+        match config_map_exists(client, &files_config_map_name).await {
+            Ok(true) => delete_config_map(client, &files_config_map_name).await?,
+            Ok(false) => trace!("No files config map {files_config_map_name} to delete"),
+            Err(err) => {
+                error!("Error checking if files config map exists");
+                info!("Trace: {:?}", err);
+                return Err(K8sError::api("Error checking if files config map exists", err));
+            }
+        }
     
         info!("Successfully deleted challenge {name}");
     }
@@ -721,6 +1327,50 @@ pub async fn delete_challenge(client : &Client, name_list : Vec<&str>) -> Result
 }
 
 
+/// Deletes every deploy target and team instance of a challenge, found through the challenge label
+/// 
+/// Unlike [`delete_challenge`][delete_challenge], this doesn't need to know the targets' resource names, so it also
+/// catches targets that were removed from the chall.yaml since they were deployed.
+/// 
+/// ## Returns
+/// - `Ok(Vec<String>)` - Resource names of everything that was deleted
+/// - `Err(K8sError)` - Error trace if error occurs
+pub async fn delete_chall_objects(client: &Client, chall_name: &str) -> Result<Vec<String>, K8sError> {
+    let deployments: Api<Deployment> = Api::default_namespaced(client.clone());
+    let services: Api<Service> = Api::default_namespaced(client.clone());
+    let selector = ListParams::default().labels(&format!("{CHALL_LABEL}={}", chall_label_value(chall_name)));
+
+    let deployment_names = match deployments.list_metadata(&selector).await {
+        Ok(list) => list.items.into_iter().filter_map(|deployment| deployment.metadata.name),
+        Err(err) => {
+            error!("Error listing deployments of {chall_name}");
+            debug!("Trace: {:?}", err);
+            return Err(K8sError::api(format!("Error listing deployments of {chall_name}"), err));
+        }
+    };
+    let mut names: Vec<String> = deployment_names.collect();
+
+    match services.list_metadata(&selector).await {
+        Ok(list) => names.extend(
+            list.items
+                .into_iter()
+                .filter_map(|service| service.metadata.name)
+                .filter_map(|name| name.strip_suffix("-service").map(str::to_string))
+        ),
+        Err(err) => {
+            error!("Error listing services of {chall_name}");
+            debug!("Trace: {:?}", err);
+            return Err(K8sError::api(format!("Error listing services of {chall_name}"), err));
+        }
+    }
+
+    names.sort();
+    names.dedup();
+
+    delete_challenge(client, names.iter().map(String::as_str).collect()).await?;
+    Ok(names)
+}
+
 // TODO - Reduce down to one function
 async fn deploy_exists(client: &Client, name : &str) -> Result<bool, Error> {
     let deployments: Api<Deployment> = Api::default_namespaced(client.clone());
@@ -737,13 +1387,23 @@ async fn secret_exists(client: &Client, name : &str) -> Result<bool, Error> {
     Ok(secrets.get_opt(name).await?.is_some())
 }
 
+async fn config_map_exists(client: &Client, name : &str) -> Result<bool, Error> {
+    let config_maps: Api<ConfigMap> = Api::default_namespaced(client.clone());
+    Ok(config_maps.get_opt(name).await?.is_some())
+}
+
+async fn autoscaler_exists(client: &Client, name : &str) -> Result<bool, Error> {
+    let autoscalers: Api<HorizontalPodAutoscaler> = Api::default_namespaced(client.clone());
+    Ok(autoscalers.get_opt(name).await?.is_some())
+}
+
 /// Helper function to simplify fetching the base challenge folder
 /// 
 /// If no `chall_folder_path` specified, defaults the path to the `CHALL_FOLDER` environment variable
 /// 
 /// ## Returns
 /// - `Ok(String)` - Path to the challenge folder
-/// - `Err(String)` - Error trace that occurred when trying to get a folder path
+/// - `Err(K8sError)` - Error trace that occurred when trying to get a folder path
 pub fn get_chall_folder(chall_folder_path: Option<&str>) -> String {
     if let Some(path) = chall_folder_path {
         path
@@ -752,3 +1412,58 @@ pub fn get_chall_folder(chall_folder_path: Option<&str>) -> String {
         chall_folder_default()
     }.to_string()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn valid_names_are_kept() {
+        assert_eq!(resource_name("pwn-heap", None, None), "pwn-heap");
+        assert_eq!(resource_name("pwn-heap", Some(Path::new("admin/bot")), None), "pwn-heap-admin-bot");
+    }
+
+    #[test]
+    fn targets_sharing_a_build_path_get_their_own_names() {
+        let root = None;
+        let dot = Some(Path::new("./"));
+        let admin = Some(Path::new("admin"));
+
+        assert!(config::shares_build_path("pwn-heap", root, [dot]));
+        assert!(config::shares_build_path("pwn-heap", admin, [Some(Path::new("./admin/"))]));
+        assert!(!config::shares_build_path("pwn-heap", admin, [root, dot]));
+
+        let web = resource_name("pwn-heap", root, Some("web"));
+        let nc = resource_name("pwn-heap", dot, Some("nc"));
+        assert_eq!(web, "pwn-heap-web");
+        assert_eq!(nc, "pwn-heap-nc");
+        assert_ne!(web, nc);
+    }
+
+    #[test]
+    fn sanitized_names_stay_unique() {
+        assert_ne!(chall_label_value("foo_bar"), chall_label_value("foo-bar"));
+        assert_ne!(chall_label_value("Foo"), chall_label_value("foo"));
+        assert!(chall_label_value("foo_bar").starts_with("foo-bar-"));
+    }
+
+    #[test]
+    fn long_names_are_truncated_with_a_hash() {
+        let long_a = "a".repeat(100);
+        let long_b = format!("{}b", "a".repeat(99));
+
+        for name in [resource_name(&long_a, None, None), resource_name(&long_b, None, None)] {
+            assert!(name.len() <= MAX_RESOURCE_NAME_LEN);
+        }
+        assert_ne!(resource_name(&long_a, None, None), resource_name(&long_b, None, None));
+    }
+
+    #[test]
+    fn port_names_have_no_repeated_hyphens() {
+        assert_eq!(config::port_name("Web Admin").as_deref(), Some("web-admin"));
+        assert_eq!(config::port_name("web -- admin").as_deref(), Some("web-admin"));
+        assert_eq!(config::port_name("__web__").as_deref(), Some("web"));
+        assert_eq!(config::port_name("--a--b--c--d--e--f--g--h--").as_deref(), Some("a-b-c-d-e-f-g-h"));
+        assert_eq!(config::port_name("-- 80 --"), None);
+    }
+}
@@ -0,0 +1,152 @@
+use std::collections::BTreeMap;
+
+use k8s_openapi::api::{
+    core::v1::Service,
+    apps::v1::Deployment,
+};
+use kube::{
+    Client,
+    Api,
+    api::{ ListParams, Patch, PatchParams },
+};
+use serde_json::json;
+
+use crate::config::{ ExposedPort, TargetConfig };
+use crate::{
+    create_autoscaler, create_deployment, create_files_config_map, create_flag_secret, create_service, exposed_ports_from_service,
+    CHALL_LABEL, INSTANCE_TEAM_LABEL, K8sError,
+};
+use crate::logging::*;
+
+/// A live [`Deployment`][Deployment] created by this crate for a deploy target
+///
+/// ## Fields
+/// - `chall_label` - Value of the deployment's challenge label (see [`chall_label_value`][crate::chall_label_value])
+/// - `image` - Image the deployment's container runs
+/// - `replicas` - Number of replicas the deployment is set to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LiveDeployment {
+    pub chall_label: String,
+    pub image: Option<String>,
+    pub replicas: i32,
+}
+
+/// The deploy target objects that currently exist in the cluster, keyed by resource name
+///
+/// Team instances (see [`crate::instance`]) are left out, they are short-lived by design.
+#[derive(Debug, Clone, Default)]
+pub struct LiveState {
+    pub deployments: BTreeMap<String, LiveDeployment>,
+    pub services: BTreeMap<String, String>,
+}
+
+fn managed_objects_selector() -> ListParams {
+    ListParams::default().labels(&format!("{CHALL_LABEL},!{INSTANCE_TEAM_LABEL}"))
+}
+
+fn chall_label_of(labels: Option<&BTreeMap<String, String>>) -> String {
+    labels
+        .and_then(|labels| labels.get(CHALL_LABEL))
+        .cloned()
+        .unwrap_or_default()
+}
+
+/// Lists every [`Deployment`][Deployment] and [`Service`][Service] carrying the challenge label
+///
+/// ## Returns
+/// - `Ok(LiveState)` - The deploy target objects in the cluster
+/// - `Err(K8sError)` - Error trace if error occurs
+pub async fn live_state(client: &Client) -> Result<LiveState, K8sError> {
+    let deployments: Api<Deployment> = Api::default_namespaced(client.clone());
+    let services: Api<Service> = Api::default_namespaced(client.clone());
+
+    let deployment_list = match deployments.list(&managed_objects_selector()).await {
+        Ok(list) => list,
+        Err(err) => {
+            error!("Error listing deployments");
+            debug!("Trace: {:?}", err);
+            return Err(K8sError::api("Error listing deployments", err));
+        }
+    };
+
+    let service_list = match services.list(&managed_objects_selector()).await {
+        Ok(list) => list,
+        Err(err) => {
+            error!("Error listing services");
+            debug!("Trace: {:?}", err);
+            return Err(K8sError::api("Error listing services", err));
+        }
+    };
+
+    let mut state = LiveState::default();
+
+    for deployment in deployment_list {
+        let Some(name) = deployment.metadata.name.clone() else { continue };
+
+        let spec = deployment.spec.as_ref();
+        let image = spec
+            .and_then(|spec| spec.template.spec.as_ref())
+            .and_then(|pod_spec| pod_spec.containers.first())
+            .and_then(|container| container.image.clone());
+        let replicas = spec.and_then(|spec| spec.replicas).unwrap_or(1);
+
+        state.deployments.insert(name, LiveDeployment {
+            chall_label: chall_label_of(deployment.metadata.labels.as_ref()),
+            image,
+            replicas,
+        });
+    }
+
+    for service in service_list {
+        let Some(name) = service.metadata.name.as_deref() else { continue };
+        let Some(resource_name) = name.strip_suffix("-service") else { continue };
+
+        state.services.insert(resource_name.to_string(), chall_label_of(service.metadata.labels.as_ref()));
+    }
+
+    Ok(state)
+}
+
+/// Sets the number of replicas of a [`Deployment`][Deployment] without recreating it
+pub async fn scale_deployment(client: &Client, name: &str, replicas: i32) -> Result<(), K8sError> {
+    let deployments: Api<Deployment> = Api::default_namespaced(client.clone());
+    let patch = json!({ "spec": { "replicas": replicas } });
+
+    match deployments.patch(name, &PatchParams::default(), &Patch::Merge(&patch)).await {
+        Ok(_) => {
+            info!("Scaled deployment {name} to {replicas} replica(s)");
+            Ok(())
+        },
+        Err(err) => {
+            error!("Error scaling deployment {name}");
+            debug!("Trace: {:?}", err);
+            Err(K8sError::api(format!("Error scaling deployment {name}"), err))
+        }
+    }
+}
+
+/// Recreates the [`Deployment`][Deployment] of a deploy target, along with its autoscaler and the flag secret and files it mounts
+///
+/// The target's [`Service`][Service] is left alone, so it keeps its node ports.
+pub async fn repair_workload(client: &Client, config: &TargetConfig) -> Result<(), K8sError> {
+    info!("Repairing deployment of {}", config.resource_name());
+
+    create_flag_secret(client, config).await?;
+    create_files_config_map(client, config).await?;
+    create_deployment(client, config).await?;
+    create_autoscaler(client, config).await?;
+
+    Ok(())
+}
+
+/// Recreates the [`Service`][Service] of a deploy target
+///
+/// ## Returns
+/// - `Ok(Vec<ExposedPort>)` - The target's exposed ports, which get new node ports
+/// - `Err(K8sError)` - Error trace if error occurs
+pub async fn repair_service(client: &Client, config: &TargetConfig) -> Result<Vec<ExposedPort>, K8sError> {
+    info!("Repairing service of {}", config.resource_name());
+
+    let service = create_service(client, config).await?;
+    exposed_ports_from_service(&service, config.cluster.as_deref())
+}
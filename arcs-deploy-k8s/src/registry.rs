@@ -0,0 +1,179 @@
+use std::collections::BTreeMap;
+
+use base64::{ Engine as _, engine::general_purpose::STANDARD as base64encoderator };
+use k8s_openapi::api::core::v1::Secret;
+use kube::{
+    Client,
+    Api,
+    api::{ Patch, PatchParams, PostParams },
+};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::env::{ reg_username, reg_password, reg_url, registries_file };
+use crate::K8sError;
+use crate::logging::*;
+
+/// Name of the pull secret of the registry set by `DOCKER_REGISTRY_URL`, the one challenge images are pushed to
+pub const DEFAULT_REGISTRY_SECRET: &str = "container-registry-credentials";
+
+/// Key of the Docker config in a `kubernetes.io/dockerconfigjson` secret
+const DOCKER_CONFIG_KEY: &str = ".dockerconfigjson";
+
+/// Credentials of a container registry that challenge images can be pulled from
+///
+/// Registries other than the default one are read from the YAML file at `DOCKER_REGISTRIES_FILE`, keyed by name:
+///
+/// ```yaml
+/// ghcr:
+///   url: ghcr.io
+///   username: arcs-bot
+///   password: ghp_...
+/// ```
+///
+/// Each registry gets its own pull secret, named `<name>-registry-credentials`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct RegistryCredentials {
+    pub url: String,
+    pub username: String,
+    pub password: String,
+}
+
+impl RegistryCredentials {
+    /// Contents of the registry's pull secret, in the format of `~/.docker/config.json`
+    fn docker_config(&self) -> String {
+        let auth = base64encoderator.encode(format!("{}:{}", self.username, self.password));
+
+        json!({
+            "auths": {
+                &self.url: {
+                    "username": self.username,
+                    "password": self.password,
+                    "auth": auth,
+                }
+            }
+        }).to_string()
+    }
+}
+
+/// Every registry that gets a pull secret, keyed by secret name
+///
+/// ## Returns
+/// - `Ok(BTreeMap<String, RegistryCredentials>)` - The default registry, and the ones in `DOCKER_REGISTRIES_FILE` if it is set
+/// - `Err(K8sError)` - The registries file couldn't be read
+fn registries() -> Result<BTreeMap<String, RegistryCredentials>, K8sError> {
+    let mut registries = BTreeMap::from([(
+        DEFAULT_REGISTRY_SECRET.to_string(),
+        RegistryCredentials {
+            url: reg_url().to_string(),
+            username: reg_username().to_string(),
+            password: reg_password().to_string(),
+        },
+    )]);
+
+    let Some(path) = registries_file() else {
+        return Ok(registries);
+    };
+
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| K8sError::Config(format!("Failed to read registries @ {path:?}: {e}")))?;
+    let extra: BTreeMap<String, RegistryCredentials> = serde_yaml::from_str(&text)
+        .map_err(|e| K8sError::Config(format!("Invalid registries @ {path:?}: {e}")))?;
+
+    registries.extend(extra.into_iter().map(|(name, credentials)| (format!("{name}-registry-credentials"), credentials)));
+    Ok(registries)
+}
+
+/// Names of the pull secrets every challenge `Deployment` references
+///
+/// Falls back to only the default registry if the registries file can't be read.
+pub fn registry_secret_names() -> Vec<String> {
+    match registries() {
+        Ok(registries) => registries.into_keys().collect(),
+        Err(e) => {
+            error!("{e}");
+            vec![DEFAULT_REGISTRY_SECRET.to_string()]
+        }
+    }
+}
+
+/// Creates the pull secret of a single registry, or patches it if its credentials changed
+///
+/// A secret that is already up to date is left alone, so deployments that are mid-pull keep working.
+async fn ensure_registry_secret(client: &Client, secret_name: &str, credentials: &RegistryCredentials) -> Result<(), K8sError> {
+    let secrets: Api<Secret> = Api::default_namespaced(client.clone());
+    let docker_config = credentials.docker_config();
+
+    let existing = match secrets.get_opt(secret_name).await {
+        Ok(existing) => existing,
+        Err(err) => {
+            error!("Error checking if registry secret {secret_name} exists");
+            debug!("Trace: {:?}", err);
+            return Err(K8sError::api(format!("Error checking if registry secret {secret_name} exists"), err));
+        }
+    };
+
+    let data = json!({ DOCKER_CONFIG_KEY: base64encoderator.encode(&docker_config) });
+
+    match existing {
+        Some(secret) => {
+            let current = secret.data
+                .as_ref()
+                .and_then(|data| data.get(DOCKER_CONFIG_KEY))
+                .map(|value| value.0.as_slice());
+
+            if current == Some(docker_config.as_bytes()) {
+                trace!("Registry secret {secret_name} is up to date");
+                return Ok(());
+            }
+
+            info!("Credentials of {} changed, patching registry secret {secret_name}", credentials.url);
+            let patch = json!({ "data": data });
+            match secrets.patch(secret_name, &PatchParams::default(), &Patch::Merge(&patch)).await {
+                Ok(_) => Ok(()),
+                Err(err) => {
+                    error!("Error patching registry secret {secret_name}");
+                    debug!("Trace: {:?}", err);
+                    Err(K8sError::api(format!("Error patching registry secret {secret_name}"), err))
+                }
+            }
+        },
+        None => {
+            info!("Creating registry secret {secret_name} for {}", credentials.url);
+            let secret: Secret = serde_json::from_value(json!({
+                "apiVersion": "v1",
+                "kind": "Secret",
+                "metadata": {
+                    "name": secret_name,
+                },
+                "data": data,
+                "type": "kubernetes.io/dockerconfigjson"
+            })).map_err(|err| {
+                error!("Error generating json for registry secret {secret_name}");
+                debug!("Trace: {:?}", err);
+                K8sError::object("registry secret", err)
+            })?;
+
+            match secrets.create(&PostParams::default(), &secret).await {
+                Ok(_) => Ok(()),
+                Err(err) => {
+                    error!("Error creating registry secret {secret_name}");
+                    debug!("Trace: {:?}", err);
+                    Err(K8sError::api(format!("Error creating registry secret {secret_name}"), err))
+                }
+            }
+        }
+    }
+}
+
+/// Makes sure the client's namespace has an up to date pull secret for every registry
+///
+/// ## Returns
+/// - `Ok(())` - Every pull secret exists and matches its registry's credentials
+/// - `Err(K8sError)` - Error trace if error occurs
+pub async fn ensure_registry_secrets(client: &Client) -> Result<(), K8sError> {
+    for (secret_name, credentials) in registries()? {
+        ensure_registry_secret(client, &secret_name, &credentials).await?;
+    }
+    Ok(())
+}
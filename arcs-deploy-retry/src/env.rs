@@ -0,0 +1,5 @@
+use arcs_env::*;
+
+env_var_opt!(RETRY_MAX_ATTEMPTS);
+env_var_opt!(RETRY_BASE_DELAY_MS -> RETRY_BASE_DELAY);
+env_var_opt!(RETRY_MAX_DELAY_MS -> RETRY_MAX_DELAY);
@@ -0,0 +1,363 @@
+use lazy_static::lazy_static;
+use rand::Rng;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::future::Future;
+use std::sync::{ Mutex, MutexGuard, PoisonError };
+use std::time::Duration;
+
+mod env;
+use env::{ retry_base_delay, retry_max_attempts, retry_max_delay };
+
+#[allow(unused_macros)]
+pub mod logging {
+    use arcs_logging_rs::with_target;
+    with_target! { "arcs-deploy" }
+}
+
+use logging::*;
+
+const DEFAULT_MAX_ATTEMPTS: u32 = 4;
+const DEFAULT_BASE_DELAY: Duration = Duration::from_millis(500);
+const DEFAULT_MAX_DELAY: Duration = Duration::from_secs(30);
+
+/// Errors that can tell whether the operation that caused them is worth trying again
+pub trait Retryable {
+    /// Whether trying again later might succeed, e.g. because the other end was briefly unreachable
+    fn is_retryable(&self) -> bool;
+}
+
+/// How often and how long to wait before retrying a failed operation
+///
+/// Set with `RETRY_MAX_ATTEMPTS`, `RETRY_BASE_DELAY_MS` and `RETRY_MAX_DELAY_MS`.
+///
+/// ## Fields
+/// - `max_attempts` - Number of attempts before giving up, including the first one
+/// - `base_delay` - Delay before the first retry, doubled on every retry after that
+/// - `max_delay` - Longest delay between two attempts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            base_delay: DEFAULT_BASE_DELAY,
+            max_delay: DEFAULT_MAX_DELAY,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// The policy set through the environment, falling back to the default for every variable that isn't set
+    pub fn from_env() -> Self {
+        let millis = |value: Option<&str>| value.and_then(|ms| ms.parse().ok()).map(Duration::from_millis);
+
+        Self {
+            max_attempts: retry_max_attempts().and_then(|attempts| attempts.parse().ok()).unwrap_or(DEFAULT_MAX_ATTEMPTS).max(1),
+            base_delay: millis(retry_base_delay()).unwrap_or(DEFAULT_BASE_DELAY),
+            max_delay: millis(retry_max_delay()).unwrap_or(DEFAULT_MAX_DELAY),
+        }
+    }
+
+    /// Delay before the given retry (starting at 1), exponential with "equal jitter"
+    ///
+    /// Half of the exponential delay is always waited, the other half is random so that operations that failed together
+    /// don't all retry at the same time.
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let exponential = self.base_delay
+            .saturating_mul(2u32.saturating_pow(retry.saturating_sub(1)))
+            .min(self.max_delay);
+
+        let half = exponential / 2;
+        let jitter = rand::thread_rng().gen_range(0..=half.as_millis() as u64);
+        half + Duration::from_millis(jitter)
+    }
+}
+
+/// How often the operations of a given kind were retried since the server started
+///
+/// ## Fields
+/// - `calls` - Number of times the operation was run
+/// - `retries` - Number of retries across every call
+/// - `recovered` - Calls that failed at first, but succeeded on a retry
+/// - `exhausted` - Calls that failed on every attempt (or with an error that isn't worth retrying)
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RetryStats {
+    pub calls: u64,
+    pub retries: u64,
+    pub recovered: u64,
+    pub exhausted: u64,
+}
+
+lazy_static! {
+    static ref RETRY_STATS: Mutex<BTreeMap<&'static str, RetryStats>> = Mutex::new(BTreeMap::new());
+}
+
+fn stats() -> MutexGuard<'static, BTreeMap<&'static str, RetryStats>> {
+    RETRY_STATS.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Retry counters of every kind of operation that was run through [`retry`][retry], keyed by operation
+pub fn retry_stats() -> BTreeMap<&'static str, RetryStats> {
+    stats().clone()
+}
+
+fn record_outcome(operation: &'static str, retries: u32, succeeded: bool) {
+    let mut stats = stats();
+    let entry = stats.entry(operation).or_default();
+
+    entry.calls += 1;
+    entry.retries += u64::from(retries);
+    if succeeded && retries > 0 {
+        entry.recovered += 1;
+    } else if !succeeded {
+        entry.exhausted += 1;
+    }
+}
+
+/// Runs an async operation, retrying it with the environment's [`RetryPolicy`][RetryPolicy] as long as it fails with a
+/// [`Retryable`][Retryable] error
+///
+/// ## Arguments
+/// - `operation` - Kind of operation, e.g. `registry push`, which retries are counted under
+/// - `subject` - What the operation is run on (an image, a key, ...), only used in the logs
+/// - `attempt` - Runs a single attempt of the operation
+///
+/// ## Returns
+/// - `Ok(T)` - Result of the first attempt that succeeded
+/// - `Err(E)` - Error of the last attempt
+pub async fn retry<T, E, F, Fut>(operation: &'static str, subject: impl Display, attempt: F) -> Result<T, E>
+where
+    E: Retryable + Display,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+{
+    retry_if(operation, subject, E::is_retryable, attempt).await
+}
+
+/// Same as [`retry`][retry], for errors that are classified by `is_retryable` instead of [`Retryable`][Retryable]
+pub async fn retry_if<T, E, F, Fut>(
+    operation: &'static str,
+    subject: impl Display,
+    is_retryable: impl Fn(&E) -> bool,
+    attempt: F,
+) -> Result<T, E>
+where
+    E: Display,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+{
+    retry_with_policy(RetryPolicy::from_env(), operation, subject, is_retryable, attempt).await
+}
+
+/// Same as [`retry_if`][retry_if], with the given policy instead of the environment's
+async fn retry_with_policy<T, E, F, Fut>(
+    policy: RetryPolicy,
+    operation: &'static str,
+    subject: impl Display,
+    is_retryable: impl Fn(&E) -> bool,
+    mut attempt: F,
+) -> Result<T, E>
+where
+    E: Display,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+{
+    let mut retries = 0;
+
+    loop {
+        match attempt().await {
+            Ok(value) => {
+                if retries > 0 {
+                    info!("{operation} of {subject} succeeded after {retries} retr{}", if retries == 1 { "y" } else { "ies" });
+                }
+                record_outcome(operation, retries, true);
+                return Ok(value);
+            },
+            Err(err) if retries + 1 < policy.max_attempts && is_retryable(&err) => {
+                retries += 1;
+                let delay = policy.backoff(retries);
+                warn!("{operation} of {subject} failed (attempt {retries}/{}), retrying in {delay:?}: {err}", policy.max_attempts);
+                tokio::time::sleep(delay).await;
+            },
+            Err(err) => {
+                if retries > 0 {
+                    error!("{operation} of {subject} failed after {} attempt(s): {err}", retries + 1);
+                }
+                record_outcome(operation, retries, false);
+                return Err(err);
+            },
+        }
+    }
+}
+
+/// Same as [`retry_if`][retry_if], for blocking operations
+///
+/// The thread is put to sleep between attempts, so this should only be used off of the async runtime's worker threads or
+/// where blocking is already accepted (e.g. git operations).
+pub fn retry_blocking_if<T, E>(
+    operation: &'static str,
+    subject: impl Display,
+    is_retryable: impl Fn(&E) -> bool,
+    attempt: impl FnMut() -> Result<T, E>,
+) -> Result<T, E>
+where
+    E: Display,
+{
+    retry_blocking_with_policy(RetryPolicy::from_env(), operation, subject, is_retryable, attempt)
+}
+
+/// Same as [`retry_blocking_if`][retry_blocking_if], with the given policy instead of the environment's
+fn retry_blocking_with_policy<T, E>(
+    policy: RetryPolicy,
+    operation: &'static str,
+    subject: impl Display,
+    is_retryable: impl Fn(&E) -> bool,
+    mut attempt: impl FnMut() -> Result<T, E>,
+) -> Result<T, E>
+where
+    E: Display,
+{
+    let mut retries = 0;
+
+    loop {
+        match attempt() {
+            Ok(value) => {
+                if retries > 0 {
+                    info!("{operation} of {subject} succeeded after {retries} retr{}", if retries == 1 { "y" } else { "ies" });
+                }
+                record_outcome(operation, retries, true);
+                return Ok(value);
+            },
+            Err(err) if retries + 1 < policy.max_attempts && is_retryable(&err) => {
+                retries += 1;
+                let delay = policy.backoff(retries);
+                warn!("{operation} of {subject} failed (attempt {retries}/{}), retrying in {delay:?}: {err}", policy.max_attempts);
+                std::thread::sleep(delay);
+            },
+            Err(err) => {
+                if retries > 0 {
+                    error!("{operation} of {subject} failed after {} attempt(s): {err}", retries + 1);
+                }
+                record_outcome(operation, retries, false);
+                return Err(err);
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Policy that retries right away, so tests don't wait
+    fn instant_policy(max_attempts: u32) -> RetryPolicy {
+        RetryPolicy { max_attempts, base_delay: Duration::ZERO, max_delay: Duration::ZERO }
+    }
+
+    fn always_retryable(_: &String) -> bool {
+        true
+    }
+
+    #[test]
+    fn backoff_stays_between_half_and_the_full_exponential_delay() {
+        let policy = RetryPolicy { max_attempts: 10, base_delay: Duration::from_millis(100), max_delay: Duration::from_secs(1) };
+
+        for retry in 1..=10 {
+            let exponential = Duration::from_millis(100 * 2u64.pow(retry - 1)).min(Duration::from_secs(1));
+            for _ in 0..50 {
+                let delay = policy.backoff(retry);
+                assert!(delay >= exponential / 2, "retry {retry}: {delay:?} is shorter than half of {exponential:?}");
+                assert!(delay <= exponential, "retry {retry}: {delay:?} is longer than {exponential:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn backoff_is_capped_at_the_max_delay() {
+        let policy = RetryPolicy { max_attempts: 10, base_delay: Duration::from_millis(500), max_delay: Duration::from_secs(30) };
+
+        for retry in [40, 1000, u32::MAX] {
+            assert!(policy.backoff(retry) <= Duration::from_secs(30));
+        }
+        assert_eq!(instant_policy(3).backoff(5), Duration::ZERO);
+    }
+
+    #[tokio::test]
+    async fn retries_until_max_attempts() {
+        let mut attempts = 0;
+        let result: Result<(), String> = retry_with_policy(instant_policy(3), "test exhausted", "subject", always_retryable, || {
+            attempts += 1;
+            async { Err("unreachable".to_string()) }
+        }).await;
+
+        assert!(result.is_err());
+        assert_eq!(attempts, 3);
+
+        let stats = retry_stats().remove("test exhausted").unwrap();
+        assert_eq!((stats.calls, stats.retries, stats.recovered, stats.exhausted), (1, 2, 0, 1));
+    }
+
+    #[tokio::test]
+    async fn stops_on_errors_that_arent_retryable() {
+        let mut attempts = 0;
+        let result: Result<(), String> = retry_with_policy(instant_policy(5), "test permanent", "subject", |_: &String| false, || {
+            attempts += 1;
+            async { Err("denied".to_string()) }
+        }).await;
+
+        assert!(result.is_err());
+        assert_eq!(attempts, 1);
+
+        let stats = retry_stats().remove("test permanent").unwrap();
+        assert_eq!((stats.calls, stats.retries, stats.recovered, stats.exhausted), (1, 0, 0, 1));
+    }
+
+    #[tokio::test]
+    async fn counts_a_success_on_a_retry_as_recovered() {
+        let mut attempts = 0;
+        let result = retry_with_policy(instant_policy(5), "test recovered", "subject", always_retryable, || {
+            attempts += 1;
+            let attempt = attempts;
+            async move { if attempt < 3 { Err("unreachable".to_string()) } else { Ok(attempt) } }
+        }).await;
+
+        assert_eq!(result, Ok(3));
+
+        let stats = retry_stats().remove("test recovered").unwrap();
+        assert_eq!((stats.calls, stats.retries, stats.recovered, stats.exhausted), (1, 2, 1, 0));
+    }
+
+    #[test]
+    fn a_single_attempt_is_never_retried() {
+        let mut attempts = 0;
+        let result: Result<(), String> = retry_blocking_with_policy(instant_policy(1), "test single", "subject", always_retryable, || {
+            attempts += 1;
+            Err("unreachable".to_string())
+        });
+
+        assert!(result.is_err());
+        assert_eq!(attempts, 1);
+    }
+
+    #[test]
+    fn blocking_retries_count_the_same_way() {
+        let mut attempts = 0;
+        let result = retry_blocking_with_policy(instant_policy(4), "test blocking", "subject", always_retryable, || {
+            attempts += 1;
+            if attempts < 4 { Err("unreachable".to_string()) } else { Ok(()) }
+        });
+
+        assert_eq!(result, Ok(()));
+        assert_eq!(attempts, 4);
+
+        let stats = retry_stats().remove("test blocking").unwrap();
+        assert_eq!((stats.calls, stats.retries, stats.recovered, stats.exhausted), (1, 3, 1, 0));
+    }
+}
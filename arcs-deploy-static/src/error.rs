@@ -0,0 +1,52 @@
+use std::path::PathBuf;
+
+use arcs_docker::DockerError;
+use arcs_retry::Retryable;
+use thiserror::Error;
+use yaml::File;
+
+/// Errors that can occur while uploading or deleting a challenge's static files
+///
+/// ## Variants
+/// - `S3Client` - The S3 client couldn't be created from the environment
+/// - `ChallYamlMissing` - The challenge has no chall.yaml
+/// - `ChallYamlInvalid` - The challenge's chall.yaml failed to parse
+/// - `ContainerFile` - A file couldn't be copied out of the challenge's container
+/// - `Upload` - Some files couldn't be uploaded, the rest were
+/// - `Bucket` - A request to the bucket failed
+/// - `Rejected` - The bucket refused a request
+#[derive(Debug, Error)]
+pub enum StaticError {
+    #[error("Failed to create S3 client")]
+    S3Client(#[source] s3::error::S3Error),
+    #[error("No chall.yaml for {0}")]
+    ChallYamlMissing(String),
+    #[error("Invalid chall.yaml for {chall_name}: {message}")]
+    ChallYamlInvalid { chall_name: String, message: String },
+    #[error("Failed to copy {path:?} out of the container of {chall_name}")]
+    ContainerFile { chall_name: String, path: PathBuf, #[source] source: DockerError },
+    #[error("Failed to upload {} file(s): {0:?}", .0.len())]
+    Upload(Vec<File>),
+    #[error("Failed to {action} {key}")]
+    Bucket { action: &'static str, key: String, #[source] source: s3::error::S3Error },
+    #[error("Bucket refused to {action} {key} (status {status})")]
+    Rejected { action: &'static str, key: String, status: u16 },
+}
+
+impl Retryable for StaticError {
+    /// The bucket couldn't be reached or is briefly overloaded, or the container a file is copied out of was unreachable
+    fn is_retryable(&self) -> bool {
+        match self {
+            Self::Bucket { .. } => true,
+            Self::Rejected { status, .. } => *status == 429 || *status >= 500,
+            Self::ContainerFile { source, .. } => source.is_retryable(),
+            _ => false,
+        }
+    }
+}
+
+impl From<StaticError> for String {
+    fn from(err: StaticError) -> Self {
+        err.to_string()
+    }
+}
@@ -13,6 +13,10 @@ use logging::*;
 pub mod env;
 use env::*;
 
+mod error;
+pub use error::StaticError;
+
+use arcs_retry::retry;
 use yaml::{YamlShape, YamlVerifyError, File};
 use reqwest::header::{HeaderName, HeaderMap};
 use s3::Bucket;
@@ -35,21 +39,19 @@ pub async fn fetch_chall_yaml(chall_folder_name: &str) -> Option<Result<YamlShap
     Some(YamlShape::try_from_str(&yaml_data, &Default::default(), Some(&folder_path)))
 }
 
-pub async fn get_container_file_data(name: &str, file: &File, docker: &Docker) -> Option<Vec<u8>> {
+pub async fn get_container_file_data(name: &str, file: &File, docker: &Docker) -> Result<Vec<u8>, StaticError> {
     if file.container().is_none() {
-        return Some(file.data_vec_cloned().unwrap_or_default())
+        return Ok(file.data_vec_cloned().unwrap_or_default())
     };
             
     info!("Deploying files in container for challenge: {}", name);
-    warn!("At the moment, this is currently NOT functioning if running a multi-server cluster.");
-    warn!("To fix, build the container on the same server as this one and redeploy.");
 
     let file_fetch_result = fetch_container_file(docker, name, file.path()).await;
     match file_fetch_result {
-        Ok(file_data) => Some(file_data),
+        Ok(file_data) => Ok(file_data),
         Err(e) => {
             error!("Failed to fetch file from container {name}: {:#?}", e);
-            None
+            Err(StaticError::ContainerFile { chall_name: name.to_string(), path: file.path().to_path_buf(), source: e })
         }
     }
 }
@@ -64,14 +66,14 @@ pub fn create_s3_client() -> Result<s3::Bucket, s3::error::S3Error> {
 }
 
 // TODO --> if it is not relative (if its a url), add new function flow
-pub async fn deploy_static_files(docker: &Docker, chall_name: &str) -> Result<Vec<File>,  Vec<File>> {
+pub async fn deploy_static_files(docker: &Docker, chall_name: &str) -> Result<Vec<File>, StaticError> {
     info!("Deploying static challenge: {}", chall_name);
 
     let bucket = match create_s3_client() {
         Ok(bucket) => bucket,
         Err(e) => {
             error!("Failed to create S3 client: {:#?}", e);
-            return Err(vec![]);
+            return Err(StaticError::S3Client(e));
         }
     };
 
@@ -81,13 +83,13 @@ pub async fn deploy_static_files(docker: &Docker, chall_name: &str) -> Result<Ve
                     Ok(yaml) => yaml,
                     Err(e) => {
                         error!("Failed to parse chall.yaml for {}: {:#?}", chall_name, e);
-                        return Err(vec![]);
+                        return Err(StaticError::ChallYamlInvalid { chall_name: chall_name.to_string(), message: format!("{e:?}") });
                     },
                 }
             },
         None => {
             error!("Failed to find chall.yaml for challenge: {}", chall_name);
-            return Err(vec![]);
+            return Err(StaticError::ChallYamlMissing(chall_name.to_string()));
         }
     };
 
@@ -116,9 +118,7 @@ pub async fn deploy_static_files(docker: &Docker, chall_name: &str) -> Result<Ve
 
         let s3_path = format!("/{}/{}", chall_name.trim_matches('/'), sub_chall_file);
 
-        let Some(filedata): Option<Vec<u8>> = get_container_file_data(chall_name, &file, docker).await else {
-            return Err(vec![]);
-        };
+        let filedata = get_container_file_data(chall_name, &file, docker).await?;
 
         let mut custom_headers = HeaderMap::new();
         custom_headers.insert(
@@ -126,12 +126,19 @@ pub async fn deploy_static_files(docker: &Docker, chall_name: &str) -> Result<Ve
             "public-read".parse().unwrap(),
         );
 
-        let res = bucket.with_extra_headers(custom_headers).put_object(s3_path, &filedata).await;
-        
+        let res = retry("s3 upload", &s3_path, || async {
+            let res = bucket.with_extra_headers(custom_headers.clone()).put_object(&s3_path, &filedata).await
+                .map_err(|source| StaticError::Bucket { action: "upload", key: s3_path.clone(), source })?;
+
+            match res.status_code() {
+                200 => Ok(()),
+                status => Err(StaticError::Rejected { action: "upload", key: s3_path.clone(), status }),
+            }
+        }).await;
 
         match res {
-            Ok(res) if (res.status_code() == 200) => success.push(file),
-            error => {
+            Ok(()) => success.push(file),
+            Err(error) => {
                 error!("Failed to upload file: {:#?}", error);
                 warn!("Ensure CDN auth token is valid.");
                 failure.push(file)
@@ -142,6 +149,46 @@ pub async fn deploy_static_files(docker: &Docker, chall_name: &str) -> Result<Ve
     if failure.is_empty() {
         Ok(success)
     } else {
-        Err(failure)
+        Err(StaticError::Upload(failure))
+    }
+}
+
+/// Deletes every file uploaded for a challenge, i.e. every object under the challenge's prefix in the bucket
+/// 
+/// ## Returns
+/// - `Ok(usize)` - Number of files deleted
+/// - `Err(StaticError)` - Error trace if the bucket couldn't be listed or a file couldn't be deleted
+pub async fn delete_static_files(chall_name: &str) -> Result<usize, StaticError> {
+    info!("Deleting static files of challenge: {}", chall_name);
+
+    let bucket = create_s3_client().map_err(|e| {
+        error!("Failed to create S3 client: {:#?}", e);
+        StaticError::S3Client(e)
+    })?;
+
+    let prefix = format!("{}/", chall_name.trim_matches('/'));
+    let listing = bucket.list(prefix.clone(), None).await.map_err(|e| {
+        error!("Failed to list files under {prefix}: {:#?}", e);
+        StaticError::Bucket { action: "list", key: prefix.clone(), source: e }
+    })?;
+
+    let mut deleted = 0;
+    for object in listing.into_iter().flat_map(|page| page.contents) {
+        match bucket.delete_object(&object.key).await {
+            Ok(res) if (200..300).contains(&res.status_code()) => {
+                trace!("Deleted {}", object.key);
+                deleted += 1;
+            },
+            Ok(res) => {
+                error!("Failed to delete file {}: status {}", object.key, res.status_code());
+                return Err(StaticError::Rejected { action: "delete", key: object.key, status: res.status_code() });
+            },
+            Err(e) => {
+                error!("Failed to delete file {}: {:#?}", object.key, e);
+                return Err(StaticError::Bucket { action: "delete", key: object.key, source: e });
+            }
+        }
     }
+
+    Ok(deleted)
 }
@@ -0,0 +1,210 @@
+use lazy_static::lazy_static;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::{ Mutex, MutexGuard, PoisonError };
+use std::time::{ SystemTime, UNIX_EPOCH };
+use serde::{ Deserialize, Serialize };
+
+use arcs_k8s::config::{ ExposedPort, TargetConfig, target_key };
+
+use crate::env::deploy_state_dir;
+use crate::logging::*;
+
+const DEFAULT_DEPLOY_STATE_DIR: &str = "./deploy-state";
+const DEPLOY_RECORDS_FILE: &str = "deploy-records.json";
+
+/// What a single deploy target of a challenge was deployed as
+///
+/// ## Fields
+/// - `target` - Key of the target in the `deploy` section of the chall.yaml (see [`target_key`][target_key])
+/// - `resource_name` - Name of the target's Kubernetes resources
+/// - `image` - Image the target's containers run, pinned to its digest if known
+/// - `image_digest` - Digest of the image that was pulled for the deployment, if the daemon reported one
+/// - `replicas` - Number of replicas the target was deployed with (or scaled to)
+/// - `autoscaled` - Whether the target's replicas are managed by an autoscaler
+/// - `ports` - Ports the target was exposed on
+/// - `cluster` - Cluster profile the target was deployed to, the default cluster if not set
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TargetRecord {
+    pub target: String,
+    pub resource_name: String,
+    pub image: String,
+    #[serde(default)]
+    pub image_digest: Option<String>,
+    pub replicas: i32,
+    #[serde(default)]
+    pub autoscaled: bool,
+    pub ports: Vec<ExposedPort>,
+    #[serde(default)]
+    pub cluster: Option<String>,
+}
+
+impl TargetRecord {
+    pub fn new(config: &TargetConfig, ports: Vec<ExposedPort>) -> Self {
+        Self {
+            target: target_key(config.target_type).to_string(),
+            resource_name: config.resource_name(),
+            image: config.image_reference(),
+            image_digest: config.image_digest.clone(),
+            replicas: config.initial_replicas(),
+            autoscaled: config.autoscale.is_some(),
+            ports,
+            cluster: config.cluster.clone(),
+        }
+    }
+}
+
+/// The last successful deployment of a challenge
+///
+/// ## Fields
+/// - `chall_name` - Name of the challenge (folder)
+/// - `targets` - What every deploy target of the challenge was deployed as
+/// - `deployed_at` - Unix timestamp of the deployment
+/// - `deleting` - Whether the challenge is being deleted, so its targets must not be repaired (see [`mark_deleting`][mark_deleting])
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeployRecord {
+    pub chall_name: String,
+    pub targets: Vec<TargetRecord>,
+    pub deployed_at: u64,
+    #[serde(default)]
+    pub deleting: bool,
+}
+
+impl DeployRecord {
+    pub fn new(chall_name: &str, targets: Vec<TargetRecord>) -> Self {
+        let deployed_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or_default();
+
+        Self { chall_name: chall_name.to_string(), targets, deployed_at, deleting: false }
+    }
+}
+
+type DeployRecords = BTreeMap<String, DeployRecord>;
+
+lazy_static! {
+    /// Serializes reads and writes of the records file
+    static ref RECORDS_LOCK: Mutex<()> = Mutex::new(());
+}
+
+fn records_lock() -> MutexGuard<'static, ()> {
+    RECORDS_LOCK.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Location of a file in the folder set by `DEPLOY_STATE_DIR`, where everything the server persists is kept
+pub(crate) fn state_file_path(file_name: &str) -> PathBuf {
+    PathBuf::from(deploy_state_dir().unwrap_or(DEFAULT_DEPLOY_STATE_DIR)).join(file_name)
+}
+
+/// Location of the records file, in the folder set by `DEPLOY_STATE_DIR`
+fn records_path() -> PathBuf {
+    state_file_path(DEPLOY_RECORDS_FILE)
+}
+
+fn read_records() -> Result<DeployRecords, String> {
+    let path = records_path();
+
+    let text = match std::fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(DeployRecords::new()),
+        Err(e) => return Err(format!("Failed to read deploy records @ {path:?}: {e}")),
+    };
+
+    serde_json::from_str(&text).map_err(|e| format!("Failed to parse deploy records @ {path:?}: {e}"))
+}
+
+fn write_records(records: &DeployRecords) -> Result<(), String> {
+    let path = records_path();
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| format!("Failed to create deploy state folder {parent:?}: {e}"))?;
+    }
+
+    let text = serde_json::to_string_pretty(records).map_err(|e| format!("Failed to serialize deploy records: {e}"))?;
+
+    // Write to a temporary file first so a crash mid-write can't leave a truncated records file behind
+    let tmp_path = path.with_extension("json.tmp");
+    std::fs::write(&tmp_path, text).map_err(|e| format!("Failed to write deploy records @ {tmp_path:?}: {e}"))?;
+    std::fs::rename(&tmp_path, &path).map_err(|e| format!("Failed to replace deploy records @ {path:?}: {e}"))
+}
+
+/// Loads every persisted deploy record, keyed by challenge name
+pub fn load_deploy_records() -> Result<DeployRecords, String> {
+    let _lock = records_lock();
+    read_records()
+}
+
+/// Persists the record of a challenge's deployment, replacing the previous one
+pub fn save_deploy_record(record: DeployRecord) -> Result<(), String> {
+    let _lock = records_lock();
+
+    let mut records = read_records()?;
+    trace!("Saving deploy record for {}", record.chall_name);
+    records.insert(record.chall_name.clone(), record);
+
+    write_records(&records)
+}
+
+/// Applies `update` to the record of a single deploy target and persists the result
+fn update_target_record(chall_name: &str, resource_name: &str, update: impl FnOnce(&mut TargetRecord)) -> Result<(), String> {
+    let _lock = records_lock();
+
+    let mut records = read_records()?;
+    let Some(target) = records
+        .get_mut(chall_name)
+        .and_then(|record| record.targets.iter_mut().find(|target| target.resource_name == resource_name))
+    else {
+        return Err(format!("No deploy record for {resource_name} of {chall_name}"));
+    };
+    update(target);
+
+    write_records(&records)
+}
+
+/// Updates the recorded ports of a single deploy target, e.g. after its `Service` was recreated
+pub fn update_target_ports(chall_name: &str, resource_name: &str, ports: Vec<ExposedPort>) -> Result<(), String> {
+    update_target_record(chall_name, resource_name, |target| target.ports = ports)
+}
+
+/// Updates the recorded replicas of a single deploy target after it was scaled by hand, along with whether the cluster
+/// autoscales it
+pub fn update_target_replicas(chall_name: &str, resource_name: &str, replicas: i32, autoscaled: bool) -> Result<(), String> {
+    update_target_record(chall_name, resource_name, |target| {
+        target.replicas = replicas;
+        target.autoscaled = autoscaled;
+    })
+}
+
+/// Marks the record of a challenge as being deleted before any of its targets are torn down, returning it if there is one
+///
+/// The mark is persisted, so the reconciler leaves the challenge alone even if the server restarts mid-deletion, until
+/// the record is removed or replaced by a new deployment.
+pub fn mark_deleting(chall_name: &str) -> Result<Option<DeployRecord>, String> {
+    let _lock = records_lock();
+
+    let mut records = read_records()?;
+    let Some(record) = records.get_mut(chall_name) else {
+        return Ok(None);
+    };
+    record.deleting = true;
+    let record = record.clone();
+    trace!("Marked deploy record for {chall_name} as deleting");
+
+    write_records(&records)?;
+    Ok(Some(record))
+}
+
+/// Removes the record of a challenge's deployment, returning it if there was one
+pub fn remove_deploy_record(chall_name: &str) -> Result<Option<DeployRecord>, String> {
+    let _lock = records_lock();
+
+    let mut records = read_records()?;
+    let Some(record) = records.remove(chall_name) else {
+        return Ok(None);
+    };
+    trace!("Removed deploy record for {chall_name}");
+
+    write_records(&records)?;
+    Ok(Some(record))
+}
@@ -0,0 +1,179 @@
+use std::fs::{ read_dir, read_to_string };
+use std::path::{ Path, PathBuf };
+
+use serde::Serialize;
+
+use arcs_k8s::config::{ target_build_path, target_key };
+
+use crate::server::utils::yaml::parse_chall_yaml;
+use crate::logging::*;
+
+/// File every challenge folder is recognized by
+const CHALL_YAML: &str = "chall.yaml";
+
+/// Deploy target of an indexed challenge
+///
+/// ## Fields
+/// - `target_type` - Key of the target in the `deploy` section of the chall.yaml
+/// - `build_path` - Subfolder of the challenge the target's image is built from, the challenge folder itself if not set
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct IndexedTarget {
+    pub target_type: &'static str,
+    pub build_path: Option<PathBuf>,
+}
+
+/// File of an indexed challenge that is handed out to players
+///
+/// ## Fields
+/// - `path` - Path of the file, relative to the challenge folder (or inside the container)
+/// - `in_container` - Whether the file is copied out of the challenge's image rather than the challenge folder
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct IndexedFile {
+    pub path: PathBuf,
+    pub in_container: bool,
+}
+
+/// Challenge found in the challenge repo
+///
+/// ## Fields
+/// - `folder` - Folder of the challenge relative to the repo, what deployments refer to the challenge by
+/// - `name` - Display name of the challenge, from its chall.yaml
+/// - `path` - Full path of the challenge folder
+/// - `categories` - Categories of the challenge
+/// - `targets` - Deploy targets of the challenge, empty for static challenges
+/// - `files` - Files handed out to players
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct IndexedChall {
+    pub folder: String,
+    pub name: String,
+    pub path: PathBuf,
+    pub categories: Vec<String>,
+    pub targets: Vec<IndexedTarget>,
+    pub files: Vec<IndexedFile>,
+}
+
+/// Folder with a chall.yaml that couldn't be read or parsed
+///
+/// ## Fields
+/// - `folder` - Folder of the challenge relative to the repo
+/// - `error` - Why its chall.yaml couldn't be read
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct InvalidChall {
+    pub folder: String,
+    pub error: String,
+}
+
+/// Every challenge in the challenge repo, sorted by folder
+///
+/// ## Fields
+/// - `challs` - Challenges whose chall.yaml parsed
+/// - `invalid` - Folders with a chall.yaml that didn't
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ChallIndex {
+    pub challs: Vec<IndexedChall>,
+    pub invalid: Vec<InvalidChall>,
+}
+
+impl ChallIndex {
+    /// Folder of every challenge with a chall.yaml, whether it parsed or not
+    pub fn folders(&self) -> Vec<String> {
+        let mut folders: Vec<String> = self.challs
+            .iter()
+            .map(|chall| chall.folder.clone())
+            .chain(self.invalid.iter().map(|invalid| invalid.folder.clone()))
+            .collect();
+
+        folders.sort();
+        folders
+    }
+
+    /// Challenge folder and build path of every image built by the challenges' deploy targets, without duplicates
+    pub fn images(&self) -> Vec<(String, Option<PathBuf>)> {
+        let mut images: Vec<(String, Option<PathBuf>)> = self.challs
+            .iter()
+            .flat_map(|chall| chall.targets.iter().map(|target| (chall.folder.clone(), target.build_path.clone())))
+            .collect();
+
+        images.sort();
+        images.dedup();
+        images
+    }
+}
+
+/// Reads the chall.yaml of a challenge folder into its index entry
+fn index_chall(folder: String, path: &Path) -> Result<IndexedChall, String> {
+    let yaml_data = read_to_string(path.join(CHALL_YAML)).map_err(|e| format!("Failed to read {CHALL_YAML}: {e}"))?;
+    // Parsed like a deployment would, so a chall.yaml whose deploy options are invalid is listed as invalid too
+    let yaml = parse_chall_yaml(&yaml_data, Some(path))?.shape;
+
+    let targets = yaml.deploy()
+        .map(|deploy_options| {
+            deploy_options.clone()
+                .into_iter()
+                .map(|(target, target_type)| IndexedTarget {
+                    target_type: target_key(target_type),
+                    build_path: target_build_path(&target),
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let files = yaml.file_iter()
+        .into_iter()
+        .flatten()
+        .map(|file| IndexedFile { path: file.path().to_path_buf(), in_container: file.container().is_some() })
+        .collect();
+
+    Ok(IndexedChall {
+        folder,
+        name: yaml.chall_name().to_string(),
+        path: path.to_path_buf(),
+        categories: yaml.category_str_iter().map(str::to_string).collect(),
+        targets,
+        files,
+    })
+}
+
+/// Walks the challenge repo at `root` and indexes every folder (at any depth) that contains a chall.yaml
+///
+/// Hidden folders (`.git`, ...) are skipped. A chall.yaml that doesn't parse doesn't stop the walk, the folder is listed
+/// in [`ChallIndex::invalid`][ChallIndex] instead.
+///
+/// ## Returns
+/// - `Ok(ChallIndex)` - Every challenge found
+/// - `Err(std::io::Error)` - A folder of the repo couldn't be read
+pub fn discover_challs(root: &Path) -> std::io::Result<ChallIndex> {
+    let mut index = ChallIndex::default();
+    let mut folders = vec![PathBuf::new()];
+
+    while let Some(folder) = folders.pop() {
+        let path = root.join(&folder);
+
+        for entry in read_dir(&path)? {
+            let entry = entry?;
+            let hidden = entry.file_name().to_string_lossy().starts_with('.');
+            if entry.file_type()?.is_dir() && !hidden {
+                folders.push(folder.join(entry.file_name()));
+            }
+        }
+
+        if folder.as_os_str().is_empty() || !path.join(CHALL_YAML).is_file() {
+            continue;
+        }
+
+        let folder = folder.to_string_lossy().to_string();
+        match index_chall(folder.clone(), &path) {
+            Ok(chall) => index.challs.push(chall),
+            Err(error) => {
+                warn!("Skipping challenge {folder}, its {CHALL_YAML} is invalid: {error}");
+                index.invalid.push(InvalidChall { folder, error });
+            },
+        }
+    }
+
+    index.challs.sort_by(|a, b| a.folder.cmp(&b.folder));
+    index.invalid.sort_by(|a, b| a.folder.cmp(&b.folder));
+    debug!("Found {} challenge(s), {} with an invalid {CHALL_YAML}", index.challs.len() + index.invalid.len(), index.invalid.len());
+
+    Ok(index)
+}
@@ -16,6 +16,23 @@ env_var_req!(GIT_INTEGRATION_EMAIL -> GIT_EMAIL);
 env_var_req!(GIT_DEPLOY_BRANCH_NAME -> GIT_BRANCH);
 env_var_req!(GIT_SSH_KEY_PATH -> GIT_KEY_PATH);
 
+env_var_opt!(INSTANCE_TTL_SECONDS -> INSTANCE_TTL);
+env_var_opt!(MAX_INSTANCES_PER_TEAM);
+env_var_opt!(MAX_INSTANCES_TOTAL);
+
+env_var_opt!(DEPLOY_STATE_DIR);
+env_var_opt!(RECONCILE_INTERVAL_SECONDS -> RECONCILE_INTERVAL);
+env_var_opt!(RECONCILE_SELF_HEAL);
+
+env_var_opt!(UPTIME_CHECK_INTERVAL_SECONDS -> UPTIME_CHECK_INTERVAL);
+
+env_var_opt!(OUTBOX_RETRY_INTERVAL_SECONDS -> OUTBOX_RETRY_INTERVAL);
+
+env_var_opt!(MAX_PARALLEL_BUILDS);
+
+env_var_opt!(GC_INTERVAL_SECONDS -> GC_INTERVAL);
+env_var_opt!(GC_DRY_RUN);
+
 assert_req_env!(check_env_vars:
     PORT,
     DEPLOY_TOKEN, WEBHOOK_TOKEN, WEBHOOK_ADDRESS,
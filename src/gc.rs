@@ -0,0 +1,320 @@
+use lazy_static::lazy_static;
+use std::collections::BTreeSet;
+use std::fmt::Display;
+use std::path::Path;
+use std::time::{ Duration, SystemTime, UNIX_EPOCH };
+use serde::Serialize;
+
+use arcs_docker::{ BuilderBackend, builder_backend, dangling_images, docker_login, orphaned_helper_containers, registry_images, remove_helper_container, remove_image };
+use arcs_k8s::{ chall_label_value, client_for_cluster, create_client, delete_challenge };
+use arcs_k8s::clusters::cluster_profiles;
+use arcs_k8s::reconcile::live_state;
+use arcs_static::env::chall_folder_default;
+use kube::Client;
+use shiplift::Docker;
+
+use crate::deploy_records::load_deploy_records;
+use crate::discovery::discover_challs;
+use crate::env::{ gc_dry_run, gc_interval };
+use crate::logging::*;
+
+/// How often garbage is collected, set with `GC_INTERVAL_SECONDS`; never if not set
+fn interval() -> Option<Duration> {
+    gc_interval()
+        .and_then(|secs| secs.parse().ok())
+        .filter(|secs| *secs > 0)
+        .map(Duration::from_secs)
+}
+
+/// Whether scheduled collections only report what they would remove, set with `GC_DRY_RUN`
+fn scheduled_dry_run() -> bool {
+    gc_dry_run()
+        .map(|value| matches!(value.to_ascii_lowercase().as_str(), "1" | "true" | "yes"))
+        .unwrap_or(false)
+}
+
+/// Something left behind that nothing uses anymore
+///
+/// ## Variants
+/// - `HelperContainer` - A `file.*` container that copying a static file out of an image left behind
+/// - `DanglingImage` - An image in the local Docker daemon without any tag left
+/// - `StaleImage` - A local image of a challenge (or deploy target) that is no longer in the challenge repo
+/// - `StaleObjects` - The Kubernetes objects of a deploy target whose challenge is no longer in the challenge repo
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Garbage {
+    HelperContainer { name: String },
+    DanglingImage { id: String },
+    StaleImage { image: String, tags: Vec<String> },
+    StaleObjects { chall_label: String, resource_name: String, cluster: Option<String> },
+}
+
+impl Display for Garbage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::HelperContainer { name } =>
+                write!(f, "helper container `{name}`"),
+            Self::DanglingImage { id } =>
+                write!(f, "untagged image `{id}`"),
+            Self::StaleImage { image, tags } =>
+                write!(f, "image `{image}` ({} tag(s))", tags.len()),
+            Self::StaleObjects { chall_label, resource_name, cluster } =>
+                write!(f, "**{chall_label}**: `{resource_name}` on cluster {}", cluster.as_deref().unwrap_or("default")),
+        }
+    }
+}
+
+/// Result of a garbage collection
+///
+/// ## Fields
+/// - `checked_at` - Unix timestamp of the collection
+/// - `dry_run` - Whether the garbage was only reported, not removed
+/// - `found` - Everything that was found
+/// - `removed` - What was removed, empty on a dry run
+/// - `kept` - What couldn't be removed because it is still in use
+/// - `errors` - Why removing the rest failed
+/// - `skipped` - Checks that couldn't be made (unreachable Docker daemon or cluster, unreadable challenge repo, ...), and why
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct GcReport {
+    pub checked_at: u64,
+    pub dry_run: bool,
+    pub found: Vec<Garbage>,
+    pub removed: Vec<Garbage>,
+    pub kept: Vec<Garbage>,
+    pub errors: Vec<String>,
+    pub skipped: Vec<String>,
+}
+
+impl GcReport {
+    /// Adds found garbage to the report, along with the outcome of removing it
+    ///
+    /// `Ok(false)` means the garbage is still in use.
+    fn record(&mut self, garbage: Garbage, removal: Option<Result<bool, String>>) {
+        self.found.push(garbage.clone());
+
+        match removal {
+            None => {},
+            Some(Ok(true)) => self.removed.push(garbage),
+            Some(Ok(false)) => self.kept.push(garbage),
+            Some(Err(e)) => {
+                error!("Failed to remove {garbage}: {e}");
+                self.errors.push(format!("{garbage}: {e}"));
+            },
+        }
+    }
+}
+
+lazy_static! {
+    /// Held for the whole collection, so a scheduled and a requested collection don't remove the same things at once
+    static ref GC_LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::new(());
+}
+
+fn now_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+/// Challenges that still exist: every folder of the challenge repo with a chall.yaml (whether it parses or not), along with
+/// every challenge that has a deploy record, since those are removed through `DELETE`
+///
+/// An empty challenge repo (e.g. one that wasn't cloned yet) is refused, it would make every challenge look removed.
+fn known_challs() -> Result<BTreeSet<String>, String> {
+    let index = discover_challs(Path::new(chall_folder_default())).map_err(|e| format!("Failed to index the challenge repo: {e}"))?;
+    let folders = index.folders();
+    if folders.is_empty() {
+        return Err("The challenge repo has no challenges, refusing to treat every challenge as removed".to_string());
+    }
+
+    let records = load_deploy_records()?;
+    Ok(folders.into_iter().chain(records.into_keys()).collect())
+}
+
+/// Whether an image (by its path in the registry) belongs to one of the `known` challenges, or to one of their deploy targets
+fn image_known(known: &BTreeSet<String>, image: &str) -> bool {
+    known.iter().any(|chall| image == chall || image.starts_with(&format!("{chall}/")))
+}
+
+/// Removes every tag of an image, stopping at the first failure
+///
+/// ## Returns
+/// - `Ok(true)` - Every tag was removed
+/// - `Ok(false)` - A container still uses the image
+/// - `Err(String)` - A tag couldn't be removed
+async fn remove_image_tags(docker: &Docker, tags: &[String]) -> Result<bool, String> {
+    let mut all_removed = true;
+    for tag in tags {
+        all_removed &= remove_image(docker, tag).await?;
+    }
+
+    Ok(all_removed)
+}
+
+/// Collects the garbage of the local Docker daemon, helper containers first since they can keep images in use
+///
+/// Challenge images are only checked if the `known` challenges could be found.
+async fn collect_docker(docker: &Docker, known: Option<&BTreeSet<String>>, dry_run: bool, report: &mut GcReport) -> Result<(), String> {
+    for name in orphaned_helper_containers(docker).await? {
+        let removal = if dry_run {
+            None
+        } else {
+            Some(remove_helper_container(docker, &name).await.map(|_| true).map_err(String::from))
+        };
+        report.record(Garbage::HelperContainer { name }, removal);
+    }
+
+    if let Some(known) = known {
+        let stale_images = registry_images(docker).await?
+            .into_iter()
+            .filter(|(image, _)| !image_known(known, image));
+
+        for (image, tags) in stale_images {
+            let removal = if dry_run {
+                None
+            } else {
+                Some(remove_image_tags(docker, &tags).await)
+            };
+            report.record(Garbage::StaleImage { image, tags }, removal);
+        }
+    }
+
+    for id in dangling_images(docker).await? {
+        let removal = if dry_run {
+            None
+        } else {
+            Some(remove_image(docker, &id).await.map_err(String::from))
+        };
+        report.record(Garbage::DanglingImage { id }, removal);
+    }
+
+    Ok(())
+}
+
+/// Collects the deploy target objects of a single cluster whose challenge no longer exists
+///
+/// Team instances are left to the instance reaper.
+async fn collect_cluster(client: &Client, cluster: Option<&str>, known_labels: &BTreeSet<String>, dry_run: bool, report: &mut GcReport) -> Result<(), String> {
+    let live = live_state(client).await?;
+
+    let stale: BTreeSet<(String, String)> = live.deployments
+        .iter()
+        .map(|(name, deployment)| (deployment.chall_label.clone(), name.clone()))
+        .chain(live.services.iter().map(|(name, chall_label)| (chall_label.clone(), name.clone())))
+        .filter(|(chall_label, _)| !known_labels.contains(chall_label))
+        .collect();
+
+    for (chall_label, resource_name) in stale {
+        let removal = if dry_run {
+            None
+        } else {
+            Some(delete_challenge(client, vec![resource_name.as_str()]).await.map(|_| true).map_err(String::from))
+        };
+        report.record(Garbage::StaleObjects { chall_label, resource_name, cluster: cluster.map(str::to_string) }, removal);
+    }
+
+    Ok(())
+}
+
+/// Finds (and unless `dry_run`, removes) everything that was left behind and isn't used anymore:
+/// - `file.*` helper containers that copying static files out of images left behind
+/// - Untagged images in the local Docker daemon
+/// - Local images of challenges that are no longer in the challenge repo
+/// - Kubernetes objects of deploy targets whose challenge is no longer in the challenge repo, on the default cluster and
+///   every cluster profile
+///
+/// Challenges that were removed from the repo but still have a deploy record are kept, `DELETE` them instead. The Docker
+/// daemon isn't checked if images are built with the rootless builder. Checks that can't be made are listed in the report's
+/// `skipped` instead of stopping the collection.
+pub async fn collect_garbage(dry_run: bool) -> GcReport {
+    let _lock = GC_LOCK.lock().await;
+    info!("Collecting garbage{}", if dry_run { " (dry run)" } else { "" });
+
+    let mut report = GcReport { checked_at: now_timestamp(), dry_run, ..GcReport::default() };
+
+    let known = match known_challs() {
+        Ok(known) => Some(known),
+        Err(e) => {
+            warn!("Only collecting garbage that doesn't depend on the challenge repo: {e}");
+            report.skipped.push(format!("removed challenges: {e}"));
+            None
+        },
+    };
+
+    if builder_backend() == BuilderBackend::Command {
+        debug!("Images are built with the rootless builder, not collecting Docker garbage");
+        report.skipped.push("docker: images are built with the rootless builder (IMAGE_BUILDER), not the Docker daemon".to_string());
+    } else {
+        let docker_result = match docker_login().await {
+            Ok(docker) => collect_docker(&docker, known.as_ref(), dry_run, &mut report).await,
+            Err(e) => Err(e.into()),
+        };
+        if let Err(e) = docker_result {
+            error!("Failed to collect Docker garbage: {e}");
+            report.skipped.push(format!("docker: {e}"));
+        }
+    }
+
+    if let Some(known) = &known {
+        let known_labels: BTreeSet<String> = known.iter().map(|chall| chall_label_value(chall)).collect();
+
+        match create_client().await {
+            Ok(client) => {
+                if let Err(e) = collect_cluster(&client, None, &known_labels, dry_run, &mut report).await {
+                    error!("Failed to collect garbage of the default cluster: {e}");
+                    report.skipped.push(format!("default cluster: {e}"));
+                }
+
+                let profiles = cluster_profiles().unwrap_or_else(|e| {
+                    error!("Failed to read cluster profiles, only collecting garbage of the default cluster: {e}");
+                    Default::default()
+                });
+
+                for cluster in profiles.keys() {
+                    let result = match client_for_cluster(&client, Some(cluster)).await {
+                        Ok(cluster_client) => collect_cluster(&cluster_client, Some(cluster), &known_labels, dry_run, &mut report).await,
+                        Err(e) => Err(e.into()),
+                    };
+
+                    if let Err(e) = result {
+                        error!("Failed to collect garbage of cluster {cluster}: {e}");
+                        report.skipped.push(format!("cluster {cluster}: {e}"));
+                    }
+                }
+            },
+            Err(e) => {
+                error!("Garbage collector failed to create k8s client: {e}");
+                report.skipped.push(format!("default cluster: {e}"));
+            },
+        }
+    }
+
+    info!(
+        "Garbage collection done: {} found, {} removed, {} still in use, {} failed",
+        report.found.len(), report.removed.len(), report.kept.len(), report.errors.len(),
+    );
+    report
+}
+
+/// Spawns a Tokio task that periodically collects garbage, if `GC_INTERVAL_SECONDS` is set
+pub fn spawn_garbage_collector() {
+    let Some(interval) = interval() else {
+        info!("Garbage collection isn't scheduled, set GC_INTERVAL_SECONDS to schedule it");
+        return;
+    };
+    let dry_run = scheduled_dry_run();
+    info!("Starting garbage collector (interval {interval:?}, dry run {dry_run})");
+
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+
+        loop {
+            ticker.tick().await;
+
+            let report = collect_garbage(dry_run).await;
+            if dry_run && !report.found.is_empty() {
+                info!("Garbage collector would remove: {}", report.found.iter().map(ToString::to_string).collect::<Vec<_>>().join(", "));
+            }
+        }
+    });
+}
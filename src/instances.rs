@@ -0,0 +1,348 @@
+use lazy_static::lazy_static;
+use uuid::Uuid;
+use std::collections::HashMap;
+use std::sync::{ Mutex, MutexGuard, PoisonError };
+use std::time::{ Duration, SystemTime, UNIX_EPOCH };
+use serde::Serialize;
+
+use arcs_k8s::{ chall_label_value, client_for_cluster, create_client, K8sError };
+use arcs_k8s::clusters::cluster_profiles;
+use arcs_k8s::instance::{ LiveInstance, delete_instance, live_instances };
+use kube::Client;
+
+use crate::env::{ instance_ttl, max_instances_per_team, max_instances_total };
+use crate::server::utils::api_types::incoming::Link;
+use crate::logging::*;
+
+const DEFAULT_INSTANCE_TTL: Duration = Duration::from_secs(30 * 60);
+const DEFAULT_MAX_INSTANCES_PER_TEAM: usize = 3;
+const DEFAULT_MAX_INSTANCES_TOTAL: usize = 50;
+
+/// How often the reaper checks for expired instances
+const REAPER_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Every how many reaper ticks the clusters are checked for instances that aren't in the registry (every 10 minutes)
+const UNTRACKED_SWEEP_TICKS: u32 = 20;
+
+/// How long an instance lives for after it was started, set with `INSTANCE_TTL_SECONDS`
+fn ttl() -> Duration {
+    instance_ttl()
+        .and_then(|secs| secs.parse().ok())
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_INSTANCE_TTL)
+}
+
+/// Maximum number of instances a single team can have at once, set with `MAX_INSTANCES_PER_TEAM`
+fn team_cap() -> usize {
+    max_instances_per_team()
+        .and_then(|max| max.parse().ok())
+        .unwrap_or(DEFAULT_MAX_INSTANCES_PER_TEAM)
+}
+
+/// Maximum number of instances across all teams, set with `MAX_INSTANCES_TOTAL`
+fn total_cap() -> usize {
+    max_instances_total()
+        .and_then(|max| max.parse().ok())
+        .unwrap_or(DEFAULT_MAX_INSTANCES_TOTAL)
+}
+
+/// The Kubernetes resources of a single deploy target of an instance
+///
+/// ## Fields
+/// - `name` - Name of the resources
+/// - `cluster` - Cluster profile the resources were created on, the default cluster if not set
+#[derive(Debug, Clone)]
+pub struct InstanceResource {
+    pub name: String,
+    pub cluster: Option<String>,
+}
+
+impl InstanceResource {
+    /// Deletes the resources from the cluster they were created on
+    pub async fn delete(&self, client: &Client) -> Result<(), K8sError> {
+        let client = client_for_cluster(client, self.cluster.as_deref()).await?;
+        delete_instance(&client, &self.name).await
+    }
+}
+
+/// A team's instance of a challenge
+///
+/// ## Fields
+/// - `chall_name` - The challenge the instance is a copy of
+/// - `team_id` - The team the instance belongs to
+/// - `resources` - Kubernetes resources of the instance, one per deploy target
+/// - `links` - Links to the instance's exposed ports
+/// - `expires_at` - When the instance gets torn down by the reaper
+/// - `ready` - `false` while the instance's resources are still being created
+#[derive(Debug, Clone)]
+pub struct Instance {
+    pub chall_name: String,
+    pub team_id: Uuid,
+    pub resources: Vec<InstanceResource>,
+    pub links: Vec<Link>,
+    pub expires_at: SystemTime,
+    pub ready: bool,
+}
+
+/// Information about an instance that is sent back to the client
+#[derive(Debug, Clone, Serialize)]
+pub struct InstanceInfo {
+    chall_name: String,
+    team_id: Uuid,
+    links: Vec<Link>,
+    expires_at: u64,
+    ready: bool,
+}
+
+impl Instance {
+    pub fn info(&self) -> InstanceInfo {
+        let expires_at = self.expires_at
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or_default();
+
+        InstanceInfo {
+            chall_name: self.chall_name.clone(),
+            team_id: self.team_id,
+            links: self.links.clone(),
+            expires_at,
+            ready: self.ready,
+        }
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.expires_at <= SystemTime::now()
+    }
+}
+
+/// Reasons an instance couldn't be reserved
+///
+/// ## Variants
+/// - `AlreadyExists` - The team already has an instance of the challenge, which is returned
+/// - `TeamLimit` - The team is at its instance cap
+/// - `TotalLimit` - The server is at its global instance cap
+#[derive(Debug, Clone)]
+pub enum ReserveErr {
+    AlreadyExists(Instance),
+    TeamLimit(usize),
+    TotalLimit(usize),
+}
+
+impl std::fmt::Display for ReserveErr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::AlreadyExists(instance) => write!(f, "Team {} already has an instance of {}", instance.team_id, instance.chall_name),
+            Self::TeamLimit(max) => write!(f, "Teams can't have more than {max} instances running at once"),
+            Self::TotalLimit(max) => write!(f, "There can't be more than {max} instances running at once"),
+        }
+    }
+}
+
+type InstanceKey = (String, Uuid);
+
+lazy_static! {
+    static ref CURRENT_INSTANCES: Mutex<HashMap<InstanceKey, Instance>> = Mutex::new(HashMap::new());
+}
+
+fn current_instances() -> MutexGuard<'static, HashMap<InstanceKey, Instance>> {
+    CURRENT_INSTANCES.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Reserves a slot for a team's instance of a challenge, checking the per-team and global caps
+///
+/// The reserved instance isn't ready until [`activate_instance`][activate_instance] is called with its resources.
+pub fn reserve_instance(chall_name: &str, team_id: Uuid) -> Result<(), ReserveErr> {
+    let mut instances = current_instances();
+
+    if let Some(instance) = instances.get(&(chall_name.to_string(), team_id)) {
+        return Err(ReserveErr::AlreadyExists(instance.clone()));
+    }
+
+    let total_cap = total_cap();
+    if instances.len() >= total_cap {
+        return Err(ReserveErr::TotalLimit(total_cap));
+    }
+
+    let team_cap = team_cap();
+    if instances.values().filter(|instance| instance.team_id == team_id).count() >= team_cap {
+        return Err(ReserveErr::TeamLimit(team_cap));
+    }
+
+    trace!("Reserving instance of {chall_name} for team {team_id}");
+    instances.insert(
+        (chall_name.to_string(), team_id),
+        Instance {
+            chall_name: chall_name.to_string(),
+            team_id,
+            resources: vec![],
+            links: vec![],
+            expires_at: SystemTime::now() + ttl(),
+            ready: false,
+        },
+    );
+
+    Ok(())
+}
+
+/// Marks a reserved instance as ready, starting its TTL from now
+///
+/// ## Returns
+/// - `Some(Instance)` - The now ready instance
+/// - `None` - The reservation was released (stopped) while the instance was being created
+pub fn activate_instance(chall_name: &str, team_id: Uuid, resources: Vec<InstanceResource>, links: Vec<Link>) -> Option<Instance> {
+    let mut instances = current_instances();
+    let instance = instances.get_mut(&(chall_name.to_string(), team_id))?;
+
+    instance.resources = resources;
+    instance.links = links;
+    instance.expires_at = SystemTime::now() + ttl();
+    instance.ready = true;
+
+    Some(instance.clone())
+}
+
+/// Removes a team's instance of a challenge from the registry, returning it if there was one
+pub fn release_instance(chall_name: &str, team_id: Uuid) -> Option<Instance> {
+    current_instances().remove(&(chall_name.to_string(), team_id))
+}
+
+/// Puts an instance back in the registry after tearing it down failed, so it is torn down again later
+///
+/// An instance the team started again in the meantime is kept instead.
+pub fn restore_instance(instance: Instance) {
+    current_instances()
+        .entry((instance.chall_name.clone(), instance.team_id))
+        .or_insert(instance);
+}
+
+/// Removes every team's instance of a challenge from the registry and returns them, e.g. when the challenge is deleted
+pub fn release_chall_instances(chall_name: &str) -> Vec<Instance> {
+    let mut instances = current_instances();
+
+    let chall_keys: Vec<InstanceKey> = instances
+        .keys()
+        .filter(|(instance_chall, _)| instance_chall == chall_name)
+        .cloned()
+        .collect();
+
+    chall_keys
+        .into_iter()
+        .filter_map(|key| instances.remove(&key))
+        .collect()
+}
+
+/// Removes every ready instance whose TTL ran out from the registry and returns them
+fn take_expired_instances() -> Vec<Instance> {
+    let mut instances = current_instances();
+
+    let expired_keys: Vec<InstanceKey> = instances
+        .iter()
+        .filter(|(_, instance)| instance.ready && instance.is_expired())
+        .map(|(key, _)| key.clone())
+        .collect();
+
+    expired_keys
+        .into_iter()
+        .filter_map(|key| instances.remove(&key))
+        .collect()
+}
+
+/// Deletes the resources of every deploy target of an instance, stopping at the first failure
+pub async fn tear_down(client: &Client, instance: &Instance) -> Result<(), K8sError> {
+    for resource in &instance.resources {
+        resource.delete(client).await?;
+    }
+
+    Ok(())
+}
+
+/// Whether an instance found in the cluster belongs to an instance in the registry, ready or still being created
+fn is_tracked(live: &LiveInstance) -> bool {
+    current_instances()
+        .values()
+        .any(|instance| chall_label_value(&instance.chall_name) == live.chall_label && instance.team_id.to_string() == live.team_id)
+}
+
+/// Deletes the instances of a single cluster that aren't in the registry
+async fn reap_untracked_on_cluster(client: &Client) -> Result<usize, K8sError> {
+    let mut reaped = 0;
+
+    for live in live_instances(client).await?.iter().filter(|live| !is_tracked(live)) {
+        info!("Instance {} of team {} isn't tracked, tearing down", live.name, live.team_id);
+        match delete_instance(client, &live.name).await {
+            Ok(()) => reaped += 1,
+            Err(e) => error!("Failed to tear down untracked instance {}: {e}", live.name),
+        }
+    }
+
+    Ok(reaped)
+}
+
+/// Tears down the instances in the default cluster and every cluster profile that aren't in the registry
+async fn reap_untracked_instances(client: &Client) {
+    let profiles = cluster_profiles().unwrap_or_else(|e| {
+        error!("Failed to read cluster profiles, only reaping untracked instances of the default cluster: {e}");
+        Default::default()
+    });
+    let clusters = std::iter::once(None).chain(profiles.keys().map(|cluster| Some(cluster.as_str())));
+
+    for cluster in clusters {
+        let result = match client_for_cluster(client, cluster).await {
+            Ok(cluster_client) => reap_untracked_on_cluster(&cluster_client).await,
+            Err(e) => Err(e),
+        };
+
+        match result {
+            Ok(0) => {},
+            Ok(reaped) => info!("Tore down {reaped} untracked instance(s) on cluster {}", cluster.unwrap_or("default")),
+            Err(e) => error!("Failed to reap untracked instances on cluster {}: {e}", cluster.unwrap_or("default")),
+        }
+    }
+}
+
+/// Spawns a Tokio task that periodically tears down instances whose TTL ran out
+///
+/// Instances in the cluster that aren't in the registry are torn down when the task starts (the registry only lives in
+/// memory, so those are left behind by a previous run of the server) and every [`UNTRACKED_SWEEP_TICKS`] ticks after
+/// (e.g. ones whose clean up failed after they couldn't be started).
+pub fn spawn_instance_reaper() {
+    info!("Starting instance reaper (TTL {:?})", ttl());
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(REAPER_INTERVAL);
+        let mut ticks: u32 = 0;
+
+        loop {
+            interval.tick().await;
+
+            let sweep = ticks % UNTRACKED_SWEEP_TICKS == 0;
+            ticks = ticks.wrapping_add(1);
+
+            let expired = take_expired_instances();
+            if expired.is_empty() && !sweep { continue }
+
+            let client = match create_client().await {
+                Ok(client) => client,
+                Err(e) => {
+                    error!("Instance reaper failed to create k8s client, {} expired instance(s) left running: {e}", expired.len());
+                    for instance in expired {
+                        restore_instance(instance);
+                    }
+                    continue;
+                },
+            };
+
+            for instance in expired {
+                info!("Instance of {} for team {} expired, tearing down", instance.chall_name, instance.team_id);
+                if let Err(e) = tear_down(&client, &instance).await {
+                    error!("Failed to tear down expired instance of {} for team {}, retrying later: {e}", instance.chall_name, instance.team_id);
+                    restore_instance(instance);
+                }
+            }
+
+            if sweep {
+                reap_untracked_instances(&client).await;
+            }
+        }
+    });
+}
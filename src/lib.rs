@@ -1,6 +1,12 @@
 mod server;
 mod polling;
+mod instances;
+mod deploy_records;
+mod reconciler;
+mod uptime;
 mod auth;
+mod discovery;
+mod gc;
 
 pub mod env;
 
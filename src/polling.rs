@@ -2,8 +2,14 @@ use lazy_static::lazy_static;
 use uuid::Uuid;
 use std::time::{ Instant, SystemTime, Duration };
 use chashmap::CHashMap;
+use arcs_docker::{ LayerProgress, LintFinding };
+use arcs_k8s::config::{ ExposedPort, target_key };
 use serde::{ Serialize, Serializer };
 use crate::server::responses::{Response, Metadata};
+use crate::server::utils::errors::{ DeployProcessErr, FailureDetails };
+use std::collections::{ BTreeMap, BTreeSet };
+use std::path::Path;
+use yaml::deploy::structs::DeployTargetType;
 use crate::logging::*;
 
 macro_rules! create_prefix {
@@ -57,6 +63,49 @@ impl DeployStep {
     }
 }
 
+/// Why a deployment failed
+/// 
+/// ## Fields
+/// - `reason` - Error trace of the failure, returned when polling the deployment
+/// - `process_err` - Subcode and description of the `55X` status code of the failure, if a step of the deploy process failed
+/// - `details` - Machine-readable details of the failure, returned when polling the deployment
+#[derive(Debug, Clone, Serialize)]
+pub struct DeployFailure {
+    pub reason: String,
+    #[serde(skip)]
+    pub process_err: Option<(u64, &'static str)>,
+    pub details: FailureDetails,
+}
+
+impl DeployFailure {
+    /// Attaches the deploy target the failure happened on
+    pub fn for_target(self, target_type: DeployTargetType, build_path: Option<&Path>) -> Self {
+        Self { details: self.details.for_target(target_type, build_path), ..self }
+    }
+}
+
+impl From<String> for DeployFailure {
+    fn from(reason: String) -> Self {
+        Self { details: FailureDetails::setup(&reason), reason, process_err: None }
+    }
+}
+
+impl From<&str> for DeployFailure {
+    fn from(reason: &str) -> Self {
+        reason.to_string().into()
+    }
+}
+
+impl From<&DeployProcessErr> for DeployFailure {
+    fn from(err: &DeployProcessErr) -> Self {
+        Self {
+            reason: err.reasons().join(": "),
+            process_err: Some((err.subcode(), err.description())),
+            details: err.details(),
+        }
+    }
+}
+
 /// Enum that represents the main states a deployment can be in 
 /// 
 /// ## Variants
@@ -69,8 +118,8 @@ impl DeployStep {
 #[derive(Debug, Clone, Default)]
 pub enum DeploymentStatus {
     InProgress(Instant, DeployStep),
-    Success(Instant, Vec<i32>),
-    Failure(Instant, String),
+    Success(Instant, Vec<ExposedPort>),
+    Failure(Instant, DeployFailure),
     #[default]
     Unknown,
 }
@@ -116,7 +165,7 @@ impl DeploymentStatus {
     pub fn finished_data(&self) -> Option<serde_json::Value> {
         match self {
             Self::Success(_, ports) => Some(serde_json::to_value(ports).ok()?),
-            Self::Failure(_, response) => Some(serde_json::to_value(response).ok()?),
+            Self::Failure(_, failure) => Some(serde_json::to_value(failure).ok()?),
             Self::InProgress(..) => None,
             Self::Unknown => None,
         }
@@ -166,21 +215,115 @@ pub type PollingId = Uuid;
 
 lazy_static! {
     static ref CURRENT_DEPLOYMENTS: CHashMap<PollingId, DeploymentStatus> = CHashMap::new();
+
+    /// Challenge that every registered deployment deploys
+    static ref DEPLOYMENT_CHALLS: CHashMap<PollingId, String> = CHashMap::new();
+
+    /// Latest progress of every layer of the image a deployment is pushing, keyed by layer
+    static ref PUSH_PROGRESS: CHashMap<PollingId, BTreeMap<String, LayerProgress>> = CHashMap::new();
+
+    /// How long the image of every deploy target of a deployment took to build
+    static ref BUILD_TIMES: CHashMap<PollingId, Vec<TargetBuildTime>> = CHashMap::new();
+}
+
+/// How long the image of a deploy target took to build, returned along with a finished deployment
+/// 
+/// ## Fields
+/// - `target_type` - Key of the target in the `deploy` section of the chall.yaml
+/// - `build_path` - Subfolder of the challenge the target is built from, if any
+/// - `seconds` - Duration of the build
+/// - `succeeded` - Whether the build succeeded
+/// - `warnings` - Findings of the pre-build checks that didn't fail the build
+#[derive(Debug, Clone, Serialize)]
+pub struct TargetBuildTime {
+    pub target_type: &'static str,
+    pub build_path: Option<String>,
+    pub seconds: f64,
+    pub succeeded: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<LintFinding>,
+}
+
+impl TargetBuildTime {
+    pub fn new(target_type: DeployTargetType, build_path: Option<&Path>, duration: Duration, succeeded: bool) -> Self {
+        Self {
+            target_type: target_key(target_type),
+            build_path: build_path.map(|path| path.to_string_lossy().to_string()),
+            seconds: duration.as_secs_f64(),
+            succeeded,
+            warnings: vec![],
+        }
+    }
+
+    /// Attaches the warnings of the pre-build checks of the target
+    pub fn with_warnings(self, warnings: Vec<LintFinding>) -> Self {
+        Self { warnings, ..self }
+    }
+}
+
+/// Records how long the image of one of the targets of a deployment took to build
+pub fn record_build_time(id: PollingId, build_time: TargetBuildTime) {
+    BUILD_TIMES.upsert(
+        id,
+        || vec![build_time.clone()],
+        |build_times| build_times.push(build_time.clone()),
+    );
+}
+
+/// Build time of every target of a deployment built so far, in the order the builds finished
+pub fn build_times(id: PollingId) -> Vec<TargetBuildTime> {
+    BUILD_TIMES.get(&id).map(|build_times| build_times.clone()).unwrap_or_default()
+}
+
+/// Records the progress of a layer of the image being pushed by a deployment, returned when polling it
+pub fn report_push_progress(id: PollingId, progress: &LayerProgress) {
+    PUSH_PROGRESS.upsert(
+        id,
+        || BTreeMap::from([(progress.layer.clone(), progress.clone())]),
+        |layers| { layers.insert(progress.layer.clone(), progress.clone()); },
+    );
+}
+
+/// Progress of every layer of the image a deployment is pushing, empty if it isn't pushing
+pub fn push_progress(id: PollingId) -> Vec<LayerProgress> {
+    PUSH_PROGRESS
+        .get(&id)
+        .map(|layers| layers.values().cloned().collect())
+        .unwrap_or_default()
+}
+
+/// Forgets the push progress of a deployment, once its push is over
+pub fn clear_push_progress(id: PollingId) {
+    PUSH_PROGRESS.remove(&id);
 }
 
 /// Registers a new deployment with the given `PollingId` and returns an error if the deployment is already in progress
-pub fn register_chall_deployment(id: PollingId) -> Result<(), DeploymentStatus> {
+pub fn register_chall_deployment(id: PollingId, chall_name: &str) -> Result<(), DeploymentStatus> {
     trace!("Registering deployment with ID: {id:?}");
     if let Some(curr_status) = CURRENT_DEPLOYMENTS.get(&id) {
         Err(curr_status.clone())
     } else {
         CURRENT_DEPLOYMENTS.insert(id, DeploymentStatus::InProgress(Instant::now(), DeployStep::Building));
+        DEPLOYMENT_CHALLS.insert(id, chall_name.to_string());
         Ok(())
     }
 }
 
+/// Challenges with a deployment that hasn't finished yet, whose cluster objects may not match their deploy record
+pub fn deploying_challs() -> BTreeSet<String> {
+    DEPLOYMENT_CHALLS
+        .clone()
+        .into_iter()
+        .filter(|(id, _)| CURRENT_DEPLOYMENTS.get(id).is_some_and(|status| !status.is_finished()))
+        .map(|(_, chall_name)| chall_name)
+        .collect()
+}
+
 /// Registers a new deployment with the given `PollingId` and returns an error if the deployment is already in progress
 pub fn deregister_id(id: PollingId) -> Option<DeploymentStatus> {
+    clear_push_progress(id);
+    BUILD_TIMES.remove(&id);
+    DEPLOYMENT_CHALLS.remove(&id);
     if let Some(curr_status) = CURRENT_DEPLOYMENTS.remove(&id) {
         Some(curr_status)
     } else {
@@ -275,7 +418,8 @@ pub fn advance_deployment_step(id: PollingId, new_step: Option<DeployStep>) -> R
 /// ## Returns
 /// - `Ok(DeploymentStatus)` : Returns the new `DeploymentStatus` if the `PollingId` was marked as successful
 /// - `Err(PollingId)` : Returns the `PollingId` if the given `PollingId` is already marked as finished
-pub fn fail_deployment(id: PollingId, reason: String) -> Result<DeploymentStatus, PollingId> {
+pub fn fail_deployment(id: PollingId, reason: impl Into<DeployFailure>) -> Result<DeploymentStatus, PollingId> {
+    let reason = reason.into();
     let status_mapper = |status: &DeploymentStatus| {
         (!status.is_finished()).then_some(DeploymentStatus::Failure(Instant::now(), reason))
     };
@@ -287,7 +431,7 @@ pub fn fail_deployment(id: PollingId, reason: String) -> Result<DeploymentStatus
 /// ## Returns
 /// - `Ok(DeploymentStatus)` : Returns the new `DeploymentStatus` if the `PollingId` was marked as successful
 /// - `Err(PollingId)` : Returns the `PollingId` if the given `PollingId` is already marked as finished
-pub fn succeed_deployment(id: PollingId, response: &[i32]) -> Result<DeploymentStatus, PollingId> {
+pub fn succeed_deployment(id: PollingId, response: &[ExposedPort]) -> Result<DeploymentStatus, PollingId> {
     let status_mapper = |status: &DeploymentStatus| {
         (!status.is_finished()).then_some(DeploymentStatus::Success(Instant::now(), response.to_vec()))
     };
@@ -0,0 +1,382 @@
+use lazy_static::lazy_static;
+use std::collections::{ BTreeMap, BTreeSet };
+use std::fmt::Display;
+use std::sync::{ Mutex, PoisonError };
+use std::time::{ Duration, SystemTime, UNIX_EPOCH };
+use serde::Serialize;
+
+use arcs_k8s::{ chall_label_value, client_for_cluster, create_client };
+use arcs_k8s::clusters::cluster_profiles;
+use arcs_k8s::config::TargetConfig;
+use arcs_k8s::reconcile::{ LiveState, live_state, repair_service, repair_workload, scale_deployment };
+use kube::Client;
+
+use crate::deploy_records::{ DeployRecord, load_deploy_records, update_target_ports };
+use crate::emitter::send_developer_alert;
+use crate::polling::deploying_challs;
+use crate::env::{ reconcile_interval, reconcile_self_heal };
+use crate::server::utils::api_types::incoming::AlertLevel;
+use crate::server::utils::yaml::load_chall_yaml;
+use crate::logging::*;
+
+const DEFAULT_RECONCILE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How often the cluster is compared against the deploy records, set with `RECONCILE_INTERVAL_SECONDS`
+fn interval() -> Duration {
+    reconcile_interval()
+        .and_then(|secs| secs.parse().ok())
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_RECONCILE_INTERVAL)
+}
+
+/// Whether drift gets repaired automatically, set with `RECONCILE_SELF_HEAL`
+fn self_heal_enabled() -> bool {
+    reconcile_self_heal()
+        .map(|value| matches!(value.to_ascii_lowercase().as_str(), "1" | "true" | "yes"))
+        .unwrap_or(false)
+}
+
+/// A difference between a challenge's deploy record and what is running in the cluster
+///
+/// ## Variants
+/// - `MissingDeployment` - The target's `Deployment` is gone
+/// - `MissingService` - The target's `Service` is gone
+/// - `Extra` - A labelled object exists for a target that has no deploy record
+/// - `WrongImage` - The target's `Deployment` runs a different image than it was deployed with
+/// - `WrongReplicas` - The target's `Deployment` is scaled differently than it was deployed with
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Drift {
+    MissingDeployment { chall_name: String, resource_name: String },
+    MissingService { chall_name: String, resource_name: String },
+    Extra { chall_label: String, resource_name: String },
+    WrongImage { chall_name: String, resource_name: String, expected: String, actual: Option<String> },
+    WrongReplicas { chall_name: String, resource_name: String, expected: i32, actual: i32 },
+}
+
+impl Drift {
+    /// Extra objects are only reported, since they might have been created by hand on purpose
+    fn is_healable(&self) -> bool {
+        !matches!(self, Self::Extra { .. })
+    }
+
+    /// Challenge whose deploy record the drift is about, `None` for extra objects that have no record
+    fn chall_name(&self) -> Option<&str> {
+        match self {
+            Self::MissingDeployment { chall_name, .. } |
+            Self::MissingService { chall_name, .. } |
+            Self::WrongImage { chall_name, .. } |
+            Self::WrongReplicas { chall_name, .. } => Some(chall_name),
+            Self::Extra { .. } => None,
+        }
+    }
+
+    /// Whether the drift is about one of the `chall_names`, extra objects being matched by their challenge label
+    fn concerns_any(&self, chall_names: &BTreeSet<String>) -> bool {
+        match self {
+            Self::Extra { chall_label, .. } => chall_names.iter().any(|chall_name| &chall_label_value(chall_name) == chall_label),
+            _ => self.chall_name().is_some_and(|chall_name| chall_names.contains(chall_name)),
+        }
+    }
+}
+
+impl Display for Drift {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::MissingDeployment { chall_name, resource_name } =>
+                write!(f, "**{chall_name}**: deployment `{resource_name}` is missing"),
+            Self::MissingService { chall_name, resource_name } =>
+                write!(f, "**{chall_name}**: service `{resource_name}-service` is missing"),
+            Self::Extra { chall_label, resource_name } =>
+                write!(f, "**{chall_label}**: `{resource_name}` is running but was never deployed"),
+            Self::WrongImage { chall_name, resource_name, expected, actual } =>
+                write!(f, "**{chall_name}**: `{resource_name}` runs `{}` instead of `{expected}`", actual.as_deref().unwrap_or("no image")),
+            Self::WrongReplicas { chall_name, resource_name, expected, actual } =>
+                write!(f, "**{chall_name}**: `{resource_name}` has {actual} replica(s) instead of {expected}"),
+        }
+    }
+}
+
+/// Result of comparing the deploy records with the cluster
+///
+/// ## Fields
+/// - `checked_at` - Unix timestamp of the check
+/// - `drift` - Every difference that was found
+/// - `healed` - Differences that were repaired, only when self-healing is enabled
+/// - `heal_errors` - Why repairing the other differences failed
+/// - `unreachable_clusters` - Cluster profiles that couldn't be checked, and why
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DriftReport {
+    pub checked_at: u64,
+    pub drift: Vec<Drift>,
+    pub healed: Vec<Drift>,
+    pub heal_errors: Vec<String>,
+    pub unreachable_clusters: Vec<String>,
+}
+
+lazy_static! {
+    static ref LAST_REPORT: Mutex<Option<DriftReport>> = Mutex::new(None);
+}
+
+/// Compares the deploy records with the labelled objects in the cluster
+pub fn find_drift(records: &BTreeMap<String, DeployRecord>, live: &LiveState) -> Vec<Drift> {
+    let mut drift = vec![];
+    let mut recorded_names = BTreeSet::new();
+
+    for record in records.values() {
+        // The targets of a challenge that is being deleted are torn down one by one, they are neither missing nor extra
+        if record.deleting {
+            recorded_names.extend(record.targets.iter().map(|target| target.resource_name.clone()));
+            continue;
+        }
+
+        for target in &record.targets {
+            let chall_name = record.chall_name.clone();
+            let resource_name = target.resource_name.clone();
+            recorded_names.insert(resource_name.clone());
+
+            match live.deployments.get(&resource_name) {
+                None => drift.push(Drift::MissingDeployment { chall_name: chall_name.clone(), resource_name: resource_name.clone() }),
+                Some(deployment) => {
+                    if deployment.image.as_deref() != Some(target.image.as_str()) {
+                        drift.push(Drift::WrongImage {
+                            chall_name: chall_name.clone(),
+                            resource_name: resource_name.clone(),
+                            expected: target.image.clone(),
+                            actual: deployment.image.clone(),
+                        });
+                    }
+                    // The autoscaler moves the replicas of autoscaled targets around by design
+                    if !target.autoscaled && deployment.replicas != target.replicas {
+                        drift.push(Drift::WrongReplicas {
+                            chall_name: chall_name.clone(),
+                            resource_name: resource_name.clone(),
+                            expected: target.replicas,
+                            actual: deployment.replicas,
+                        });
+                    }
+                },
+            }
+
+            if !live.services.contains_key(&resource_name) {
+                drift.push(Drift::MissingService { chall_name, resource_name });
+            }
+        }
+    }
+
+    let live_names = live.deployments
+        .iter()
+        .map(|(name, deployment)| (name, &deployment.chall_label))
+        .chain(live.services.iter());
+
+    let extra: BTreeSet<Drift> = live_names
+        .filter(|(name, _)| !recorded_names.contains(*name))
+        .map(|(name, chall_label)| Drift::Extra { chall_label: chall_label.clone(), resource_name: name.clone() })
+        .collect();
+    drift.extend(extra);
+
+    drift
+}
+
+/// Rebuilds the deploy config of a recorded target from the challenge's chall.yaml, pinned to the recorded image digest
+async fn target_config(chall_name: &str, resource_name: &str) -> Result<TargetConfig, String> {
+    let chall_yaml = match load_chall_yaml(chall_name).await {
+        Some(result) => result?,
+        None => return Err(format!("Failed to find chall.yaml for {chall_name}")),
+    };
+
+    let Some((_, mut config)) = chall_yaml
+        .target_configs(chall_name)
+        .into_iter()
+        .find(|(_, config)| config.resource_name() == resource_name)
+    else {
+        return Err(format!("{resource_name} is no longer a deploy target of {chall_name}"));
+    };
+
+    // Repairs redeploy the image that was deployed, not whatever the mutable tag points to now
+    config.image_digest = load_deploy_records()?
+        .get(chall_name)
+        .and_then(|record| record.targets.iter().find(|target| target.resource_name == resource_name))
+        .and_then(|target| target.image_digest.clone());
+
+    Ok(config)
+}
+
+/// Whether the record `drift` is about was removed or marked as deleting, or its challenge started being deployed, since
+/// the records were compared with the cluster
+fn is_stale(drift: &Drift) -> bool {
+    let Some(chall_name) = drift.chall_name() else { return false };
+
+    if deploying_challs().contains(chall_name) {
+        return true;
+    }
+
+    match load_deploy_records() {
+        Ok(records) => records.get(chall_name).map_or(true, |record| record.deleting),
+        Err(e) => {
+            error!("Failed to reload deploy records before healing drift ({drift}): {e}");
+            true
+        },
+    }
+}
+
+/// Repairs a single difference between the deploy records and the cluster
+async fn heal(client: &Client, drift: &Drift) -> Result<(), String> {
+    info!("Healing drift: {drift}");
+
+    match drift {
+        Drift::WrongReplicas { resource_name, expected, .. } => Ok(scale_deployment(client, resource_name, *expected).await?),
+        Drift::MissingDeployment { chall_name, resource_name } |
+        Drift::WrongImage { chall_name, resource_name, .. } => {
+            let config = target_config(chall_name, resource_name).await?;
+            Ok(repair_workload(client, &config).await?)
+        },
+        Drift::MissingService { chall_name, resource_name } => {
+            let config = target_config(chall_name, resource_name).await?;
+            let ports = repair_service(client, &config).await?;
+            warn!("Service of {resource_name} was recreated, it is now exposed on {ports:?}");
+            update_target_ports(chall_name, resource_name, ports)
+        },
+        Drift::Extra { .. } => Ok(()),
+    }
+}
+
+fn now_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+/// Keeps only the recorded targets that were deployed to `cluster`
+fn records_on_cluster(records: &BTreeMap<String, DeployRecord>, cluster: Option<&str>) -> BTreeMap<String, DeployRecord> {
+    records
+        .iter()
+        .map(|(chall_name, record)| {
+            let targets = record.targets
+                .iter()
+                .filter(|target| target.cluster.as_deref() == cluster)
+                .cloned()
+                .collect();
+            (chall_name.clone(), DeployRecord { targets, ..record.clone() })
+        })
+        .collect()
+}
+
+/// Compares the deploy records of a single cluster with that cluster, repairing drift if self-healing is enabled
+async fn reconcile_cluster(client: &Client, records: &BTreeMap<String, DeployRecord>, report: &mut DriftReport) -> Result<(), String> {
+    let live = live_state(client).await?;
+
+    // A deployment in progress applies its targets before its record is saved, so they look like drift until it's done
+    let deploying = deploying_challs();
+    let drift: Vec<Drift> = find_drift(records, &live)
+        .into_iter()
+        .filter(|drift| !drift.concerns_any(&deploying))
+        .collect();
+
+    if self_heal_enabled() {
+        for drift in drift.iter().filter(|drift| drift.is_healable()) {
+            if is_stale(drift) {
+                debug!("Not healing drift ({drift}), its challenge is being deployed or deleted");
+                continue;
+            }
+
+            match heal(client, drift).await {
+                Ok(()) => report.healed.push(drift.clone()),
+                Err(e) => {
+                    error!("Failed to heal drift ({drift}): {e}");
+                    report.heal_errors.push(format!("{drift}: {e}"));
+                },
+            }
+        }
+    }
+
+    report.drift.extend(drift);
+    Ok(())
+}
+
+/// Compares the deploy records with the default cluster and every cluster profile once, repairing drift if
+/// self-healing is enabled
+///
+/// A Discord alert is sent whenever the drift found differs from the previous check.
+///
+/// ## Returns
+/// - `Ok(DriftReport)` - What was found (and repaired)
+/// - `Err(String)` - The records or the default cluster couldn't be read
+pub async fn reconcile_once(client: &Client) -> Result<DriftReport, String> {
+    let records = load_deploy_records()?;
+    let mut report = DriftReport { checked_at: now_timestamp(), ..DriftReport::default() };
+
+    reconcile_cluster(client, &records_on_cluster(&records, None), &mut report).await?;
+
+    let profiles = cluster_profiles().unwrap_or_else(|e| {
+        error!("Failed to read cluster profiles, only the default cluster was reconciled: {e}");
+        Default::default()
+    });
+
+    for cluster in profiles.keys() {
+        let result = match client_for_cluster(client, Some(cluster)).await {
+            Ok(cluster_client) => reconcile_cluster(&cluster_client, &records_on_cluster(&records, Some(cluster)), &mut report).await,
+            Err(e) => Err(e.into()),
+        };
+
+        if let Err(e) = result {
+            error!("Failed to reconcile cluster {cluster}: {e}");
+            report.unreachable_clusters.push(format!("{cluster}: {e}"));
+        }
+    }
+
+    let previous_drift = LAST_REPORT
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .replace(report.clone())
+        .map(|previous| previous.drift)
+        .unwrap_or_default();
+
+    if !report.drift.is_empty() && report.drift != previous_drift {
+        warn!("Cluster drift detected: {:?}", report.drift);
+        send_drift_alert(&report).await;
+    }
+
+    Ok(report)
+}
+
+async fn send_drift_alert(report: &DriftReport) {
+    let mut message = String::from("Deployed challenges drifted from their deploy records:");
+    for drift in &report.drift {
+        message.push_str(&format!("\n- {drift}"));
+        if report.healed.contains(drift) {
+            message.push_str(" (healed)");
+        }
+    }
+
+    let data = serde_json::to_value(report).unwrap_or_default();
+    if let Err(e) = send_developer_alert(AlertLevel::Warn, &message, data).await {
+        error!("Failed to send drift alert: {e}");
+    }
+}
+
+/// Spawns a Tokio task that periodically compares the deploy records with the cluster
+pub fn spawn_reconciler() {
+    info!("Starting reconciler (interval {:?}, self-heal {})", interval(), self_heal_enabled());
+
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval());
+
+        loop {
+            ticker.tick().await;
+
+            let client = match create_client().await {
+                Ok(client) => client,
+                Err(e) => {
+                    error!("Reconciler failed to create k8s client: {e}");
+                    continue;
+                },
+            };
+
+            if let Err(e) = reconcile_once(&client).await {
+                error!("Failed to reconcile cluster with deploy records: {e}");
+            }
+        }
+    });
+}
@@ -10,13 +10,20 @@ use yaml_editor::Modifications;
 use actix_web::post;
 use arcs_docker::docker_login;
 use arcs_k8s::create_client;
+use arcs_retry::retry_stats;
 use kube::Client;
 use serde::Deserialize;
+use uuid::Uuid;
 use shiplift::Docker;
 
 use crate::auth::validate_auth_token;
-use crate::receiver::{ delete_challenge, spawn_deploy_req, update_yaml };
+use crate::receiver::{ delete_challenge, scale_challenge, spawn_deploy_req, start_instance, stop_instance, update_yaml };
+use crate::instances::spawn_instance_reaper;
+use crate::reconciler::{ reconcile_once, spawn_reconciler };
+use crate::gc::{ collect_garbage, spawn_garbage_collector };
+use crate::uptime::{ spawn_uptime_checker, uptime_history };
 use crate::emitter::sync_metadata_with_webhook;
+use crate::emitter::outbox::{ deliver_pending, outbox_entries, replay_failed, spawn_outbox_worker };
 
 use crate::logging::*;
 use crate::polling::PollingId;
@@ -41,12 +48,20 @@ use actix_web_httpauth::middleware::HttpAuthentication;
 ///     }
 /// ```
 /// - `chall_name` - The name of the challenge that is being deployed
+/// - `team_id` - The team an instance is being started/stopped for, only used by `INSTANCE_START`/`INSTANCE_STOP`
+/// - `replicas` - The number of replicas to scale to, only used by `SCALE`
+/// - `target` - The deploy target to scale (e.g. `web`), only used by `SCALE`; every target is scaled if not set
+/// - `dry_run` - Whether `GC` only reports what it would remove, `true` if not set
 #[derive(Deserialize)]
 pub struct Deploy {
     __type : String,
     deploy_identifier: PollingId,
     chall_name: String,
     modifications: Option<Modifications>,
+    team_id: Option<Uuid>,
+    replicas: Option<u16>,
+    target: Option<String>,
+    dry_run: Option<bool>,
 }
 
 /// Generates a Docker and K8s client for use in the deploy server
@@ -75,6 +90,18 @@ async fn generate_clients(meta: Metadata) -> Result<(Docker, Client), Response>
 /// - `REDEPLOY` | `Deploy` - Fully deploys a challenge, or redeploys a challenge if it already exists
 /// - `DELETE` - Deletes a challenge from the cluster and removes local Docker image
 /// - `POLL` - Polls the status of a deployment
+/// - `INSTANCE_START` - Starts a team's own short-lived instance of a deployed challenge
+/// - `INSTANCE_STOP` - Stops a team's instance of a challenge
+/// - `RECONCILE` - Compares the deployed challenges with the cluster and reports (and optionally repairs) any drift
+/// - `UPTIME` - Up/down history of every deployed challenge
+/// - `RETRY_STATS` - How often each kind of operation was retried, recovered or gave up since the server started
+/// - `SCALE` - Changes the number of replicas of a live challenge without redeploying it
+/// - `OUTBOX` - Payloads for the webhook server that are pending or failed to be delivered
+/// - `OUTBOX_REPLAY` - Puts the failed payloads of a challenge (or of every challenge, if none is given) back in line for delivery
+/// - `GC` - Finds (and unless it is a dry run, removes) leftover containers, images and Kubernetes objects of removed challenges
+/// - `LIST_CHALLS` - Folder of every challenge in the challenge repo, including the ones whose chall.yaml is invalid
+/// - `CHALL_INDEX` - Every challenge in the challenge repo (folder, name, categories, targets and files from its chall.yaml),
+///   along with the folders whose chall.yaml is invalid
 /// 
 /// ## Returns
 ///  - `actix_web::web::Json<Response>` - Returns a `actix_web::web::JSON` object returned by the endpoint that was requested. This JSON object ultimately gets sent out as a request response.
@@ -131,9 +158,89 @@ async fn incoming_post(info: web::Json<Deploy>) -> impl Responder {
 
             sync_metadata_with_webhook(&meta, new_yaml).await.wrap()
         },
+        "INSTANCE_START" => {
+            let k8s = match create_client().await {
+                Ok(client) => client,
+                Err(err) => return Response::err_k8s_login(meta, err).wrap(),
+            };
+
+            start_instance(&k8s, meta).await.wrap()
+        },
+        "INSTANCE_STOP" => {
+            let k8s = match create_client().await {
+                Ok(client) => client,
+                Err(err) => return Response::err_k8s_login(meta, err).wrap(),
+            };
+
+            stop_instance(&k8s, meta).await.wrap()
+        },
+        "RECONCILE" => {
+            let k8s = match create_client().await {
+                Ok(client) => client,
+                Err(err) => return Response::err_k8s_login(meta, err).wrap(),
+            };
+
+            match reconcile_once(&k8s).await {
+                Ok(report) => Response::success_reconcile(report).wrap(),
+                Err(e) => Response::err_reconcile(meta, e).wrap(),
+            }
+        },
+        "UPTIME" => {
+            Response::success_uptime(uptime_history()).wrap()
+        },
+        "RETRY_STATS" => {
+            Response::success_retry_stats(retry_stats()).wrap()
+        },
+        "OUTBOX" => {
+            match outbox_entries() {
+                Ok(entries) => Response::success_outbox(entries).wrap(),
+                Err(e) => Response::unknown_ise(meta, e).wrap(),
+            }
+        },
+        "OUTBOX_REPLAY" => {
+            let chall_name = Some(meta.chall_name().as_str()).filter(|name| !name.is_empty());
+
+            if let Err(e) = replay_failed(chall_name) {
+                return Response::unknown_ise(meta, e).wrap();
+            }
+
+            // Deliver the replayed payloads in the background, the admin can follow along with `OUTBOX`
+            tokio::spawn(async {
+                if let Err(e) = deliver_pending().await {
+                    error!("Failed to deliver the replayed webhook payloads: {e}");
+                }
+            });
+
+            match outbox_entries() {
+                Ok(entries) => Response::success_outbox(entries).wrap(),
+                Err(e) => Response::unknown_ise(meta, e).wrap(),
+            }
+        },
+        "SCALE" => {
+            let Some(replicas) = info.0.replicas else {
+                return Response::replicas_missing(meta).wrap();
+            };
+
+            let k8s = match create_client().await {
+                Ok(client) => client,
+                Err(err) => return Response::err_k8s_login(meta, err).wrap(),
+            };
+
+            scale_challenge(&k8s, meta, replicas, info.0.target.as_deref()).await.wrap()
+        },
+        "GC" => {
+            let report = collect_garbage(info.0.dry_run.unwrap_or(true)).await;
+            Response::success_gc(report).wrap()
+        },
         "LIST_CHALLS" => {
-            match crate::server::utils::git::get_all_chall_names(std::path::Path::new(arcs_static::env::chall_folder_default()), &meta) {
-                Ok(chall_names) => Response::success_list_challs(&chall_names).wrap(),
+            match crate::server::utils::git::get_chall_index(std::path::Path::new(arcs_static::env::chall_folder_default()), &meta).await {
+                Ok(index) => Response::success_list_challs(&index.folders()).wrap(),
+                Err(resp) => resp.wrap(),
+            }
+        },
+        "CHALL_INDEX" => {
+            match crate::server::utils::git::get_chall_index(std::path::Path::new(arcs_static::env::chall_folder_default()), &meta).await {
+                Ok(index) => Response::success_chall_index(index).wrap(),
                 Err(resp) => resp.wrap(),
             }
         },
@@ -149,6 +256,12 @@ pub async fn initialize_server() -> std::io::Result<()> {
     let server_ip = deploy_address().strip_prefix("http://").or(deploy_address().strip_prefix("https://")).unwrap();
     let server_port : u16 = port().parse().unwrap();
 
+    spawn_instance_reaper();
+    spawn_reconciler();
+    spawn_uptime_checker();
+    spawn_outbox_worker();
+    spawn_garbage_collector();
+
     info!("Deploy server listening on {}:{}", server_ip, server_port);
 
     HttpServer::new(|| {
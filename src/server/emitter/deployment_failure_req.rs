@@ -1,6 +1,5 @@
 use serde_json::json;
 
-use crate::env::{webhook_address, deploy_token};
 use crate::logging::*;
 use crate::server::responses::Metadata;
 
@@ -29,11 +28,7 @@ async fn send_deployment_failure(
 
     debug!("Sending DeploymentFailure message: {err}");
 
-    let response = client.post(webhook_address())
-        .bearer_auth(deploy_token())
-        .json(&fail_payload)
-        .send()
-        .await;
+    let response = super::outbox::send(client, Some(meta.chall_name().as_str()), "DeploymentFailure", &fail_payload).await;
     trace!("Sent DeploymentSuccess req");
 
     let response = match response {
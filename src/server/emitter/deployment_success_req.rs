@@ -2,17 +2,18 @@ use serde_json::json;
 
 use yaml::YamlShape;
 
-use crate::env::{webhook_address, deploy_token};
 use crate::logging::*;
 use crate::server::responses::Metadata;
 
-async fn send_deployment_success(
-    client: &reqwest::Client,
+use super::outbox::QueuedPayload;
+
+/// Queues the payload creating the deployed challenge, delivered by [`deployment_success_message`][deployment_success_message]
+pub fn queue_deployment_success(
     meta: &Metadata,
     yaml: &YamlShape,
     disc_message: String,
     links: Vec<crate::server::utils::api_types::incoming::Link>,
-) -> Result<reqwest::Response, String> {
+) -> Result<QueuedPayload, String> {
     use crate::server::utils::api_types::incoming::*;
 
     let developer_discord_payload = ToDiscord::Developer(
@@ -64,11 +65,14 @@ async fn send_deployment_success(
     //     sql: None,
     // };
 
-    let response = client.post(webhook_address())
-        .bearer_auth(deploy_token())
-        .json(&success_payload)
-        .send()
-        .await;
+    super::outbox::queue(Some(meta.chall_name().as_str()), "DeploymentSuccess", &success_payload)
+}
+
+async fn send_deployment_success(
+    client: &reqwest::Client,
+    queued: &QueuedPayload,
+) -> Result<reqwest::Response, String> {
+    let response = super::outbox::deliver(client, queued).await;
     trace!("Sent DeploymentSuccess req");
 
     let response = match response {
@@ -131,12 +135,10 @@ async fn handle_deployment_success(
 pub async fn deployment_success_message(
     client: &reqwest::Client,
     meta: &Metadata,
-    yaml: &YamlShape,
-    disc_message: String,
-    links: Vec<crate::server::utils::api_types::incoming::Link>,
+    queued: QueuedPayload,
 ) -> Result<(), String> {
     trace!("Sending DeploymentSuccess message to SQL and Discord server");
 
-    let response = send_deployment_success(client, meta, yaml, disc_message, links).await?;
+    let response = send_deployment_success(client, &queued).await?;
     handle_deployment_success(response, meta).await
 }
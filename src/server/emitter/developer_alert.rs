@@ -0,0 +1,87 @@
+use crate::logging::*;
+use crate::server::utils::api_types::incoming::AlertLevel;
+
+async fn send_developer_alert(
+    client: &reqwest::Client,
+    level: AlertLevel,
+    message: &str,
+    data: serde_json::Value,
+) -> Result<reqwest::Response, String> {
+    use crate::server::utils::api_types::incoming::*;
+
+    let discord_payload = ToDiscord::Developer(
+        DeveloperDiscordMessage {
+            data,
+            level,
+            message: message.to_string(),
+            include_chall_writers: false,
+        }
+    );
+    let alert_payload = Incoming {
+        deploy: None,
+        discord: Some(discord_payload),
+        frontend: None,
+        sql: None,
+    };
+    trace!("Build DeveloperAlert payload");
+
+    debug!("Sending DeveloperAlert message: {message}");
+
+    let response = super::outbox::send(client, None, "DeveloperAlert", &alert_payload).await;
+    trace!("Sent DeveloperAlert req");
+
+    let response = match response {
+        Ok(response) => response,
+        Err(err) => {
+            error!("Error sending DeveloperAlert message to webhook server");
+            error!("Trace: {:#?}", err);
+            return Err("Error sending DeveloperAlert message to webhook server".to_string());
+        }
+    };
+    trace!("Response recieved successfully");
+
+    Ok(response)
+}
+
+async fn handle_developer_alert(
+    response: reqwest::Response,
+) -> Result<(), String> {
+    use crate::server::utils::api_types::outgoing::*;
+
+    let status_code = response.status();
+
+    match response.json::<Outgoing>().await {
+        Ok(response) => {
+            let Some(DiscordResult::Success(_)) = response.discord else {
+                error!("Expected a successful result from Discord, but got none or a bad result");
+                return Err("Discord returned an undexpected result".to_string());
+            };
+
+            if !status_code.is_success() {
+                error!("Despite good results otherwise, status code had an issue");
+                return Err("Status code issue".to_string());
+            }
+        },
+        Err(outgoing_error) => {
+            error!("Error parsing response from webhook server");
+            debug!("Trace: {:#?}", outgoing_error);
+            return Err("Error parsing response from webhook server".to_string());
+        },
+    };
+
+    trace!("Successfully sent DeveloperAlert message to webhook server");
+
+    Ok(())
+}
+
+pub async fn developer_alert_message(
+    client: &reqwest::Client,
+    level: AlertLevel,
+    message: &str,
+    data: serde_json::Value,
+) -> Result<(), String> {
+    trace!("Sending DeveloperAlert message to Discord server");
+
+    let response = send_developer_alert(client, level, message, data).await?;
+    handle_developer_alert(response).await
+}
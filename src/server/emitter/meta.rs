@@ -1,6 +1,5 @@
 use yaml::YamlShape;
 
-use crate::env::{webhook_address, deploy_token};
 use crate::logging::*;
 use crate::server::responses::Metadata;
 
@@ -36,11 +35,7 @@ async fn send_metadata_update(
 
     trace!("Built UpdateMetadata payload");
 
-    let response = client.post(webhook_address())
-        .bearer_auth(deploy_token())
-        .json(&update_metadata_payload)
-        .send()
-        .await;
+    let response = super::outbox::send(client, Some(meta.chall_name().as_str()), "UpdateMetadata", &update_metadata_payload).await;
     trace!("Sent UpdateMetadata req");
 
     let response = match response {
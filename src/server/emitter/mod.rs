@@ -1,21 +1,45 @@
 mod deployment_failure_req;
+mod developer_alert;
 mod deployment_success_req;
 mod meta;
+mod removal;
 mod sync;
+pub mod outbox;
 
 use reqwest::Client;
+use serde::Serialize;
+
+use arcs_retry::retry_if;
+
+use arcs_k8s::config::ExposedPort;
 
 use yaml::deploy::structs::DeployTargetType;
 use yaml::YamlShape;
 
+use crate::env::{ webhook_address, deploy_token };
 use crate::logging::*;
 use crate::server::utils::metadata::*;
 use crate::server::responses::{ Response, Metadata };
 
-use super::utils::api_types::incoming::Link;
+use super::utils::api_types::incoming::{ AlertLevel, Link };
+
 
+/// Whether a failed request to the webhook server is worth sending again, i.e. it never got a response
+fn is_transient_webhook_err(err: &reqwest::Error) -> bool {
+    err.is_timeout() || err.is_connect() || err.is_request()
+}
+
+/// Sends a payload to the webhook server, retrying it if the server couldn't be reached or timed out
+async fn post_to_webhook<T: Serialize + ?Sized>(client: &Client, payload: &T) -> reqwest::Result<reqwest::Response> {
+    retry_if("webhook post", webhook_address(), is_transient_webhook_err, || {
+        client.post(webhook_address())
+            .bearer_auth(deploy_token())
+            .json(payload)
+            .send()
+    }).await
+}
 
-async fn get_deployment_success_info(meta: &Metadata, ports: &Option<Vec<(DeployTargetType, Vec<i32>)>>) -> Result<(YamlShape, String, Vec<Link>), String> {
+async fn get_deployment_success_info(meta: &Metadata, ports: &Option<Vec<(DeployTargetType, Vec<ExposedPort>)>>) -> Result<(YamlShape, String, Vec<Link>), String> {
     // Get YAML for sending challenge metadata
     let yaml_file = get_yaml_shape(meta).await?;
     debug!("Have a yaml: {yaml_file:#?}");
@@ -46,18 +70,22 @@ async fn get_deployment_success_info(meta: &Metadata, ports: &Option<Vec<(Deploy
     Ok((yaml_file, disc_message, links))
 }
 
-pub async fn send_deployment_success(meta: &Metadata, ports: Option<Vec<(DeployTargetType, Vec<i32>)>>) -> Result<(), String> {
+pub async fn send_deployment_success(meta: &Metadata, ports: Option<Vec<(DeployTargetType, Vec<ExposedPort>)>>) -> Result<(), String> {
     // reqwest client for contacting the webhook server
     let client = Client::new();
     
     // Get deployment success info
     let (yaml_file, disc_message, links) = get_deployment_success_info(meta, &ports).await?;
 
+    // Both payloads are queued first, so the frontend sync isn't lost if the webhook server can't be reached
+    let success = deployment_success_req::queue_deployment_success(meta, &yaml_file, disc_message, links)?;
+    let frontend_sync = sync::queue_frontend_sync(meta)?;
+
     // Create chall on webhook and send discord message
-    deployment_success_req::deployment_success_message(&client, meta, &yaml_file, disc_message, links).await?;
+    deployment_success_req::deployment_success_message(&client, meta, success).await?;
 
     // Tell frontend to sync the new chall data
-    sync::frontend_sync_message(&client, meta).await?;
+    sync::frontend_sync_message(&client, meta, frontend_sync).await?;
 
     Ok(())
 }
@@ -70,6 +98,31 @@ pub async fn send_deployment_failure(meta: &Metadata, err: String) -> Result<(),
     deployment_failure_req::deployment_failure_message(&client, meta, &err).await
 }
 
+pub async fn send_chall_removal(meta: &Metadata) -> Result<(), String> {
+    // reqwest client for contacting the webhook server
+    let client = Client::new();
+
+    // Both payloads are queued first, so the frontend sync isn't lost if the webhook server can't be reached
+    let chall_removal = removal::queue_chall_removal(meta)?;
+    let frontend_sync = sync::queue_frontend_sync(meta)?;
+
+    // Hide the chall on the webhook and send discord message
+    removal::chall_removal_message(&client, meta, chall_removal).await?;
+
+    // Tell frontend to sync the removed chall
+    sync::frontend_sync_message(&client, meta, frontend_sync).await?;
+
+    Ok(())
+}
+
+pub async fn send_developer_alert(level: AlertLevel, message: &str, data: serde_json::Value) -> Result<(), String> {
+    // reqwest client for contacting the webhook server
+    let client = Client::new();
+
+    // Send discord message
+    developer_alert::developer_alert_message(&client, level, message, data).await
+}
+
 pub async fn sync_metadata_with_webhook(meta: &Metadata, new_yaml: YamlShape) -> Response {
     // reqwest client for contacting the webhook server
     let client = Client::new();
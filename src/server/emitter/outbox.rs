@@ -0,0 +1,334 @@
+use futures::future::join_all;
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{ Arc, Mutex, MutexGuard, PoisonError };
+use std::time::{ Duration, SystemTime, UNIX_EPOCH };
+use reqwest::Client;
+use serde::{ Deserialize, Serialize };
+
+use crate::deploy_records::state_file_path;
+use crate::env::outbox_retry_interval;
+use crate::logging::*;
+
+const OUTBOX_FILE: &str = "webhook-outbox.json";
+
+const DEFAULT_RETRY_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Number of failed deliveries after which a payload is marked as failed and has to be replayed by an admin
+const MAX_DELIVERY_ATTEMPTS: u32 = 20;
+
+/// A payload for the webhook server that hasn't been delivered yet
+///
+/// Payloads are delivered in order per challenge: a payload is only sent once every earlier payload of the same
+/// challenge was delivered, so a failed payload holds back the ones queued after it.
+///
+/// ## Fields
+/// - `id` - Position of the payload in the outbox, increasing with every payload queued
+/// - `chall_name` - Challenge the payload is about, `None` for developer alerts that aren't about a challenge
+/// - `kind` - What the payload is, e.g. `DeploymentSuccess`
+/// - `payload` - The serialized `Incoming` payload
+/// - `queued_at` - Unix timestamp of when the payload was queued
+/// - `attempts` - Number of failed deliveries so far
+/// - `last_error` - Why the last delivery failed
+/// - `failed` - Whether the payload gave up on being delivered, until it's replayed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutboxEntry {
+    pub id: u64,
+    pub chall_name: Option<String>,
+    pub kind: String,
+    pub payload: serde_json::Value,
+    pub queued_at: u64,
+    pub attempts: u32,
+    pub last_error: Option<String>,
+    pub failed: bool,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Outbox {
+    next_id: u64,
+    entries: Vec<OutboxEntry>,
+}
+
+lazy_static! {
+    /// Serializes reads and writes of the outbox file
+    static ref OUTBOX_LOCK: Mutex<()> = Mutex::new(());
+
+    /// Makes sure only one delivery runs at a time per challenge, so no payload is sent twice or out of order, while a
+    /// challenge whose payloads can't be delivered doesn't hold back the payloads of other challenges
+    static ref DELIVERY_LOCKS: Mutex<HashMap<Option<String>, Arc<tokio::sync::Mutex<()>>>> = Mutex::new(HashMap::new());
+}
+
+fn outbox_lock() -> MutexGuard<'static, ()> {
+    OUTBOX_LOCK.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Lock held while delivering the payloads of a challenge (or the developer alerts, if `chall_name` is `None`)
+fn delivery_lock(chall_name: Option<&str>) -> Arc<tokio::sync::Mutex<()>> {
+    DELIVERY_LOCKS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .entry(chall_name.map(str::to_string))
+        .or_default()
+        .clone()
+}
+
+/// How often undelivered payloads are retried, set with `OUTBOX_RETRY_INTERVAL_SECONDS`
+fn interval() -> Duration {
+    outbox_retry_interval()
+        .and_then(|secs| secs.parse().ok())
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_RETRY_INTERVAL)
+}
+
+fn outbox_path() -> PathBuf {
+    state_file_path(OUTBOX_FILE)
+}
+
+fn read_outbox() -> Result<Outbox, String> {
+    let path = outbox_path();
+
+    let text = match std::fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Outbox::default()),
+        Err(e) => return Err(format!("Failed to read webhook outbox @ {path:?}: {e}")),
+    };
+
+    serde_json::from_str(&text).map_err(|e| format!("Failed to parse webhook outbox @ {path:?}: {e}"))
+}
+
+fn write_outbox(outbox: &Outbox) -> Result<(), String> {
+    let path = outbox_path();
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| format!("Failed to create deploy state folder {parent:?}: {e}"))?;
+    }
+
+    let text = serde_json::to_string_pretty(outbox).map_err(|e| format!("Failed to serialize webhook outbox: {e}"))?;
+
+    // Write to a temporary file first so a crash mid-write can't lose every queued payload
+    let tmp_path = path.with_extension("json.tmp");
+    std::fs::write(&tmp_path, text).map_err(|e| format!("Failed to write webhook outbox @ {tmp_path:?}: {e}"))?;
+    std::fs::rename(&tmp_path, &path).map_err(|e| format!("Failed to replace webhook outbox @ {path:?}: {e}"))
+}
+
+/// Applies `update` to the outbox and persists the result
+fn update_outbox<T>(update: impl FnOnce(&mut Outbox) -> T) -> Result<T, String> {
+    let _lock = outbox_lock();
+
+    let mut outbox = read_outbox()?;
+    let result = update(&mut outbox);
+    write_outbox(&outbox)?;
+
+    Ok(result)
+}
+
+fn now_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+/// Every payload that hasn't been delivered yet, oldest first
+pub fn outbox_entries() -> Result<Vec<OutboxEntry>, String> {
+    let _lock = outbox_lock();
+    Ok(read_outbox()?.entries)
+}
+
+/// Puts the failed payloads of a challenge (or of every challenge if `chall_name` is `None`) back in line for delivery
+///
+/// ## Returns
+/// - `Ok(usize)` - Number of payloads that were replayed
+/// - `Err(String)` - The outbox couldn't be read or written
+pub fn replay_failed(chall_name: Option<&str>) -> Result<usize, String> {
+    update_outbox(|outbox| {
+        let mut replayed = 0;
+        for entry in outbox.entries.iter_mut().filter(|entry| entry.failed) {
+            if chall_name.is_some() && entry.chall_name.as_deref() != chall_name {
+                continue;
+            }
+
+            info!("Replaying {} #{} to the webhook server", entry.kind, entry.id);
+            entry.failed = false;
+            entry.attempts = 0;
+            replayed += 1;
+        }
+        replayed
+    })
+}
+
+fn enqueue(chall_name: Option<&str>, kind: &str, payload: serde_json::Value) -> Result<u64, String> {
+    update_outbox(|outbox| {
+        let id = outbox.next_id;
+        outbox.next_id += 1;
+
+        trace!("Queueing {kind} #{id} for the webhook server");
+        outbox.entries.push(OutboxEntry {
+            id,
+            chall_name: chall_name.map(str::to_string),
+            kind: kind.to_string(),
+            payload,
+            queued_at: now_timestamp(),
+            attempts: 0,
+            last_error: None,
+            failed: false,
+        });
+        id
+    })
+}
+
+/// Records a failed delivery of a payload, marking it as failed if it won't get any better by retrying
+fn record_failure(id: u64, error: &str, permanent: bool) -> Result<(), String> {
+    update_outbox(|outbox| {
+        let Some(entry) = outbox.entries.iter_mut().find(|entry| entry.id == id) else { return };
+
+        entry.attempts += 1;
+        entry.last_error = Some(error.to_string());
+        entry.failed = permanent || entry.attempts >= MAX_DELIVERY_ATTEMPTS;
+
+        if entry.failed {
+            error!("Gave up delivering {} #{} to the webhook server after {} attempt(s): {error}", entry.kind, id, entry.attempts);
+        }
+    })
+}
+
+/// Delivers the payloads of a single challenge in order, until `until` is delivered (or every payload if `None`)
+///
+/// ## Returns
+/// - `Ok(Some(reqwest::Response))` - The webhook server's response to `until`
+/// - `Ok(None)` - Every payload of the challenge was delivered
+/// - `Err(String)` - A payload couldn't be delivered, it and every payload after it are kept for later
+async fn deliver_queue(client: &Client, chall_name: Option<&str>, until: Option<u64>) -> Result<Option<reqwest::Response>, String> {
+    let lock = delivery_lock(chall_name);
+    let _delivery = lock.lock().await;
+
+    loop {
+        let next = outbox_entries()?
+            .into_iter()
+            .find(|entry| entry.chall_name.as_deref() == chall_name);
+
+        let Some(entry) = next else { return Ok(None) };
+
+        if entry.failed {
+            return Err(format!("{} #{} failed to be delivered and is holding back the payloads after it, replay it first", entry.kind, entry.id));
+        }
+
+        debug!("Delivering {} #{} to the webhook server", entry.kind, entry.id);
+        let response = match super::post_to_webhook(client, &entry.payload).await {
+            Ok(response) if response.status().is_success() => response,
+            Ok(response) => {
+                // The server won't accept a payload it rejected on a retry, only on an error of its own
+                let status = response.status();
+                let error = format!("Webhook server responded with {status}");
+                record_failure(entry.id, &error, status.is_client_error() && status != reqwest::StatusCode::TOO_MANY_REQUESTS)?;
+                return Err(error);
+            },
+            Err(e) => {
+                let error = format!("Failed to reach the webhook server: {e}");
+                record_failure(entry.id, &error, false)?;
+                return Err(error);
+            },
+        };
+
+        update_outbox(|outbox| outbox.entries.retain(|queued| queued.id != entry.id))?;
+        trace!("Delivered {} #{} to the webhook server", entry.kind, entry.id);
+
+        if until == Some(entry.id) {
+            return Ok(Some(response));
+        }
+    }
+}
+
+/// A payload that was put in the outbox by [`queue`][queue]
+///
+/// ## Fields
+/// - `id` - Position of the payload in the outbox
+/// - `chall_name` - Challenge the payload is about, `None` for developer alerts
+/// - `kind` - What the payload is, e.g. `DeploymentSuccess`
+#[derive(Debug, Clone)]
+pub struct QueuedPayload {
+    pub id: u64,
+    pub chall_name: Option<String>,
+    pub kind: String,
+}
+
+/// Puts a payload for the webhook server in the outbox, behind every payload of the same challenge queued before it
+///
+/// Once queued, the payload is delivered by [`deliver`][deliver] or, if that doesn't happen or fails, in the background.
+/// Payloads that belong together should all be queued before any of them is delivered, so a webhook server that is down
+/// can't keep the later ones from being queued.
+pub fn queue<T: Serialize + ?Sized>(chall_name: Option<&str>, kind: &str, payload: &T) -> Result<QueuedPayload, String> {
+    let payload = serde_json::to_value(payload).map_err(|e| format!("Failed to serialize {kind} payload: {e}"))?;
+    let id = enqueue(chall_name, kind, payload)?;
+
+    Ok(QueuedPayload { id, chall_name: chall_name.map(str::to_string), kind: kind.to_string() })
+}
+
+/// Delivers a queued payload, after every payload of the same challenge queued before it
+///
+/// If it can't be delivered now, the payload stays in the outbox and is retried in the background.
+///
+/// ## Returns
+/// - `Ok(reqwest::Response)` - The webhook server's response to the payload
+/// - `Err(String)` - The payload wasn't delivered yet
+pub async fn deliver(client: &Client, queued: &QueuedPayload) -> Result<reqwest::Response, String> {
+    let QueuedPayload { id, chall_name, kind } = queued;
+
+    match deliver_queue(client, chall_name.as_deref(), Some(*id)).await {
+        Ok(Some(response)) => Ok(response),
+        Ok(None) => Err(format!("{kind} #{id} is no longer in the outbox")),
+        Err(e) => {
+            warn!("{kind} #{id} was kept in the outbox for a later delivery: {e}");
+            Err(e)
+        },
+    }
+}
+
+/// Queues a payload for the webhook server and delivers it right away, see [`queue`][queue] and [`deliver`][deliver]
+///
+/// ## Returns
+/// - `Ok(reqwest::Response)` - The webhook server's response to the payload
+/// - `Err(String)` - The payload couldn't be queued or wasn't delivered yet
+pub async fn send<T: Serialize + ?Sized>(client: &Client, chall_name: Option<&str>, kind: &str, payload: &T) -> Result<reqwest::Response, String> {
+    let queued = queue(chall_name, kind, payload)?;
+    deliver(client, &queued).await
+}
+
+/// Tries to deliver every payload in the outbox, each challenge's payloads in order
+pub async fn deliver_pending() -> Result<(), String> {
+    let client = Client::new();
+
+    let mut queues: Vec<Option<String>> = vec![];
+    for entry in outbox_entries()? {
+        if !entry.failed && !queues.contains(&entry.chall_name) {
+            queues.push(entry.chall_name);
+        }
+    }
+
+    // Every challenge is delivered on its own, so one that keeps failing doesn't delay the others
+    join_all(queues.iter().map(|chall_name| async {
+        if let Err(e) = deliver_queue(&client, chall_name.as_deref(), None).await {
+            warn!("Failed to deliver payloads of {} to the webhook server: {e}", chall_name.as_deref().unwrap_or("developer alerts"));
+        }
+    })).await;
+
+    Ok(())
+}
+
+/// Spawns a Tokio task that periodically retries every payload that couldn't be delivered to the webhook server
+pub fn spawn_outbox_worker() {
+    info!("Starting webhook outbox worker (interval {:?})", interval());
+
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval());
+
+        loop {
+            ticker.tick().await;
+
+            if let Err(e) = deliver_pending().await {
+                error!("Failed to deliver the webhook outbox: {e}");
+            }
+        }
+    });
+}
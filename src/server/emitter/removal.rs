@@ -0,0 +1,112 @@
+use crate::logging::*;
+use crate::server::responses::Metadata;
+
+use super::outbox::QueuedPayload;
+
+/// Queues the payload hiding the removed challenge, delivered by [`chall_removal_message`][chall_removal_message]
+pub fn queue_chall_removal(
+    meta: &Metadata,
+) -> Result<QueuedPayload, String> {
+    use crate::server::utils::api_types::incoming::*;
+
+    // The challenge is hidden rather than dropped, so solves and history stay intact
+    let sql_payload = ToSql::Chall(ChallQuery::Update {
+        id: meta.poll_id(),
+
+        visible: Some(false),
+        links: Some(vec![]),
+
+        name: None,
+        description: None,
+        points: None,
+        categories: None,
+        tags: None,
+        authors: None,
+        hints: None,
+        source_folder: None,
+    });
+
+    let developer_discord_payload = ToDiscord::Developer(
+        DeveloperDiscordMessage {
+            data: serde_json::json!({}),
+            include_chall_writers: false,
+            level: AlertLevel::Info,
+            message: format!("**{}** was deleted", meta.chall_name()),
+        }
+    );
+
+    let removal_payload = Incoming {
+        deploy: None,
+        discord: Some(developer_discord_payload),
+        frontend: None,
+        sql: Some(sql_payload),
+    };
+    trace!("Built ChallRemoval payload");
+
+    super::outbox::queue(Some(meta.chall_name().as_str()), "ChallRemoval", &removal_payload)
+}
+
+async fn send_chall_removal(
+    client: &reqwest::Client,
+    queued: &QueuedPayload,
+) -> Result<reqwest::Response, String> {
+    let response = super::outbox::deliver(client, queued).await;
+    trace!("Sent ChallRemoval req");
+
+    let response = match response {
+        Ok(response) => response,
+        Err(err) => {
+            error!("Error sending ChallRemoval message to webhook server");
+            error!("Trace: {:#?}", err);
+            return Err("Error sending ChallRemoval message to webhook server".to_string());
+        }
+    };
+    trace!("Response recieved successfully");
+
+    Ok(response)
+}
+
+async fn handle_chall_removal(
+    response: reqwest::Response,
+    meta: &Metadata,
+) -> Result<(), String> {
+    use crate::server::utils::api_types::outgoing::*;
+
+    let status_code = response.status();
+
+    match response.json::<Outgoing>().await {
+        Ok(response) => {
+            let Some(SqlResult::Success(FromSql::Chall(chall))) = response.sql else {
+                error!("Expected a hidden challenge from the SQL server, but got none, a bad result, or non-chall result");
+                return Err("SQL server returned an unexpected response".to_string());
+            };
+            if chall.id != meta.poll_id() {
+                error!("SQL Server return type did not have the same ID as the poll ID");
+                return Err("SQL Server returned a challenge with a different ID than the poll ID".to_string());
+            }
+
+            if !status_code.is_success() {
+                error!("Despite good results otherwise, status code had an issue");
+                return Err("Status code issue".to_string());
+            }
+        },
+        Err(outgoing_err) => {
+            error!("Error parsing response from webhook server");
+            debug!("Error: {:#?}", outgoing_err);
+            return Err("Error parsing response from webhook server".to_string());
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn chall_removal_message(
+    client: &reqwest::Client,
+    meta: &Metadata,
+    queued: QueuedPayload,
+) -> Result<(), String> {
+    trace!("Sending ChallRemoval message to SQL and Discord server");
+
+    let response = send_chall_removal(client, &queued).await?;
+    handle_chall_removal(response, meta).await
+}
@@ -1,11 +1,12 @@
-use crate::env::{webhook_address, deploy_token};
 use crate::logging::*;
 use crate::server::responses::Metadata;
 
-async fn send_frontend_sync(
-    client: &reqwest::Client,
+use super::outbox::QueuedPayload;
+
+/// Queues the payload telling the frontend to sync the challenge, delivered by [`frontend_sync_message`][frontend_sync_message]
+pub fn queue_frontend_sync(
     meta: &Metadata,
-) -> Result<reqwest::Response, String> {
+) -> Result<QueuedPayload, String> {
     use crate::server::utils::api_types::incoming::*;
 
     let chall_id = meta.poll_id();
@@ -19,11 +20,14 @@ async fn send_frontend_sync(
     };
     trace!("Build sync payload");
 
-    let response = client.post(webhook_address())
-        .bearer_auth(deploy_token())
-        .json(&sync_payload)
-        .send()
-        .await;
+    super::outbox::queue(Some(meta.chall_name().as_str()), "FrontendSync", &sync_payload)
+}
+
+async fn send_frontend_sync(
+    client: &reqwest::Client,
+    queued: &QueuedPayload,
+) -> Result<reqwest::Response, String> {
+    let response = super::outbox::deliver(client, queued).await;
     trace!("Sent DeploymentSuccess req");
 
     let response = match response {
@@ -82,9 +86,10 @@ async fn handle_frontend_sync(
 pub async fn frontend_sync_message(
     client: &reqwest::Client,
     meta: &Metadata,
+    queued: QueuedPayload,
 ) -> Result<(), String> {
     trace!("Sending SyncDeploy message to Frontend server");
 
-    let response = send_frontend_sync(client, meta).await?;
+    let response = send_frontend_sync(client, &queued).await?;
     handle_frontend_sync(response, meta).await
 }
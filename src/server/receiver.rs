@@ -1,13 +1,18 @@
-use std::path::Path;
+use std::collections::{ BTreeMap, BTreeSet };
+use std::path::{ Path, PathBuf };
+use std::time::{ Duration, Instant };
 
-use arcs_docker::{ build_image, delete_image as delete_docker_image, push_image, pull_image };
-use arcs_k8s::{ create_challenge as create_full_k8s_deployment, delete_challenge as delete_k8s_challenge, get_chall_folder };
-use arcs_static::deploy_static_files;
+use futures::stream::{ self, StreamExt };
+
+use arcs_docker::{ BuildConfig, BuilderBackend, BuiltImage, build_all_images, builder_backend, build_image, delete_file_containers, delete_image as delete_docker_image, image_builder, push_image, pull_image };
+use arcs_k8s::{ client_for_cluster, K8sError, create_challenge as create_full_k8s_deployment, delete_chall_objects, delete_challenge as delete_k8s_challenge, scale_target, config::{ ExposedPort, TargetConfig } };
+use arcs_k8s::instance::{ create_instance, instance_name };
+use arcs_static::{ delete_static_files, deploy_static_files };
 
 use arcs_static::env::chall_folder_default;
 use yaml_editor::Modifications;
 use yaml::{
-    deploy::structs::{DeployTarget, DeployTargetType}, YamlShape
+    deploy::structs::DeployTargetType, YamlShape
 };
 
 
@@ -17,61 +22,106 @@ use super::responses::{ Metadata, Response };
 
 use crate::{emitter::send_deployment_failure, server::utils::{
     errors::DeployProcessErr,
-    git::{ ensure_repo_up_to_date, make_commit, push_all },
-    state_management::{ advance_with_fail_log, send_failure_message },
-    yaml::{ handle_yaml_get, update_yaml_file },
+    git::{ ensure_repo_up_to_date, head_commit, make_commit, push_all },
+    state_management::{ advance_with_fail_log, reset_step_with_fail_log, restart_steps_with_fail_log, send_failure_message },
+    yaml::{ handle_yaml_get, load_chall_yaml, update_yaml_file },
 }};
-use crate::emitter::send_deployment_success;
+use crate::emitter::{ send_chall_removal, send_deployment_success };
+use crate::deploy_records::{ DeployRecord, TargetRecord, load_deploy_records, mark_deleting, remove_deploy_record, save_deploy_record, update_target_replicas };
+use crate::instances::{ InstanceResource, ReserveErr, activate_instance, release_chall_instances, release_instance, reserve_instance, restore_instance, tear_down };
+use crate::server::utils::metadata::{ container_links::links_from_port_listing, links::into_webhook_links };
+use crate::discovery::discover_challs;
 use crate::logging::*;
-use crate::polling::{ PollingId, register_chall_deployment, fail_deployment, succeed_deployment, deregister_id };
+use crate::polling::{ DeployFailure, DeployStep, PollingId, TargetBuildTime, register_chall_deployment, fail_deployment, succeed_deployment, deregister_id, record_build_time, report_push_progress, clear_push_progress };
+use crate::env::max_parallel_builds;
 
 // TODO --> Add function to deploy everything, 
 // initial deployments to k8s clusters & general instance management
 // (this may be done through ansible but setting up cluster as well)
 
-pub async fn build_challenge(docker: &Docker, name: &String, inner_path: Option<&Path>, polling_id: PollingId) -> Result<(), DeployProcessErr> {
+/// Builds the image of a challenge (or of one of its deploy targets) with the builder set by `IMAGE_BUILDER`, returning the
+/// version tag of the build and the warnings of its pre-build checks
+pub async fn build_challenge(docker: &Docker, name: &String, inner_path: Option<&Path>, build_config: &BuildConfig, polling_id: PollingId) -> Result<BuiltImage, DeployProcessErr> {
     info!("Starting build; name: {name} poll_id: {polling_id}");
-    build_image(docker, name.as_str(), inner_path).await.map_err(DeployProcessErr::Build)
+    let commit = head_commit(Path::new(chall_folder_default()));
+    build_image(image_builder(docker).as_ref(), name.as_str(), inner_path, commit.as_deref(), build_config).await.map_err(DeployProcessErr::Build)
+}
+
+/// Builds the image of every deploy target of every challenge in the challenge repo, as found by the challenge index
+///
+/// Challenges whose chall.yaml doesn't parse are skipped.
+pub async fn build_all_challs(docker: &Docker) -> Result<String, String> {
+    let index = discover_challs(Path::new(chall_folder_default())).map_err(|e| {
+        error!("Failed to index the challenge repo: {e}");
+        format!("Failed to index the challenge repo: {e}")
+    })?;
+
+    build_all_images(image_builder(docker).as_ref(), &index.images()).await.map_err(|e| {
+        error!("Failed to build all challenges: {e}");
+        e.to_string()
+    })
 }
 
-pub async fn push_challenge(docker: &Docker, name: &String, inner_path: Option<&Path>, polling_id: PollingId) -> Result<(), DeployProcessErr> {
-    info!("Starting push; name: {name} poll_id: {polling_id}");
-    push_image(docker, name, inner_path).await.map_err(DeployProcessErr::Push)
+/// Build config of a deploy target's image, from the `build` options of the target in the chall.yaml
+fn target_build_config(config: &TargetConfig) -> BuildConfig {
+    BuildConfig {
+        dockerfile: config.build.dockerfile.clone(),
+        args: config.build.args.clone(),
+        flag_arg: config.build.flag_arg.clone(),
+        target: config.build.target.clone(),
+        labels: config.build.labels.clone(),
+        no_cache: config.build.no_cache,
+    }
 }
 
-pub async fn pull_challenge(docker: &Docker, name: &String, inner_path: Option<&Path>, polling_id: PollingId) -> Result<(), DeployProcessErr> {
-    info!("Starting pull; name: {name} poll_id: {polling_id}");
-    pull_image(docker, name, inner_path).await.map_err(DeployProcessErr::Pull)
+pub async fn push_challenge(docker: &Docker, name: &String, inner_path: Option<&Path>, version: &str, polling_id: PollingId) -> Result<Option<String>, DeployProcessErr> {
+    info!("Starting push; name: {name} version: {version} poll_id: {polling_id}");
+    let pushed = push_image(image_builder(docker).as_ref(), name, inner_path, version, |progress| report_push_progress(polling_id, progress)).await;
+    clear_push_progress(polling_id);
+
+    pushed.map_err(DeployProcessErr::Push)
+}
+
+pub async fn pull_challenge(docker: &Docker, name: &String, inner_path: Option<&Path>, version: &str, pushed_digest: Option<&str>, polling_id: PollingId) -> Result<Option<String>, DeployProcessErr> {
+    info!("Starting pull; name: {name} version: {version} poll_id: {polling_id}");
+    pull_image(image_builder(docker).as_ref(), name, inner_path, Some(version), pushed_digest).await.map_err(DeployProcessErr::Pull)
 }
 
 // may want to move the other two functions into this one and just call this when user asks for deploy/redeploy
 // response message is port challenge is running on (or if it's not running, No Port Returned)
 
+/// Pulls the `version` of a deploy target's image (checking it's the one that was just pushed) and deploys it to its
+/// cluster, pinned to the image's digest
+/// 
+/// ## Returns
+/// - `Ok((Vec<ExposedPort>, Option<String>))` - Ports the target is exposed on and the digest of the deployed image
+/// - `Err(DeployProcessErr)` - The pull or the deployment failed
 pub async fn deploy_challenge(
     docker: &Docker,
     k8s: &Client,
-    name: &String,
-    chall_folder_path: Option<&str>,
-    inner_path: Option<&Path>,
+    config: &TargetConfig,
+    version: &str,
+    pushed_digest: Option<&str>,
     polling_id: PollingId,
-) -> Result<Vec<i32>, DeployProcessErr> {
+) -> Result<(Vec<ExposedPort>, Option<String>), DeployProcessErr> {
+    let name = &config.chall_name;
     info!("Deploying {} to Kubernetes cluster...", name);
 
-    let chall_folder = get_chall_folder(chall_folder_path);
+    let digest = pull_challenge(docker, name, config.build_path(), version, pushed_digest, polling_id).await?;
+    let config = &TargetConfig { image_digest: digest.clone(), ..config.clone() };
 
-    pull_challenge(docker, name, inner_path, polling_id).await?;
+    let k8s = client_for_cluster(k8s, config.cluster.as_deref()).await.map_err(DeployProcessErr::Deploy)?;
     
-    // FIXME --> Update k8s to use the inner_paths as well
-    match create_full_k8s_deployment(k8s, vec![name], Some(&chall_folder)).await {
+    match create_full_k8s_deployment(&k8s, config).await {
         Ok(ports) => {
             if ports.is_empty() { 
                 error!("Error deploying {} ({polling_id}) to k8s cluster", name);
                 error!("No Port Returned");
 
-                Err(DeployProcessErr::Deploy("No Port(s) Returned".into()))
+                Err(DeployProcessErr::Deploy(K8sError::MissingNodePorts(config.resource_name())))
             } else {
                 info!("Successfully deployed {name} ({polling_id}) to port(s): {ports:?}");
-                Ok(ports)
+                Ok((ports, digest))
             }
         }
         Err(s) => {
@@ -82,39 +132,115 @@ pub async fn deploy_challenge(
     }
 }
 
-// FIXME: Deprecation bad.
+/// Tears down everything that was created for a challenge
+/// 
+/// That is every team instance and deploy target on every cluster the challenge was deployed to (along with their
+/// secrets, config maps and autoscalers), the `file.*` helper containers and the image of every target, the files
+/// uploaded to the bucket and the challenge's deploy record. The webhook server is then told the challenge is gone.
+/// 
+/// ## Returns
+/// - `Response` - Success if everything was deleted, error trace of the first step that failed otherwise
 pub async fn delete_challenge(docker: &Docker, client: &Client, meta: Metadata) -> Response {
-    let name = meta.chall_name();
+    let name = meta.chall_name().clone();
     
     warn!("Deleting {}...", name);
 
-    // TODO: Use the variables! (better logs please)
-    match delete_k8s_challenge(client, vec![name.as_str()]).await {
-        Ok(_) => {
-            info!("Successfully deleted {} from Kubernetes cluster", name);
-            "Success deleting Kubernetes deployment/service".to_string()
-        },
+    // Marked before anything is torn down, so the reconciler doesn't recreate the targets that are already gone
+    let record = match mark_deleting(&name) {
+        Ok(record) => record,
         Err(e) => {
-            error!("Error deleting {} from Kubernetes cluster", name);
-            error!("Trace: {}", e);
-            return Response::err_k8s_del(meta, e); 
-        } 
+            error!("Failed to mark deploy record as deleting, only deleting the targets in the chall.yaml of {name}: {e}");
+            None
+        },
     };
-    // TODO --> add delete docker container so things delete properly
-
-    // TODO: Use the variables! (better logs please)
-    // FIXME: make this use an actual inner_path
-    #[allow(unused_variables)]
-    match delete_docker_image(docker, name, None).await {
-        Ok(v) => {
-            info!("Successfully deleted {} from Docker", name);
-            "Success deleting Docker image".to_string()
+
+    let configs = match load_chall_yaml(&name).await {
+        Some(Ok(chall_yaml)) => chall_yaml.target_configs(&name),
+        _ => {
+            warn!("Couldn't read chall.yaml of {name}, only deleting its recorded targets");
+            vec![]
         },
+    };
+
+    // Resource names known for each cluster the challenge might be on, the bare name covers old single target deployments
+    let mut k8s_deletions: BTreeMap<Option<String>, BTreeSet<String>> = BTreeMap::new();
+    k8s_deletions.entry(None).or_default().insert(name.clone());
+    for target in record.iter().flat_map(|record| record.targets.iter()) {
+        k8s_deletions.entry(target.cluster.clone()).or_default().insert(target.resource_name.clone());
+    }
+    for (_, config) in &configs {
+        k8s_deletions.entry(config.cluster.clone()).or_default().insert(config.resource_name());
+    }
+
+    for instance in release_chall_instances(&name) {
+        info!("Stopping instance of {name} for team {} before deleting it", instance.team_id);
+        clean_up_instance(client, &instance.resources).await;
+    }
+
+    for (cluster, resource_names) in &k8s_deletions {
+        let cluster_client = match client_for_cluster(client, cluster.as_deref()).await {
+            Ok(cluster_client) => cluster_client,
+            Err(e) => return Response::err_k8s_del(meta, e),
+        };
+
+        // Labelled objects are found even if their target was removed from the chall.yaml since
+        let deletion = match delete_chall_objects(&cluster_client, &name).await {
+            Ok(_) => delete_k8s_challenge(&cluster_client, resource_names.iter().map(String::as_str).collect()).await,
+            Err(e) => Err(e),
+        };
+
+        if let Err(e) = deletion {
+            error!("Error deleting {} from Kubernetes cluster ({})", name, cluster.as_deref().unwrap_or("default cluster"));
+            error!("Trace: {}", e);
+            return Response::err_k8s_del(meta, e);
+        }
+    }
+    info!("Successfully deleted {} from Kubernetes cluster(s)", name);
+
+    // Helper containers keep the image in use, so they have to go first
+    match delete_file_containers(docker, &name).await {
+        Ok(0) => trace!("No helper containers of {name} to delete"),
+        Ok(removed) => info!("Removed {removed} helper container(s) of {name}"),
         Err(e) => {
-            error!("Error deleting {} from Docker: {e:?}", name);
+            error!("Error deleting helper containers of {} from Docker: {e:?}", name);
             return Response::err_docker_del(meta, e);
-        } 
-    };
+        },
+    }
+
+    let mut inner_paths: Vec<Option<PathBuf>> = configs
+        .iter()
+        .map(|(_, config)| config.build_path.clone())
+        .collect();
+    inner_paths.push(None);
+    inner_paths.sort();
+    inner_paths.dedup();
+
+    // The rootless builder keeps its images in its own storage, there may not even be a daemon
+    if builder_backend() == BuilderBackend::Docker {
+        for inner_path in &inner_paths {
+            if let Err(e) = delete_docker_image(docker, &name, inner_path.as_deref()).await {
+                error!("Error deleting {} ({inner_path:?}) from Docker: {e:?}", name);
+                return Response::err_docker_del(meta, e);
+            }
+        }
+        info!("Successfully deleted {} from Docker", name);
+    }
+
+    match delete_static_files(&name).await {
+        Ok(deleted) => info!("Deleted {deleted} uploaded file(s) of {name}"),
+        Err(e) => {
+            error!("Error deleting uploaded files of {name}: {e}");
+            return Response::err_static_del(meta, e);
+        },
+    }
+
+    if let Err(e) = remove_deploy_record(&name) {
+        error!("Failed to remove deploy record for {name}: {e}");
+    }
+
+    if let Err(e) = send_chall_removal(&meta).await {
+        error!("Failed to tell the webhook server {name} was deleted: {e}");
+    }
 
     debug!("Deleted '{name}'");
     Response::success_remove(meta)
@@ -126,20 +252,25 @@ async fn build_static(docker: &Docker, meta: &Metadata) -> Result<(), String> {
     let polling_id = meta.poll_id();
     let name = meta.chall_name().clone();
 
-    if let Err(build_err) = build_challenge(docker, &name, None, polling_id).await {
-        error!("Failed to build static file container for `{name}` ({polling_id}) with err {build_err:?}");
-        if fail_deployment(polling_id, build_err.to_string()).is_err() {
-            error!("`fail_deployment` failed to mark polling id {polling_id} as errored");
-        }
-        send_failure_message(&meta, "Build Static Container").await;
-        return Err(build_err.to_string());
-    }
+    if !restart_steps_with_fail_log(polling_id) { return Err("Failed to reset status to building".to_string()); }
+
+    let version = match build_challenge(docker, &name, None, &BuildConfig::default(), polling_id).await {
+        Ok(built) => built.version,
+        Err(build_err) => {
+            error!("Failed to build static file container for `{name}` ({polling_id}) with err {build_err:?}");
+            if fail_deployment(polling_id, &build_err).is_err() {
+                error!("`fail_deployment` failed to mark polling id {polling_id} as errored");
+            }
+            send_failure_message(&meta, "Build Static Container").await;
+            return Err(build_err.to_string());
+        },
+    };
     if !advance_with_fail_log(polling_id) { return Err("Failed to advance status to pushing".to_string()); }
 
 
-    if let Err(push_err) = push_challenge(docker, &name, None, polling_id).await {
+    if let Err(push_err) = push_challenge(docker, &name, None, &version, polling_id).await {
         error!("Failed to push static file container for `{name}` ({polling_id}) with err {push_err:?}");
-        if fail_deployment(polling_id, push_err.to_string()).is_err() {
+        if fail_deployment(polling_id, &push_err).is_err() {
             error!("`fail_deployment` failed to mark polling id {polling_id} as errored");
         }
         send_failure_message(&meta, "Push Static Container").await;
@@ -149,90 +280,186 @@ async fn build_static(docker: &Docker, meta: &Metadata) -> Result<(), String> {
     Ok(())
 }
 
-async fn deploy_target(
+/// Number of deploy targets of a challenge built at once if `MAX_PARALLEL_BUILDS` isn't set
+const DEFAULT_PARALLEL_BUILDS: usize = 2;
+
+/// Number of deploy targets of a challenge built at once, set with `MAX_PARALLEL_BUILDS`
+fn parallel_builds() -> usize {
+    max_parallel_builds()
+        .and_then(|max| max.parse().ok())
+        .unwrap_or(DEFAULT_PARALLEL_BUILDS)
+        .max(1)
+}
+
+/// Builds the image of every deploy target of a challenge, at most `MAX_PARALLEL_BUILDS` images at once
+/// 
+/// Targets with the same build path share an image, so they are built one after the other instead of racing on its tags,
+/// and a build config is only built once per image. How long each build took (and the warnings of its pre-build checks)
+/// is recorded on the deployment. Every build runs to the end, even once one failed; the first failure then fails the
+/// deployment.
+/// 
+/// ## Returns
+/// - `Some(Vec<(DeployTargetType, TargetConfig, String)>)` - Every target along with the version of its image, in the order
+///   they were given
+/// - `None` - A build failed
+async fn build_targets(
     docker: &Docker,
-    client: &Client,
-    target: DeployTarget,
-    target_type: DeployTargetType,
+    targets: Vec<(DeployTargetType, TargetConfig)>,
     meta: &Metadata,
-    deployed_servers: &mut Vec<(DeployTargetType, Vec<i32>)>,
-) -> bool {
-    let meta = meta.clone();
+) -> Option<Vec<(DeployTargetType, TargetConfig, String)>> {
     let polling_id = meta.poll_id();
-    let name = meta.chall_name().clone();
+    let name = meta.chall_name();
 
-    // if built_path defaulted or set to ".", subfolder is None
-    let build_path_buf = if target.build.to_string_lossy() == "." {
-        None
-    } else {
-        Some(target.build.clone())
-    };
+    if !restart_steps_with_fail_log(polling_id) { return None; }
 
-    let build_path = build_path_buf.as_deref(); 
+    let mut images: Vec<(Option<PathBuf>, Vec<(usize, DeployTargetType, TargetConfig)>)> = vec![];
+    for (index, (target_type, config)) in targets.into_iter().enumerate() {
+        let build_path = config.build_path().map(Path::to_path_buf);
+        match images.iter_mut().find(|(image_path, _)| *image_path == build_path) {
+            Some((_, image_targets)) => image_targets.push((index, target_type, config)),
+            None => images.push((build_path, vec![(index, target_type, config)])),
+        }
+    }
 
-    if let Err(build_err) = build_challenge(docker, &name, build_path, polling_id).await {
-        error!("Failed to build `{name}` ({polling_id}) with err {build_err:?}");
-        if fail_deployment(polling_id, build_err.to_string()).is_err() {
-            error!("`fail_deployment` failed to mark polling id {polling_id} as errored");
+    let built_images: Vec<_> = stream::iter(images)
+        .map(|(_, image_targets)| async move {
+            let mut finished: Vec<(BuildConfig, BuiltImage, Duration)> = vec![];
+            let mut results = vec![];
+
+            for (index, target_type, config) in image_targets {
+                let build_config = target_build_config(&config);
+                let reused = finished
+                    .iter()
+                    .find(|(finished_config, ..)| *finished_config == build_config)
+                    .map(|(_, built, elapsed)| (built.clone(), *elapsed));
+
+                let (built, elapsed) = match reused {
+                    Some((built, elapsed)) => {
+                        debug!("Reusing the image of `{name}` ({polling_id}) built for another target with the same config");
+                        (Ok(built), elapsed)
+                    },
+                    None => {
+                        let started = Instant::now();
+                        let built = build_challenge(docker, name, config.build_path(), &build_config, polling_id).await;
+                        let elapsed = started.elapsed();
+                        if let Ok(built) = &built {
+                            finished.push((build_config, built.clone(), elapsed));
+                        }
+                        (built, elapsed)
+                    },
+                };
+
+                let build_time = TargetBuildTime::new(target_type, config.build_path(), elapsed, built.is_ok());
+                let warnings = built.as_ref().map(|built| built.warnings.clone()).unwrap_or_default();
+                record_build_time(polling_id, build_time.with_warnings(warnings));
+
+                results.push((index, target_type, config, built));
+            }
+
+            results
+        })
+        .buffer_unordered(parallel_builds())
+        .collect()
+        .await;
+
+    let mut results: Vec<_> = built_images.into_iter().flatten().collect();
+    results.sort_by_key(|(index, ..)| *index);
+
+    let mut built_targets = vec![];
+    let mut first_failure = None;
+    for (_, target_type, config, built) in results {
+        match built {
+            Ok(built) => built_targets.push((target_type, config, built.version)),
+            Err(build_err) => {
+                error!("Failed to build `{name}` ({polling_id}) with err {build_err:?}");
+                first_failure.get_or_insert_with(|| DeployFailure::from(&build_err).for_target(target_type, config.build_path()));
+            },
         }
-        send_failure_message(&meta, "Build").await;
-        return false;
     }
-    if !advance_with_fail_log(polling_id) { return false; }
-    
 
-    if let Err(push_err) = push_challenge(docker, &name, build_path, polling_id).await {
-        error!("Failed to push `{name}` ({polling_id}) with err {push_err:?}");
-        if fail_deployment(polling_id, push_err.to_string()).is_err() {
+    if let Some(failure) = first_failure {
+        if fail_deployment(polling_id, failure).is_err() {
             error!("`fail_deployment` failed to mark polling id {polling_id} as errored");
         }
-        send_failure_message(&meta, "Push").await;
-        return false;
+        send_failure_message(meta, "Build").await;
+        return None;
     }
-    if !advance_with_fail_log(polling_id) { return false; }
 
-    let ports = match deploy_challenge(docker, client, &name, None, build_path, polling_id).await {
-        Ok(ports) => {
-            info!("Successfully deployed `{name}` ({polling_id}) to port(s): {:?}", &ports);
-            if succeed_deployment(polling_id, &ports).is_err() {
-                error!("`succeed_deployment` failed to mark polling id {polling_id} as succeeded");
+    Some(built_targets)
+}
+
+/// Pushes the already built `version` of a deploy target's image and deploys it
+async fn deploy_target(
+    docker: &Docker,
+    client: &Client,
+    target_type: DeployTargetType,
+    config: TargetConfig,
+    version: &str,
+    meta: &Metadata,
+    deployed_servers: &mut Vec<(DeployTargetType, Vec<ExposedPort>)>,
+) -> Option<TargetRecord> {
+    let meta = meta.clone();
+    let polling_id = meta.poll_id();
+    let name = meta.chall_name().clone();
+    let build_path = config.build_path();
+
+    if !reset_step_with_fail_log(polling_id, DeployStep::Pushing) { return None; }
+
+    let pushed_digest = match push_challenge(docker, &name, build_path, version, polling_id).await {
+        Ok(digest) => digest,
+        Err(push_err) => {
+            error!("Failed to push `{name}` ({polling_id}) with err {push_err:?}");
+            if fail_deployment(polling_id, DeployFailure::from(&push_err).for_target(target_type, build_path)).is_err() {
+                error!("`fail_deployment` failed to mark polling id {polling_id} as errored");
             }
-            ports
+            send_failure_message(&meta, "Push").await;
+            return None;
+        },
+    };
+    if !advance_with_fail_log(polling_id) { return None; }
+
+    let (ports, digest) = match deploy_challenge(docker, client, &config, version, pushed_digest.as_deref(), polling_id).await {
+        Ok((ports, digest)) => {
+            // The deployment is only marked as succeeded once every target (and the static files) are deployed
+            info!("Successfully deployed `{name}` ({polling_id}) to port(s): {:?}", &ports);
+            (ports, digest)
         },
         Err(deploy_err) => {
             error!("Failed to deploy `{name}` ({polling_id}) with err {deploy_err:?}");
-            if fail_deployment(polling_id, deploy_err.to_string()).is_err() {
+            if fail_deployment(polling_id, DeployFailure::from(&deploy_err).for_target(target_type, build_path)).is_err() {
                 error!("`fail_deployment` failed to mark polling id {polling_id} as errored");
             }
             send_failure_message(&meta, "Deploy").await;
-            return false;
+            return None;
         }
     };
     
     // // FIXME --> This might break if there are two different deployed containers that have a weird container/image name --> fix will most likely include server type possibly??
     // if let Err(failed_files) = deploy_static_files(docker, meta.chall_name().as_str()).await {
     //     error!("Failed to deploy static files {:?} for {} ({})", failed_files, meta.chall_name(), polling_id);
-    //     if fail_deployment(polling_id, DeployProcessErr::FileUpload(failed_files).to_string()).is_err() {
+    //     if fail_deployment(polling_id, &DeployProcessErr::FileUpload(failed_files)).is_err() {
     //         error!("`fail_deployment` failed to mark polling id {polling_id} as errored");
     //     }
     //     send_failure_message(&meta, "Deploy Static Files").await;
-    //     return false;
+    //     return None;
     // }
 
+    let config = TargetConfig { image_digest: digest, ..config };
+    let record = TargetRecord::new(&config, ports.clone());
     deployed_servers.push((target_type, ports));
 
-    true
+    Some(record)
 }
 
 
 async fn quick_fail_deployment_with_logs(
     polling_id: PollingId,
     metadata: &Metadata,
-    failure_message: impl ToString,
+    failure_message: impl Into<DeployFailure>,
     err: impl ToString,
 ) -> bool {
     let mut had_errors = false;
-    if let Err(id) = fail_deployment(polling_id, failure_message.to_string()) {
+    if let Err(id) = fail_deployment(polling_id, failure_message) {
         error!("Failed to mark deployment as failed for id {id}");
         had_errors = true;
     }
@@ -258,12 +485,12 @@ pub fn spawn_deploy_req(docker: Docker, client: Client, meta: Metadata) -> Resul
     let polling_id = meta.poll_id();
 
 
-    if let Err(status) = register_chall_deployment(polling_id) {
+    if let Err(status) = register_chall_deployment(polling_id, meta.chall_name()) {
         if !status.is_finished() {
             return Err(Response::poll_id_in_use(meta, polling_id, status));
         }
         let failed_to_update_poll_id = deregister_id(polling_id).is_none();
-        let failed_to_update_poll_id = failed_to_update_poll_id || register_chall_deployment(polling_id).is_err();
+        let failed_to_update_poll_id = failed_to_update_poll_id || register_chall_deployment(polling_id, meta.chall_name()).is_err();
 
         if failed_to_update_poll_id {
             return Err(Response::unknown_ise(meta, "Failed to update deployment state"));
@@ -276,17 +503,27 @@ pub fn spawn_deploy_req(docker: Docker, client: Client, meta: Metadata) -> Resul
 
         let Some(chall_yaml) = handle_yaml_get(&meta).await else { return };
 
-        let deployed_servers = if let Some(deploy_options) = chall_yaml.deploy() {
+        let mut target_records = vec![];
+        let deployed_servers = if chall_yaml.shape.deploy().is_some() {
             // DOCKER CHALLENGES BUILD STARTING FROM HERE, STATIC CHALLS ALREADY RETURNED
             // to build multiple things iterate over chall.yaml with deploy fields and then you can take the path they say to build and build that path, return the links as a tuple with the type of server built and then from tehre that makes it easier to display and you dont need to rework everything
 
-            let collected = deploy_options.clone()
-                .into_iter()
-                .collect::<Vec<(DeployTarget, DeployTargetType)>>();
+            let configs = chall_yaml.target_configs(meta.chall_name());
+
+            let Some(built_targets) = build_targets(&docker, configs, &meta).await else {
+                error!("Failed to build servers for {} ({})", meta.chall_name(), polling_id);
+                quick_fail_deployment_with_logs(
+                    polling_id,
+                    &meta,
+                    "Failed to build servers",
+                    "Issue with building the images of the servers, see logs.",
+                ).await;
+                return;
+            };
 
-            let mut deployed_servers : Vec<(DeployTargetType, Vec<i32>)> = Vec::new();
-            for (target, target_type) in collected {
-                if !deploy_target(&docker, &client, target, target_type, &meta, &mut deployed_servers).await {
+            let mut deployed_servers : Vec<(DeployTargetType, Vec<ExposedPort>)> = Vec::new();
+            for (target_type, config, version) in built_targets {
+                let Some(record) = deploy_target(&docker, &client, target_type, config, &version, &meta, &mut deployed_servers).await else {
                     error!("Failed to deploy servers for {} ({})", meta.chall_name(), polling_id);
                     quick_fail_deployment_with_logs(
                         polling_id,
@@ -295,7 +532,8 @@ pub fn spawn_deploy_req(docker: Docker, client: Client, meta: Metadata) -> Resul
                         "Issue with deploying k8s servers, see logs.",
                     ).await;
                     return;
-                }
+                };
+                target_records.push(record);
             }
 
             info!("Deployed servers: {:?}", deployed_servers);
@@ -306,11 +544,11 @@ pub fn spawn_deploy_req(docker: Docker, client: Client, meta: Metadata) -> Resul
             vec![]
         };
 
-        let port_list: Vec<_> = deployed_servers.iter().map(|(_, ports)| ports).flatten().copied().collect();
+        let port_list: Vec<_> = deployed_servers.iter().flat_map(|(_, ports)| ports).cloned().collect();
 
 
         let needs_static_builder = 'needs_static_builder_result: {
-            let Some(mut file_iter) = chall_yaml.file_iter() else {
+            let Some(mut file_iter) = chall_yaml.shape.file_iter() else {
                 info!("No files to deploy for {} ({})", meta.chall_name(), polling_id);
                 break 'needs_static_builder_result false;
             };
@@ -338,13 +576,17 @@ pub fn spawn_deploy_req(docker: Docker, client: Client, meta: Metadata) -> Resul
             quick_fail_deployment_with_logs(
                 polling_id,
                 &meta,
-                "Failed to deploy static files",
+                &DeployProcessErr::FileUpload(e),
                 "Issue with deploying static files, see logs.",
             ).await;
             return;
         }
         info!("Successfully deployed static files for {} ({})", meta.chall_name(), polling_id);
         
+        if let Err(e) = save_deploy_record(DeployRecord::new(meta.chall_name(), target_records)) {
+            error!("Failed to save deploy record for {} ({}): {e}", meta.chall_name(), polling_id);
+        }
+
         match succeed_deployment(polling_id, &port_list) {
             Ok(_) => info!("Successfully marked deployment as succeeded for {} ({})", meta.chall_name(), polling_id),
             Err(e) => error!("Failed to mark deployment as succeeded for {} ({}): {e:?}", meta.chall_name(), polling_id),
@@ -369,7 +611,7 @@ pub async fn update_yaml(chall_folder_name: &str, modifications: Modifications,
 
     let repo_path = Path::new(chall_folder_default());
 
-    let should_push = ensure_repo_up_to_date(repo_path, &meta)?;
+    let should_push = ensure_repo_up_to_date(repo_path, &meta).await?;
     trace!("Repo up to date");
 
     let new_yaml = update_yaml_file(chall_folder_name, modifications, &meta).await?;
@@ -381,8 +623,193 @@ pub async fn update_yaml(chall_folder_name: &str, modifications: Modifications,
     
     make_commit(repo_path, &[&yaml_location_relative], &message, &meta)?;
     if should_push {
-        push_all(repo_path, &meta)?;
+        push_all(repo_path, &meta).await?;
     }
 
     Ok(new_yaml)
 }
+
+
+/// Deletes the Kubernetes resources of an instance, logging (but otherwise ignoring) failures
+async fn clean_up_instance(client: &Client, resources: &[InstanceResource]) {
+    for resource in resources {
+        if let Err(e) = resource.delete(client).await {
+            error!("Failed to clean up instance {}: {e}", resource.name);
+        }
+    }
+}
+
+/// Starts a team's own copy of every deploy target of a challenge
+/// 
+/// The challenge has to already be deployed, since its live resources are what gets copied. If the team
+/// already has an instance of the challenge, that instance is returned instead of starting a new one.
+/// 
+/// ## Returns
+/// - `Response` - The instance's links and expiry time on success, error trace otherwise
+pub async fn start_instance(client: &Client, meta: Metadata) -> Response {
+    let Some(team_id) = meta.team_id() else {
+        return Response::team_id_missing(meta);
+    };
+    let chall_name = meta.chall_name().clone();
+
+    let chall_yaml = match load_chall_yaml(&chall_name).await {
+        Some(Ok(yaml)) => yaml,
+        Some(Err(e)) => {
+            error!("Failed to parse chall.yaml for {chall_name}: {e}");
+            return Response::err_instance(meta, e);
+        },
+        None => return Response::err_chall_name_doesnt_exist(meta, &chall_name),
+    };
+
+    let configs = chall_yaml.target_configs(&chall_name);
+
+    if configs.is_empty() {
+        warn!("Instance of {chall_name} requested, but it has no deploy targets");
+        return Response::err_instance(meta, format!("{chall_name} has no deploy targets"));
+    }
+
+    match reserve_instance(&chall_name, team_id) {
+        Ok(()) => {},
+        Err(ReserveErr::AlreadyExists(instance)) => {
+            info!("Team {team_id} already has an instance of {chall_name}");
+            return Response::success_instance(instance.info());
+        },
+        Err(e) => {
+            warn!("Refusing instance of {chall_name} for team {team_id}: {e}");
+            return Response::instance_limit(meta, e);
+        },
+    }
+
+    let team = team_id.simple().to_string();
+    let mut resources = vec![];
+    let mut instance_servers: Vec<(DeployTargetType, Vec<ExposedPort>)> = vec![];
+
+    for (target_type, config) in configs {
+        let base_name = config.resource_name();
+        let name = instance_name(&base_name, &team);
+        // Instances run on the same cluster as the target they are a copy of
+        let cluster = config.cluster;
+        resources.push(InstanceResource { name: name.clone(), cluster: cluster.clone() });
+
+        let created = match client_for_cluster(client, cluster.as_deref()).await {
+            Ok(cluster_client) => create_instance(&cluster_client, cluster.as_deref(), &base_name, &name, &team_id.to_string()).await,
+            Err(e) => Err(e),
+        };
+
+        match created {
+            Ok(ports) => instance_servers.push((target_type, ports)),
+            Err(e) => {
+                error!("Failed to start instance of {chall_name} for team {team_id}: {e}");
+                clean_up_instance(client, &resources).await;
+                release_instance(&chall_name, team_id);
+                return Response::err_instance(meta, e);
+            },
+        }
+    }
+
+    let links = into_webhook_links(links_from_port_listing(&Some(instance_servers)));
+
+    match activate_instance(&chall_name, team_id, resources.clone(), links) {
+        Some(instance) => {
+            info!("Started instance of {chall_name} for team {team_id}");
+            Response::success_instance(instance.info())
+        },
+        None => {
+            warn!("Instance of {chall_name} for team {team_id} was stopped while starting, cleaning up");
+            clean_up_instance(client, &resources).await;
+            Response::err_instance(meta, "Instance was stopped while it was starting")
+        },
+    }
+}
+
+/// Stops a team's instance of a challenge and deletes its resources
+/// 
+/// ## Returns
+/// - `Response` - The stopped instance on success, error trace otherwise
+pub async fn stop_instance(client: &Client, meta: Metadata) -> Response {
+    let Some(team_id) = meta.team_id() else {
+        return Response::team_id_missing(meta);
+    };
+
+    let Some(instance) = release_instance(meta.chall_name(), team_id) else {
+        return Response::instance_doesnt_exist(meta, team_id);
+    };
+
+    if let Err(e) = tear_down(client, &instance).await {
+        error!("Failed to stop instance of {} for team {team_id}: {e}", meta.chall_name());
+        // Still tracked, so stopping it again or the reaper can finish tearing it down
+        restore_instance(instance);
+        return Response::err_instance(meta, e);
+    }
+
+    info!("Stopped instance of {} for team {team_id}", meta.chall_name());
+    Response::success_instance(instance.info())
+}
+
+
+/// Scales the live deploy targets of a challenge to `replicas`, without redeploying it
+/// 
+/// Only the target with the key `target` (e.g. `web`) is scaled if one is given, every target otherwise.
+/// The new replica count is saved to the challenge's deploy record, so the reconciler doesn't undo it.
+/// 
+/// ## Returns
+/// - `Response` - Success if every target was scaled, error trace otherwise
+pub async fn scale_challenge(client: &Client, meta: Metadata, replicas: u16, target: Option<&str>) -> Response {
+    let chall_name = meta.chall_name().clone();
+
+    let record = match load_deploy_records() {
+        Ok(mut records) => records.remove(&chall_name),
+        Err(e) => {
+            error!("Failed to load deploy records: {e}");
+            return Response::err_scale(meta, e);
+        },
+    };
+    let Some(record) = record else {
+        return Response::err_not_deployed(meta, &chall_name);
+    };
+
+    let targets: Vec<_> = record.targets
+        .iter()
+        .filter(|record_target| target.map_or(true, |target| record_target.target == target))
+        .collect();
+
+    if targets.is_empty() {
+        return Response::err_not_deployed(meta, &format!("{chall_name}/{}", target.unwrap_or_default()));
+    }
+
+    // Autoscalers need at least one replica, the cluster would reject the change halfway through the targets
+    if replicas == 0 {
+        if let Some(autoscaled) = targets.iter().find(|target| target.autoscaled) {
+            return Response::replicas_invalid(meta, &autoscaled.target);
+        }
+    }
+
+    let replicas = replicas as i32;
+    for target in targets {
+        let scaled = match client_for_cluster(client, target.cluster.as_deref()).await {
+            Ok(cluster_client) => scale_target(&cluster_client, &target.resource_name, replicas).await,
+            Err(e) => Err(e),
+        };
+
+        let autoscaled = match scaled {
+            Ok(autoscaled) => {
+                info!("Scaled {} to {replicas} replica(s) (autoscaled: {autoscaled})", target.resource_name);
+                autoscaled
+            },
+            Err(K8sError::ScaleRejected(reason)) => {
+                warn!("Refused to scale {}: {reason}", target.resource_name);
+                return Response::replicas_invalid(meta, &target.target);
+            },
+            Err(e) => {
+                error!("Failed to scale {}: {e}", target.resource_name);
+                return Response::err_scale(meta, e);
+            },
+        };
+
+        if let Err(e) = update_target_replicas(&chall_name, &target.resource_name, replicas, autoscaled) {
+            error!("Failed to save new replica count of {}: {e}", target.resource_name);
+        }
+    }
+
+    Response::success_scale(meta)
+}
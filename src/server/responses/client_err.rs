@@ -18,7 +18,7 @@ impl Response {
                 status: Status::Unknown,
                 status_time: std::time::Duration::ZERO.into(),
                 err_msg: Some(format!("Endpoint {endpoint_name:?} doesn't exist")),
-            }),
+            }).into(),
         )
     }
 
@@ -34,7 +34,7 @@ impl Response {
                 status: Status::Unknown,
                 status_time: std::time::Duration::ZERO.into(),
                 err_msg: Some(format!("Challenge {name:?} doesn't exist")),
-            }),
+            }).into(),
         )
     }
     pub fn err_poll_id_doesnt_exist(meta: Metadata, poll_id: Uuid) -> Self {
@@ -48,7 +48,7 @@ impl Response {
                 status: Status::Unknown,
                 status_time: std::time::Duration::ZERO.into(),
                 err_msg: Some(format!("Poll ID {poll_id} doesn't exist.")),
-            }),
+            }).into(),
         )
     }
 
@@ -65,7 +65,7 @@ impl Response {
                 status,
                 status_time,
                 err_msg: Some(format!("Poll ID {poll_id} already exists. Status has been sent.")),
-            }),
+            }).into(),
         )
     }
 
@@ -81,7 +81,103 @@ impl Response {
                 status: Status::Unknown,
                 status_time: std::time::Duration::ZERO.into(),
                 err_msg: Some("No modifications were provided.".to_string()),
-            }),
+            }).into(),
+        )
+    }
+
+    pub fn team_id_missing(meta: Metadata) -> Self {
+        let chall_name = Some(meta.chall_name().to_string());
+        let poll_id = meta.poll_id();
+        
+        Self(
+            StatusCode::TEAM_ID_MISSING,
+            FromDeploy::Status(DeploymentStatus {
+                chall_name,
+                poll_id,
+                status: Status::Unknown,
+                status_time: std::time::Duration::ZERO.into(),
+                err_msg: Some("No team ID was provided.".to_string()),
+            }).into(),
+        )
+    }
+
+    pub fn instance_doesnt_exist(meta: Metadata, team_id: Uuid) -> Self {
+        let chall_name = Some(meta.chall_name().to_string());
+        let poll_id = meta.poll_id();
+        
+        Self(
+            StatusCode::INSTANCE_NO_EXISTS_ERR,
+            FromDeploy::Status(DeploymentStatus {
+                chall_name,
+                poll_id,
+                status: Status::Unknown,
+                status_time: std::time::Duration::ZERO.into(),
+                err_msg: Some(format!("Team {team_id} has no instance of {:?}", meta.chall_name())),
+            }).into(),
+        )
+    }
+
+    pub fn instance_limit(meta: Metadata, e: impl std::fmt::Display) -> Self {
+        let chall_name = Some(meta.chall_name().to_string());
+        let poll_id = meta.poll_id();
+        
+        Self(
+            StatusCode::INSTANCE_LIMIT,
+            FromDeploy::Status(DeploymentStatus {
+                chall_name,
+                poll_id,
+                status: Status::Unknown,
+                status_time: std::time::Duration::ZERO.into(),
+                err_msg: Some(e.to_string()),
+            }).into(),
+        )
+    }
+
+    pub fn replicas_invalid(meta: Metadata, target: &str) -> Self {
+        let chall_name = Some(meta.chall_name().to_string());
+        let poll_id = meta.poll_id();
+        
+        Self(
+            StatusCode::REPLICAS_INVALID,
+            FromDeploy::Status(DeploymentStatus {
+                chall_name,
+                poll_id,
+                status: Status::Unknown,
+                status_time: std::time::Duration::ZERO.into(),
+                err_msg: Some(format!("Target {target:?} is autoscaled, it can't be scaled to 0 replicas.")),
+            }).into(),
+        )
+    }
+
+    pub fn replicas_missing(meta: Metadata) -> Self {
+        let chall_name = Some(meta.chall_name().to_string());
+        let poll_id = meta.poll_id();
+        
+        Self(
+            StatusCode::REPLICAS_MISSING,
+            FromDeploy::Status(DeploymentStatus {
+                chall_name,
+                poll_id,
+                status: Status::Unknown,
+                status_time: std::time::Duration::ZERO.into(),
+                err_msg: Some("No replica count was provided.".to_string()),
+            }).into(),
+        )
+    }
+
+    pub fn err_not_deployed(meta: Metadata, name: &str) -> Self {
+        let chall_name = Some(meta.chall_name().to_string());
+        let poll_id = meta.poll_id();
+        
+        Self(
+            StatusCode::NOT_DEPLOYED_ERR,
+            FromDeploy::Status(DeploymentStatus {
+                chall_name,
+                poll_id,
+                status: Status::Unknown,
+                status_time: std::time::Duration::ZERO.into(),
+                err_msg: Some(format!("{name:?} is not deployed")),
+            }).into(),
         )
     }
 }
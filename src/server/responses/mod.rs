@@ -4,9 +4,10 @@ mod server_err;
 
 use actix_web::{Responder, CustomizeResponder, web::Json};
 use serde::Serialize;
-use crate::polling::{ PollingId, DeploymentStatus, poll_deployment };
+use crate::polling::{ PollingId, DeploymentStatus, TargetBuildTime, poll_deployment };
 use super::Deploy;
 use std::{borrow::Cow, time::Duration};
+use uuid::Uuid;
 
 
 macro_rules! const_status_code {
@@ -27,6 +28,11 @@ macro_rules! const_status_code {
 /// ### 40X - Endpoint Failures
 /// - `404` - Endpoint does not exist
 /// 
+/// ### 41X/42X - Instance Request Failures
+/// - `412` - Team ID missing from an instance request
+/// - `422` - Autoscaled target scaled to 0 replicas
+/// - `429` - Instance cap reached
+/// 
 /// ### 44X - Polling ID Failures
 /// - `440` - Polling ID already exists
 /// - `441` - Polling ID has not been registered / invalid
@@ -47,6 +53,16 @@ macro_rules! const_status_code {
 /// 
 /// ### 55X - Server Deploy Process Failures 
 /// - `550` + **subcode** - Server Deploy Process Failure
+///     - `550` - Error uploading file(s) to CDN
+///     - `551` - Dockerfile failed to build
+///     - `552` - Error pushing to registry
+///     - `553` - Error pulling from registry
+///     - `554` - Error fetching challenge folder
+///     - `555` - Kubernetes rejected the challenge's objects
+///     - `556` - Docker daemon unreachable
+///     - `557` - Kubernetes cluster unreachable
+///     - `558` - Challenge's pods never came up
+///     - `559` - Dockerfile or build context failed the pre-build checks
 /// 
 /// ### 580 - Server Delete Failures
 /// - `580` - Kubernetes Service/Deployment Deletion Failure
@@ -62,10 +78,16 @@ impl StatusCode {
     const_status_code!(ENDPOINT_NO_EXIST_ERR:      404 ("Endpoint is not set up on the server"));
     const_status_code!(CHALL_NAME_NO_EXISTS_ERR:   404 ("There is no challenge with this name"));
     const_status_code!(POLL_ID_INVAL_NOEXISTS_ERR: 404 ("Polling ID does not exist"));
+    const_status_code!(INSTANCE_NO_EXISTS_ERR:     404 ("There is no instance of this challenge for this team"));
+    const_status_code!(NOT_DEPLOYED_ERR:           404 ("This challenge (or deploy target) is not deployed"));
 
     // Other Client Errors
     const_status_code!(POLL_ID_ALREADY_EXISTS_ERR: 409 ("Polling ID already exists"));
     const_status_code!(MODICATIONS_MISSING: 412 ("You must specify the modifications to make to the metadata"));
+    const_status_code!(TEAM_ID_MISSING:     412 ("You must specify the team to start/stop the instance for"));
+    const_status_code!(REPLICAS_MISSING:    412 ("You must specify the number of replicas to scale to"));
+    const_status_code!(REPLICAS_INVALID:    422 ("Autoscaled targets need at least one replica"));
+    const_status_code!(INSTANCE_LIMIT:      429 ("Too many instances are running"));
 
 
     // Metadata modification failures
@@ -83,16 +105,135 @@ impl StatusCode {
     // Deletion errors
     const_status_code!(K8S_SERVICE_DEPLOY_DEL_ERR: 500 ("Failure deleting Kubernetes resources"));
     const_status_code!(DOCKER_IMG_DEL_ERR:         500 ("Failure deleting Docker image"));
+    const_status_code!(STATIC_FILES_DEL_ERR:       500 ("Failure deleting the challenge's uploaded files"));
+
+    // Instance errors
+    const_status_code!(INSTANCE_ERR: 500 ("Failure starting/stopping the instance"));
+
+    // Scaling errors
+    const_status_code!(SCALE_ERR: 500 ("Failure scaling the challenge"));
+
+    // Reconciliation errors
+    const_status_code!(RECONCILE_ERR: 500 ("Failure comparing the deployed challenges with the cluster"));
+
+    /// Status of a failed deployment, see [`DeployProcessErr::subcode`][crate::server::utils::errors::DeployProcessErr::subcode]
+    pub fn server_deploy_process_err(subcode: u64, message: &'static str) -> Self {
+        StatusCode { code: 550 + subcode, message: Cow::Borrowed(message) }
+    }
 }
 
 
 
 use super::utils::api_types::outgoing::FromDeploy as OutgoingFromDeploy;
+use crate::emitter::outbox::OutboxEntry;
+use crate::instances::InstanceInfo;
+use crate::reconciler::DriftReport;
+use crate::gc::GcReport;
+use crate::discovery::ChallIndex;
+use crate::uptime::ChallUptime;
+use arcs_docker::LayerProgress;
+use arcs_retry::RetryStats;
+use super::utils::errors::FailureDetails;
 
-pub struct Response(StatusCode, OutgoingFromDeploy);
+/// Body of a response sent back to the client
+/// 
+/// ## Variants
+/// - `Deploy` - One of the payloads defined by the webhook server's API
+/// - `Instance` - Information about a team's instance of a challenge
+/// - `Drift` - Differences between the deployed challenges and the cluster
+/// - `Uptime` - Up/down history of the deployed challenges
+/// - `Failure` - Status of a failed deployment, along with the details of the failure
+/// - `Pushing` - Status of a deployment that is pushing its image, along with the progress of every layer
+/// - `Retries` - How often each kind of operation was retried, keyed by operation
+/// - `Outbox` - Payloads for the webhook server that weren't delivered yet
+/// - `Gc` - What a garbage collection found and removed
+/// - `Challs` - Every challenge in the challenge repo, as indexed from its chall.yaml, and the folders whose chall.yaml is invalid
+#[derive(Serialize)]
+#[serde(untagged)]
+pub enum ResponseBody {
+    Deploy(OutgoingFromDeploy),
+    Failure(FailedDeploy),
+    Pushing(PushingDeploy),
+    Deployed(DeployedDeploy),
+    Instance(InstanceInfo),
+    Drift(DriftReport),
+    Uptime(std::collections::BTreeMap<String, ChallUptime>),
+    Retries(std::collections::BTreeMap<&'static str, RetryStats>),
+    Outbox(Vec<OutboxEntry>),
+    Gc(GcReport),
+    Challs(ChallIndex),
+}
+
+/// Status of a failed deployment, serialized as the status with an added `failure` field
+/// 
+/// ## Fields
+/// - `status` - The deployment's status, as defined by the webhook server's API
+/// - `failure` - Machine-readable details of the failure
+#[derive(Serialize)]
+pub struct FailedDeploy {
+    #[serde(flatten)]
+    pub status: OutgoingFromDeploy,
+    pub failure: FailureDetails,
+}
+
+/// Status of a deployment that is pushing its image, serialized as the status with an added `layers` field
+/// 
+/// ## Fields
+/// - `status` - The deployment's status, as defined by the webhook server's API
+/// - `layers` - Latest progress of every layer of the image, as reported by the Docker daemon
+#[derive(Serialize)]
+pub struct PushingDeploy {
+    #[serde(flatten)]
+    pub status: OutgoingFromDeploy,
+    pub layers: Vec<LayerProgress>,
+}
+
+/// Status of a successful deployment, serialized as the status with an added `builds` field
+/// 
+/// ## Fields
+/// - `status` - The deployment's status, as defined by the webhook server's API
+/// - `builds` - How long the image of every deploy target took to build
+#[derive(Serialize)]
+pub struct DeployedDeploy {
+    #[serde(flatten)]
+    pub status: OutgoingFromDeploy,
+    pub builds: Vec<TargetBuildTime>,
+}
+
+impl From<OutgoingFromDeploy> for ResponseBody {
+    fn from(value: OutgoingFromDeploy) -> Self {
+        Self::Deploy(value)
+    }
+}
+
+impl From<InstanceInfo> for ResponseBody {
+    fn from(value: InstanceInfo) -> Self {
+        Self::Instance(value)
+    }
+}
+
+impl From<DriftReport> for ResponseBody {
+    fn from(value: DriftReport) -> Self {
+        Self::Drift(value)
+    }
+}
+
+impl From<GcReport> for ResponseBody {
+    fn from(value: GcReport) -> Self {
+        Self::Gc(value)
+    }
+}
+
+impl From<ChallIndex> for ResponseBody {
+    fn from(value: ChallIndex) -> Self {
+        Self::Challs(value)
+    }
+}
+
+pub struct Response(StatusCode, ResponseBody);
 
 impl Response {
-    pub fn wrap(self) -> CustomizeResponder<Json<OutgoingFromDeploy>> {
+    pub fn wrap(self) -> CustomizeResponder<Json<ResponseBody>> {
         use actix_web::http::StatusCode as ActixStatusCode;
 
         let Self(status_code, body) = self;
@@ -115,6 +256,7 @@ impl Response {
 /// - `poll_id` - PollingId to uniquely identify request
 /// - `chall_name` - Challenge name that request pertained to
 /// - `endpoint_name` - Endpoint that the request was sent/forwarded to
+/// - `team_id` - Team that the request was made for, only used by instance requests
 /// - `other_data` - `Option<serde_json::Value>` parameter that can be sent back to the client for additional information
 #[derive(Debug, Clone, Serialize)]
 pub struct Metadata {
@@ -122,6 +264,7 @@ pub struct Metadata {
     chall_name: String,
     status: DeploymentStatus,
     endpoint_name: String,
+    team_id: Option<Uuid>,
     other_data: Option<serde_json::Value>,
 }
 
@@ -130,11 +273,12 @@ impl From<&Deploy> for Metadata {
         let poll_id = deploy_input.deploy_identifier;
         let chall_name = deploy_input.chall_name.clone();
         let endpoint_name = deploy_input.__type.to_uppercase();
+        let team_id = deploy_input.team_id;
 
         let deployment = poll_deployment(poll_id).ok();
         let status = deployment.map(|d| d.status).unwrap_or_default();
 
-        Self { poll_id, chall_name, endpoint_name, status, other_data: None }
+        Self { poll_id, chall_name, endpoint_name, status, team_id, other_data: None }
     }
 }
 impl Metadata {
@@ -147,6 +291,9 @@ impl Metadata {
     pub fn endpoint_name(&self) -> &String {
         &self.endpoint_name
     }
+    pub fn team_id(&self) -> Option<Uuid> {
+        self.team_id
+    }
     pub fn status_is_unknown(&self) -> bool {
         matches!(self.status, DeploymentStatus::Unknown)
     }
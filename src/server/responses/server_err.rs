@@ -2,7 +2,9 @@ use std::fmt::Display;
 
 use crate::server::utils::api_types::outgoing::{ DeploymentStatus, FromDeploy, Status };
 
-use super::{Metadata, Response, StatusCode};
+use crate::server::utils::errors::FailureDetails;
+
+use super::{FailedDeploy, Metadata, Response, ResponseBody, StatusCode};
 
 
 impl Response {
@@ -17,7 +19,7 @@ impl Response {
                 status: Status::Unknown,
                 status_time: std::time::Duration::ZERO.into(),
                 err_msg: Some(format!("ERROR APPLYING MODIFICATIONS: {e}")),
-            }),
+            }).into(),
         )
     }
 
@@ -33,7 +35,7 @@ impl Response {
                 status: Status::Unknown,
                 status_time: std::time::Duration::ZERO.into(),
                 err_msg: Some(format!("ERROR CONNECTING TO DOCKER: {e}")),
-            }),
+            }).into(),
         )
     }
     pub fn err_k8s_login(meta: Metadata, e: impl Display) -> Self {
@@ -47,7 +49,7 @@ impl Response {
                 status: Status::Unknown,
                 status_time: std::time::Duration::ZERO.into(),
                 err_msg: Some(format!("ERROR CONNECTING TO K8S: {e}")),
-            }),
+            }).into(),
         )
     }
 
@@ -62,7 +64,21 @@ impl Response {
                 status: Status::Unknown,
                 status_time: std::time::Duration::ZERO.into(),
                 err_msg: Some(format!("ERROR DELETING DOCKER IMAGE: {e}")),
-            }),
+            }).into(),
+        )
+    }
+    pub fn err_static_del(meta: Metadata, e: impl Display) -> Self {
+        let chall_name = Some(meta.chall_name().to_string());
+        let poll_id = meta.poll_id();
+        Self(
+            StatusCode::STATIC_FILES_DEL_ERR,
+            FromDeploy::Status(DeploymentStatus {
+                chall_name,
+                poll_id,
+                status: Status::Unknown,
+                status_time: std::time::Duration::ZERO.into(),
+                err_msg: Some(format!("ERROR DELETING UPLOADED FILES: {e}")),
+            }).into(),
         )
     }
     pub fn err_k8s_del(meta: Metadata, e: impl Display) -> Self {
@@ -76,11 +92,30 @@ impl Response {
                 status: Status::Unknown,
                 status_time: std::time::Duration::ZERO.into(),
                 err_msg: Some(format!("ERROR DELETING K8S RESOURCES: {e}")),
-            }),
+            }).into(),
         )
     }
 
 
+    pub fn server_deploy_process_err(meta: Metadata, subcode: u64, message: &'static str, failure: FailureDetails) -> Self {
+        let chall_name = Some(meta.chall_name().to_string());
+        let poll_id = meta.poll_id();
+        let (status, status_time) = meta.status.into();
+        Self(
+            StatusCode::server_deploy_process_err(subcode, message),
+            ResponseBody::Failure(FailedDeploy {
+                status: FromDeploy::Status(DeploymentStatus {
+                    chall_name,
+                    poll_id,
+                    status,
+                    status_time,
+                    err_msg: Some(format!("DEPLOY PROCESS ERROR: {}", failure.message)),
+                }),
+                failure,
+            }),
+        )
+    }
+
     pub fn unknown_ise(meta: Metadata, e: impl Display) -> Self {
         let chall_name = Some(meta.chall_name().to_string());
         let poll_id = meta.poll_id();
@@ -92,7 +127,7 @@ impl Response {
                 status: Status::Unknown,
                 status_time: std::time::Duration::ZERO.into(),
                 err_msg: Some(format!("INTERNAL SERVER ERROR: {e}")),
-            }),
+            }).into(),
         )
     }
     pub fn io_err(meta: Metadata, e: impl Display) -> Self {
@@ -106,7 +141,7 @@ impl Response {
                 status: Status::Unknown,
                 status_time: std::time::Duration::ZERO.into(),
                 err_msg: Some(format!("IO ERROR: {e}")),
-            }),
+            }).into(),
         )
     }
     pub fn git_err(meta: Metadata, e: impl Display) -> Self {
@@ -120,8 +155,52 @@ impl Response {
                 status: Status::Unknown,
                 status_time: std::time::Duration::ZERO.into(),
                 err_msg: Some(format!("GIT ERROR: {e}")),
-            }),
+            }).into(),
+        )
+    }
+
+    pub fn err_instance(meta: Metadata, e: impl Display) -> Self {
+        let chall_name = Some(meta.chall_name().to_string());
+        let poll_id = meta.poll_id();
+        Self(
+            StatusCode::INSTANCE_ERR,
+            FromDeploy::Status(DeploymentStatus {
+                chall_name,
+                poll_id,
+                status: Status::Unknown,
+                status_time: std::time::Duration::ZERO.into(),
+                err_msg: Some(format!("INSTANCE ERROR: {e}")),
+            }).into(),
         )
     }
 
+    pub fn err_reconcile(meta: Metadata, e: impl Display) -> Self {
+        let chall_name = Some(meta.chall_name().to_string());
+        let poll_id = meta.poll_id();
+        Self(
+            StatusCode::RECONCILE_ERR,
+            FromDeploy::Status(DeploymentStatus {
+                chall_name,
+                poll_id,
+                status: Status::Unknown,
+                status_time: std::time::Duration::ZERO.into(),
+                err_msg: Some(format!("RECONCILE ERROR: {e}")),
+            }).into(),
+        )
+    }
+
+    pub fn err_scale(meta: Metadata, e: impl Display) -> Self {
+        let chall_name = Some(meta.chall_name().to_string());
+        let poll_id = meta.poll_id();
+        Self(
+            StatusCode::SCALE_ERR,
+            FromDeploy::Status(DeploymentStatus {
+                chall_name,
+                poll_id,
+                status: Status::Unknown,
+                status_time: std::time::Duration::ZERO.into(),
+                err_msg: Some(format!("SCALE ERROR: {e}")),
+            }).into(),
+        )
+    }
 }
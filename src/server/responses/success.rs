@@ -1,8 +1,18 @@
+use std::collections::BTreeMap;
+
+use arcs_retry::RetryStats;
 use yaml::YamlShape;
 
+use crate::emitter::outbox::OutboxEntry;
+use crate::instances::InstanceInfo;
+use crate::polling::{ build_times, push_progress };
+use crate::reconciler::DriftReport;
+use crate::gc::GcReport;
+use crate::discovery::ChallIndex;
+use crate::uptime::ChallUptime;
 use crate::server::utils::api_types::outgoing::{ DeploymentStatus, FromDeploy, Status };
 
-use super::{Metadata, Response, StatusCode};
+use super::{DeployedDeploy, FailedDeploy, Metadata, PushingDeploy, Response, ResponseBody, StatusCode};
 
 
 impl Response {
@@ -12,21 +22,62 @@ impl Response {
         let (status, status_time) = meta.status.into();
         Self(
             StatusCode::ACCEPTED,
-            FromDeploy::Status(DeploymentStatus { chall_name, poll_id, status, status_time, err_msg: None }),
+            FromDeploy::Status(DeploymentStatus { chall_name, poll_id, status, status_time, err_msg: None }).into(),
         )
     }
 
     pub fn success_deploy_poll(meta: Metadata, status: crate::polling::DeploymentStatus) -> Self {
+        // Polling a failed deployment still succeeded, the failure is carried by the status payload itself
+        if let crate::polling::DeploymentStatus::Failure(_, failure) = &status {
+            let chall_name = Some(meta.chall_name().to_string());
+            let poll_id = meta.poll_id();
+            let err_msg = Some(match failure.process_err {
+                Some((_, description)) => format!("DEPLOY PROCESS ERROR ({description}): {}", failure.reason),
+                None => failure.reason.clone(),
+            });
+            let failure = failure.details.clone();
+            let (status, status_time) = status.into();
+            return Self(
+                StatusCode::SUCCESS,
+                ResponseBody::Failure(FailedDeploy {
+                    status: FromDeploy::Status(DeploymentStatus { chall_name, poll_id, status, status_time, err_msg }),
+                    failure,
+                }),
+            );
+        }
+
         let chall_name = Some(meta.chall_name().to_string());
         let poll_id = meta.poll_id();
+        let layers = push_progress(poll_id);
+        let builds = if matches!(status, crate::polling::DeploymentStatus::Success(..)) { build_times(poll_id) } else { vec![] };
         let (status, status_time) = status.into();
+        let status = FromDeploy::Status(DeploymentStatus { chall_name, poll_id, status, status_time, err_msg: None });
+
+        if !builds.is_empty() {
+            Self(StatusCode::SUCCESS, ResponseBody::Deployed(DeployedDeploy { status, builds }))
+        } else if layers.is_empty() {
+            Self(StatusCode::SUCCESS, status.into())
+        } else {
+            Self(StatusCode::SUCCESS, ResponseBody::Pushing(PushingDeploy { status, layers }))
+        }
+    }
+
+    pub fn success_remove(meta: Metadata) -> Self {
+        let chall_name = Some(meta.chall_name().to_string());
+        let poll_id = meta.poll_id();
         Self(
             StatusCode::SUCCESS,
-            FromDeploy::Status(DeploymentStatus { chall_name, poll_id, status, status_time, err_msg: None }),
+            FromDeploy::Status(DeploymentStatus {
+                chall_name,
+                poll_id,
+                status: Status::Unknown,
+                status_time: std::time::Duration::ZERO.into(),
+                err_msg: None,
+            }).into(),
         )
     }
 
-    pub fn success_remove(meta: Metadata) -> Self {
+    pub fn success_scale(meta: Metadata) -> Self {
         let chall_name = Some(meta.chall_name().to_string());
         let poll_id = meta.poll_id();
         Self(
@@ -37,7 +88,7 @@ impl Response {
                 status: Status::Unknown,
                 status_time: std::time::Duration::ZERO.into(),
                 err_msg: None,
-            }),
+            }).into(),
         )
     }
 
@@ -52,7 +103,7 @@ impl Response {
                 status: Status::Unknown,
                 status_time: std::time::Duration::ZERO.into(),
                 err_msg: None,
-            }),
+            }).into(),
         )
     }
     pub fn conflict_modify_meta(meta: Metadata) -> Self {
@@ -66,14 +117,42 @@ impl Response {
                 status: Status::Unknown,
                 status_time: std::time::Duration::ZERO.into(),
                 err_msg: None,
-            }),
+            }).into(),
         )
     }
 
     pub fn success_list_challs(challs: &[impl ToString]) -> Self {
         Self(
             StatusCode::SUCCESS,
-            FromDeploy::ChallNameList(challs.iter().map(ToString::to_string).collect())
+            FromDeploy::ChallNameList(challs.iter().map(ToString::to_string).collect()).into()
         )
     }
+
+    pub fn success_chall_index(index: ChallIndex) -> Self {
+        Self(StatusCode::SUCCESS, index.into())
+    }
+
+    pub fn success_instance(instance: InstanceInfo) -> Self {
+        Self(StatusCode::SUCCESS, instance.into())
+    }
+
+    pub fn success_reconcile(report: DriftReport) -> Self {
+        Self(StatusCode::SUCCESS, report.into())
+    }
+
+    pub fn success_gc(report: GcReport) -> Self {
+        Self(StatusCode::SUCCESS, report.into())
+    }
+
+    pub fn success_uptime(history: BTreeMap<String, ChallUptime>) -> Self {
+        Self(StatusCode::SUCCESS, ResponseBody::Uptime(history))
+    }
+
+    pub fn success_retry_stats(stats: BTreeMap<&'static str, RetryStats>) -> Self {
+        Self(StatusCode::SUCCESS, ResponseBody::Retries(stats))
+    }
+
+    pub fn success_outbox(entries: Vec<OutboxEntry>) -> Self {
+        Self(StatusCode::SUCCESS, ResponseBody::Outbox(entries))
+    }
 }
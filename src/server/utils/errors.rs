@@ -1,76 +1,240 @@
-use yaml::File;
+use std::path::Path;
+
+use arcs_docker::{ DockerError, LintFinding };
+use arcs_k8s::{ K8sError, config::target_key };
+use arcs_retry::Retryable;
+use arcs_static::StaticError;
+use serde::Serialize;
+use thiserror::Error;
+use yaml::deploy::structs::DeployTargetType;
+
+use crate::server::responses::{ Metadata, Response };
 
 /// Enum that represents the different errors that can occur during the deploy process
-/// 
+///
 /// ## Variants
 /// - `FileUpload` - Error uploading file(s) to CDN
 /// - `Build` - Error building Docker image
 /// - `Push` - Error pushing to remote Docker registry
 /// - `Pull` - Error pulling from remote Docker registry
-/// - `Fetch` - Error fetching local challenge folder
 /// - `Deploy` - Error deploying to Kubernetes cluster
-#[derive(Debug, Clone)]
+#[derive(Debug, Error)]
 pub enum DeployProcessErr {
-    FileUpload(Vec<File>),
-    Build(String),
-    Push(String),
-    Pull(String),
-    Fetch(String),
-    Deploy(String),
+    #[error("Failed to upload: {0}")]
+    FileUpload(#[source] StaticError),
+    #[error("Failed to build: {0}")]
+    Build(#[source] DockerError),
+    #[error("Failed to push: {0}")]
+    Push(#[source] DockerError),
+    #[error("Failed to pull: {0}")]
+    Pull(#[source] DockerError),
+    #[error("Failed to deploy: {0}")]
+    Deploy(#[source] K8sError),
 }
-impl std::fmt::Display for DeployProcessErr {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+
+impl DeployProcessErr {
+    /// Subcode added to `550` in the status code of a failed deployment
+    ///
+    /// ## Subcodes
+    /// - `0` - Error uploading file(s) to CDN
+    /// - `1` - Dockerfile failed to build
+    /// - `2` - Registry failed to accept the push
+    /// - `3` - Registry failed to serve the pull
+    /// - `4` - Challenge folder couldn't be read
+    /// - `5` - Kubernetes rejected the challenge's objects
+    /// - `6` - Docker daemon unreachable
+    /// - `7` - Kubernetes cluster unreachable
+    /// - `8` - Challenge's pods never came up
+    /// - `9` - Dockerfile or build context failed the pre-build checks
+    pub fn subcode(&self) -> u64 {
+        use DeployProcessErr::*;
+        match self {
+            FileUpload(_) => 0,
+            Build(DockerError::DaemonUnreachable(_)) |
+            Push(DockerError::DaemonUnreachable(_)) |
+            Pull(DockerError::DaemonUnreachable(_)) => 6,
+            Build(DockerError::ChallFolder { .. }) => 4,
+            Build(DockerError::LintFailed { .. }) => 9,
+            Build(_) => 1,
+            Push(_) => 2,
+            Pull(_) => 3,
+            Deploy(e) if e.is_unreachable() => 7,
+            Deploy(e) if e.is_rollout_failure() => 8,
+            Deploy(_) => 5,
+        }
+    }
+
+    /// Short description of the failure, sent back as the status message
+    pub fn description(&self) -> &'static str {
+        match self.subcode() {
+            0 => "Error uploading file(s) to CDN",
+            1 => "Error building docker image",
+            2 => "Error pushing to registry",
+            3 => "Error pulling from registry",
+            4 => "Error fetching challenge folder",
+            6 => "Docker daemon unreachable",
+            7 => "Kubernetes cluster unreachable",
+            8 => "Challenge failed to start on Kubernetes",
+            9 => "Build context failed pre-build checks",
+            _ => "Error deploying to Kubernetes",
+        }
+    }
+
+    /// Step of the deploy process that failed
+    pub fn step(&self) -> FailedStep {
+        use DeployProcessErr::*;
         match self {
-            Self::FileUpload(files) => write!(f, "Failed to upload: {files:?}"),
-            Self::Build(e) => write!(f, "Failed to build: {e}"),
-            Self::Push(e) => write!(f, "Failed to push: {e}"),
-            Self::Pull(e) => write!(f, "Failed to pull: {e}"),
-            Self::Fetch(e) => write!(f, "Failed to fetch: {e}"),
-            Self::Deploy(e) => write!(f, "Failed to deploy: {e}"),
+            FileUpload(_) => FailedStep::FileUpload,
+            Build(_) => FailedStep::Build,
+            Push(_) => FailedStep::Push,
+            Pull(_) => FailedStep::Pull,
+            Deploy(_) => FailedStep::Deploy,
+        }
+    }
+
+    /// Machine-readable name of the subcode, see [`subcode`][DeployProcessErr::subcode]
+    pub fn kind(&self) -> &'static str {
+        match self.subcode() {
+            0 => "file_upload",
+            1 => "dockerfile_build",
+            2 => "registry_push",
+            3 => "registry_pull",
+            4 => "chall_folder",
+            6 => "docker_daemon_unreachable",
+            7 => "cluster_unreachable",
+            8 => "pods_not_running",
+            9 => "build_lint",
+            _ => "k8s_rejected",
+        }
+    }
+
+    /// Whether redeploying later might succeed without changing the challenge
+    pub fn retryable(&self) -> bool {
+        use DeployProcessErr::*;
+        match self {
+            FileUpload(e) => e.is_retryable(),
+            Build(e) | Push(e) | Pull(e) => e.is_retryable(),
+            Deploy(e) => e.is_retryable(),
+        }
+    }
+
+    /// Structured description of the failure, without the deploy target it happened on
+    pub fn details(&self) -> FailureDetails {
+        let build_log_tail = match self {
+            DeployProcessErr::Build(DockerError::BuildFailed { log_tail, .. }) => log_tail.clone(),
+            _ => vec![],
+        };
+        let pod_reasons = match self {
+            DeployProcessErr::Deploy(K8sError::NotRunning { reasons, .. }) => reasons.clone(),
+            _ => vec![],
+        };
+        let lint_findings = match self {
+            DeployProcessErr::Build(DockerError::LintFailed { findings, .. }) => findings.clone(),
+            _ => vec![],
+        };
+
+        FailureDetails {
+            step: self.step(),
+            kind: self.kind(),
+            message: self.reasons().join(": "),
+            target_type: None,
+            build_path: None,
+            build_log_tail,
+            lint_findings,
+            pod_reasons,
+            retryable: self.retryable(),
+        }
+    }
+
+    /// Every error in the chain, outermost first
+    pub fn reasons(&self) -> Vec<String> {
+        let mut reasons = vec![self.to_string()];
+        // The direct source is already part of this error's message
+        let mut source = std::error::Error::source(self).and_then(|err| err.source());
+        while let Some(err) = source {
+            reasons.push(err.to_string());
+            source = err.source();
         }
+        reasons
     }
 }
 
-// impl From<(DeployProcessErr, Metadata)> for Response {
-//     fn from((err, meta): (DeployProcessErr, Metadata)) -> Self {
-//         use DeployProcessErr::*;
-//         match err {
-//             FileUpload(files) => Response::server_deploy_process_err(
-//                 0,
-//                 "Error uploading file(s) to CDN",
-//                 Some(json!({ "files": files })),
-//                 meta,
-//             ),
-//             Build(s) => Response::server_deploy_process_err(
-//                 1,
-//                 "Error building docker image",
-//                 Some(json!({ "reason": s })),
-//                 meta,
-//             ),
-//             Push(s) => Response::server_deploy_process_err(
-//                 2,
-//                 "Error pushing to registry",
-//                 Some(json!({ "reason": s })),
-//                 meta,
-//             ),
-//             Pull(s) => Response::server_deploy_process_err(
-//                 3,
-//                 "Error pulling from registry",
-//                 Some(json!({ "reason": s })),
-//                 meta,
-//             ),
-//             Fetch(s) => Response::server_deploy_process_err(
-//                 4,
-//                 "Error fetching challenge folder",
-//                 Some(json!({ "reason": s })),
-//                 meta,
-//             ),
-//             Deploy(s) => Response::server_deploy_process_err(
-//                 5,
-//                 "Error deploying to Kubernetes",
-//                 Some(json!({ "reason": s })),
-//                 meta,
-//             ),
-//         }
-//     }
-// }
+impl From<(DeployProcessErr, Metadata)> for Response {
+    fn from((err, meta): (DeployProcessErr, Metadata)) -> Self {
+        Response::server_deploy_process_err(meta, err.subcode(), err.description(), err.details())
+    }
+}
+
+/// Step of a deployment that failed
+///
+/// ## Variants
+/// - `Setup` - Reading the chall.yaml or its deploy options, before anything was built
+/// - `Build` - Building a Docker image
+/// - `Push` - Pushing an image to the remote registry
+/// - `Pull` - Pulling an image from the remote registry
+/// - `Deploy` - Deploying a target to Kubernetes
+/// - `FileUpload` - Uploading the challenge's files to the CDN
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FailedStep {
+    Setup,
+    Build,
+    Push,
+    Pull,
+    Deploy,
+    FileUpload,
+}
+
+/// Machine-readable details of a failed deployment, sent along with its status so the admin panel can render them
+///
+/// ## Fields
+/// - `step` - Step of the deploy process that failed
+/// - `kind` - What went wrong, e.g. `dockerfile_build` or `cluster_unreachable`
+/// - `message` - Every error in the chain, outermost first
+/// - `target_type` - Deploy target (`web`, `nc`, ...) that failed, if the failure was specific to one
+/// - `build_path` - Folder the failed target is built from, relative to the challenge folder
+/// - `build_log_tail` - Last lines of the output of a failed Docker build
+/// - `lint_findings` - Pre-build checks the Dockerfile or the build context failed
+/// - `pod_reasons` - Why the target's pods didn't come up, e.g. `CrashLoopBackOff`
+/// - `retryable` - Whether redeploying later might succeed without changing the challenge
+#[derive(Debug, Clone, Serialize)]
+pub struct FailureDetails {
+    pub step: FailedStep,
+    pub kind: &'static str,
+    pub message: String,
+    pub target_type: Option<&'static str>,
+    pub build_path: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub build_log_tail: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub lint_findings: Vec<LintFinding>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub pod_reasons: Vec<String>,
+    pub retryable: bool,
+}
+
+impl FailureDetails {
+    /// Failure that happened before any step of the deploy process started
+    pub fn setup(message: impl ToString) -> Self {
+        Self {
+            step: FailedStep::Setup,
+            kind: "setup",
+            message: message.to_string(),
+            target_type: None,
+            build_path: None,
+            build_log_tail: vec![],
+            lint_findings: vec![],
+            pod_reasons: vec![],
+            retryable: false,
+        }
+    }
+
+    /// Attaches the deploy target the failure happened on
+    pub fn for_target(self, target_type: DeployTargetType, build_path: Option<&Path>) -> Self {
+        Self {
+            target_type: Some(target_key(target_type)),
+            build_path: build_path.map(|path| path.to_string_lossy().to_string()),
+            ..self
+        }
+    }
+}
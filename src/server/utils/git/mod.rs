@@ -1,6 +1,5 @@
 mod signature_auth;
 mod remote;
-mod files;
 
 mod stage;
 mod commit;
@@ -10,8 +9,10 @@ mod merge;
 mod prep;
 
 use std::path::Path;
+use arcs_retry::retry_blocking_if;
 use git2::Repository;
 
+use crate::discovery::{ ChallIndex, discover_challs };
 use crate::server::responses::{Metadata, Response};
 use crate::env::git_branch;
 use crate::logging::*;
@@ -21,6 +22,15 @@ pub use prep::{ make_commit, push_all };
 
 pub type GitResult<T = ()> = Result<T, git2::Error>;
 
+/// Whether a failed fetch/push is worth trying again, i.e. the remote couldn't be reached (rather than rejecting the
+/// credentials or the refs)
+fn is_transient_git_err(err: &git2::Error) -> bool {
+    use git2::{ ErrorClass, ErrorCode };
+
+    let network_error = matches!(err.class(), ErrorClass::Net | ErrorClass::Http | ErrorClass::Ssh | ErrorClass::Os);
+    network_error && !matches!(err.code(), ErrorCode::Auth | ErrorCode::Certificate | ErrorCode::NotFastForward)
+}
+
 
 
 fn get_branch_refspec(repo: &Repository, meta: &Metadata) -> Result<String, Response> {
@@ -40,7 +50,30 @@ fn get_branch_refspec(repo: &Repository, meta: &Metadata) -> Result<String, Resp
     Ok(branch_ref_name)
 }
 
-pub fn ensure_repo_up_to_date(repo_path: &Path, meta: &Metadata) -> Result<bool, Response> {
+/// Runs git work on tokio's blocking thread pool, since git2 blocks and the retries of fetches and pushes sleep between
+/// attempts, which would otherwise stall the async workers serving requests and deployments
+async fn run_blocking<T: Send + 'static>(meta: &Metadata, work: impl FnOnce() -> Result<T, Response> + Send + 'static) -> Result<T, Response> {
+    match tokio::task::spawn_blocking(work).await {
+        Ok(result) => result,
+        Err(e) => {
+            error!("Git task failed to complete: {e}");
+            Err(Response::git_err(meta.clone(), format!("Git task failed to complete: {e}")))
+        },
+    }
+}
+
+/// Commits every unstaged change of the repo and merges the remote's new commits into it, on the blocking thread pool
+/// 
+/// ## Returns
+/// - `Ok(bool)` - Whether the remote could be reached
+/// - `Err(Response)` - Git error response, the repo is rolled back if the merge failed
+pub async fn ensure_repo_up_to_date(repo_path: &Path, meta: &Metadata) -> Result<bool, Response> {
+    let repo_path = repo_path.to_path_buf();
+    let task_meta = meta.clone();
+    run_blocking(meta, move || ensure_repo_up_to_date_blocking(&repo_path, &task_meta)).await
+}
+
+fn ensure_repo_up_to_date_blocking(repo_path: &Path, meta: &Metadata) -> Result<bool, Response> {
     let meta = meta.clone();
 
     let Ok(repo) = Repository::open(repo_path) else {
@@ -68,7 +101,7 @@ pub fn ensure_repo_up_to_date(repo_path: &Path, meta: &Metadata) -> Result<bool,
 
 
     let could_connect = if let Some(mut remote) = remote::try_get_connected_remote(&repo).unwrap() {
-        if let Err(e) = fetch::fetch_from_remote(&mut remote) {
+        if let Err(e) = retry_blocking_if("git fetch", git_branch(), is_transient_git_err, || fetch::fetch_from_remote(&mut remote)) {
             error!("Failed to fetch from remote: {e:?}");
             return Err(Response::git_err(meta, format!("Failed to fetch from remote: {e:?}")));
         }
@@ -106,33 +139,45 @@ pub fn ensure_repo_up_to_date(repo_path: &Path, meta: &Metadata) -> Result<bool,
     Ok(could_connect)
 }
 
+/// Commit the repository's `HEAD` points to, `None` if the repository can't be opened or has no commits
+pub fn head_commit(repo_path: &Path) -> Option<String> {
+    let repo = Repository::open(repo_path).ok()?;
+    let commit = repo.head().ok()?.peel_to_commit().ok()?;
+
+    Some(commit.id().to_string())
+}
+
 static LAST_PULL_TIME: std::sync::Mutex<std::time::SystemTime> = std::sync::Mutex::new(std::time::SystemTime::UNIX_EPOCH);
 
-pub fn get_all_chall_names(repo_path: &Path, meta: &Metadata) -> Result<Vec<String>, Response> {
-    let Ok(repo) = Repository::open(repo_path) else {
-        error!("Failed to open repository");
-        return Err(Response::git_err(meta.clone(), "Failed to open repository"));
-    };
-    trace!("Opened repository");
+/// Index of every challenge in the challenge repo, pulling the repo first if it wasn't pulled in the last minute
+/// 
+/// Every folder with a chall.yaml is listed, including ones whose chall.yaml doesn't parse (in the index's `invalid`), so
+/// they can still be removed.
+pub async fn get_chall_index(repo_path: &Path, meta: &Metadata) -> Result<ChallIndex, Response> {
+    let repo_path = repo_path.to_path_buf();
+    let task_meta = meta.clone();
+    run_blocking(meta, move || get_chall_index_blocking(&repo_path, &task_meta)).await
+}
 
+fn get_chall_index_blocking(repo_path: &Path, meta: &Metadata) -> Result<ChallIndex, Response> {
     if let Ok(mut lock) = LAST_PULL_TIME.try_lock() {
         if let Ok(elapsed) = lock.elapsed() {
             if elapsed.as_secs() > 60 {
-                if ensure_repo_up_to_date(repo_path, meta).is_ok() {
+                if ensure_repo_up_to_date_blocking(repo_path, meta).is_ok() {
                     *lock = std::time::SystemTime::now();
                 }
             }
         }
     }
 
-    let chall_names = match files::get_all_chall_names(&repo) {
-        Ok(chall_names) => chall_names,
+    let index = match discover_challs(repo_path) {
+        Ok(index) => index,
         Err(e) => {
-            error!("Failed to get all chall names: {e:?}");
-            return Err(Response::git_err(meta.clone(), format!("Failed to get all chall names: {e:?}")));
+            error!("Failed to index challenges: {e:?}");
+            return Err(Response::git_err(meta.clone(), format!("Failed to index challenges: {e:?}")));
         },
     };
+    debug!("Found challs: {:?}", index.folders());
 
-    Ok(chall_names)
+    Ok(index)
 }
-
@@ -7,7 +7,9 @@ use git2::{
     PushOptions
 };
 
-use super::GitResult;
+use arcs_retry::retry_blocking_if;
+
+use super::{ GitResult, is_transient_git_err, run_blocking };
 use super::get_branch_refspec;
 use super::stage::stage_all_unstaged;
 use super::remote::get_remote;
@@ -53,7 +55,14 @@ pub fn make_commit(repo_path: &Path, files_to_add: &[&Path], message: &str, meta
     }
 }
 
-pub fn push_all(repo_path: &Path, meta: &Metadata) -> Result<(), Response> {
+/// Pushes the local branch to the remote, on the blocking thread pool
+pub async fn push_all(repo_path: &Path, meta: &Metadata) -> Result<(), Response> {
+    let repo_path = repo_path.to_path_buf();
+    let task_meta = meta.clone();
+    run_blocking(meta, move || push_all_blocking(&repo_path, &task_meta)).await
+}
+
+fn push_all_blocking(repo_path: &Path, meta: &Metadata) -> Result<(), Response> {
     let meta = meta.clone();
 
     let Ok(repo) = Repository::open(repo_path) else {
@@ -70,7 +79,11 @@ pub fn push_all(repo_path: &Path, meta: &Metadata) -> Result<(), Response> {
     };
     let branch_refspec = get_branch_refspec(&repo, &meta)?;
 
-    if let Err(e) = remote.push::<&str>(&[&branch_refspec], Some(PushOptions::new().remote_callbacks(get_auth_callbacks()))) {
+    let pushed = retry_blocking_if("git push", &branch_refspec, is_transient_git_err, || {
+        remote.push::<&str>(&[&branch_refspec], Some(PushOptions::new().remote_callbacks(get_auth_callbacks())))
+    });
+
+    if let Err(e) = pushed {
         error!("Failed to push to remote: {e:?}");
         Err(Response::git_err(meta, format!("Failed to push to remote: {e:?}")))
     } else {
@@ -1,3 +1,4 @@
+use arcs_k8s::config::{ ExposedPort, NetworkProtocol };
 use yaml::deploy::structs::{DeployLink, DeployTargetType};
 
 use crate::env::{deploy_address, display_address};
@@ -8,18 +9,53 @@ fn address() -> &'static str {
     deploy_address()
 }
 
-pub fn links_from_port_listing(port_descriptors: &Option<Vec<(DeployTargetType, Vec<i32>)>>) -> Vec<DeployLink> {
+/// Strips the port and path off of an address, leaving only its host
+fn host_of(address: &str) -> &str {
+    let address = address.split('/').next().unwrap_or(address);
+
+    match address.rsplit_once(':') {
+        Some((host, port)) if port.chars().all(|c| c.is_ascii_digit()) => host,
+        _ => address,
+    }
+}
+
+/// A link to a single exposed port of a deployed target
+/// 
+/// ## Fields
+/// - `name` - Name of the port in the target's deploy config
+/// - `protocol` - Transport protocol of the port
+/// - `host` - Host the challenge is reachable on, which depends on the cluster it runs on
+/// - `node_port` - Port the challenge is reachable on from outside the cluster
+/// - `link` - The link itself, as sent to the webhook server
+#[derive(Debug, Clone)]
+pub struct PortLink {
+    pub name: String,
+    pub protocol: NetworkProtocol,
+    pub host: String,
+    pub node_port: i32,
+    pub link: DeployLink,
+}
+
+pub fn port_links_from_port_listing(port_descriptors: &Option<Vec<(DeployTargetType, Vec<ExposedPort>)>>) -> Vec<PortLink> {
     let mut links = vec![];
 
     for (target_type, ports) in port_descriptors.iter().flatten() {
         for port in ports.iter() {
+            let node_port = port.node_port;
+            let address = port.host.as_deref().unwrap_or(address());
             links.push(
-                DeployLink {
-                    deploy_target: *target_type,
-                    link: if *target_type == DeployTargetType::Nc {
-                        format!("{} {}", address(), port)
-                    } else {
-                        format!("{}:{}", address(), port)
+                PortLink {
+                    name: port.name.clone(),
+                    protocol: port.protocol,
+                    host: host_of(address).to_string(),
+                    node_port,
+                    link: DeployLink {
+                        deploy_target: *target_type,
+                        link: if *target_type == DeployTargetType::Nc {
+                            format!("{} {}", address, node_port)
+                        } else {
+                            format!("{}:{}", address, node_port)
+                        },
                     },
                 }
             );
@@ -27,4 +63,11 @@ pub fn links_from_port_listing(port_descriptors: &Option<Vec<(DeployTargetType,
     }
 
     links
-}
\ No newline at end of file
+}
+
+pub fn links_from_port_listing(port_descriptors: &Option<Vec<(DeployTargetType, Vec<ExposedPort>)>>) -> Vec<DeployLink> {
+    port_links_from_port_listing(port_descriptors)
+        .into_iter()
+        .map(|port_link| port_link.link)
+        .collect()
+}
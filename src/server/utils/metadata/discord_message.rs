@@ -1,18 +1,25 @@
+use arcs_k8s::config::ExposedPort;
 use yaml::deploy::structs::{DeployLink, DeployTargetType};
 
 use crate::server::responses::Metadata;
+use super::container_links::port_links_from_port_listing;
 
 use std::fmt::{ Write, Error };
 
 pub fn build_discord_message(
     meta: &Metadata,
-    ports: &Option<Vec<(DeployTargetType, Vec<i32>)>>,
+    ports: &Option<Vec<(DeployTargetType, Vec<ExposedPort>)>>,
     complete_links: &[DeployLink]
 ) -> Result<String, Error> {
     let mut disc_message = String::with_capacity(240);
 
-    if let Some(ports) = ports {
-        writeln!(disc_message, "Successfully deployed **{}** on port(s) {ports:?}", meta.chall_name())?;
+    if ports.is_some() {
+        let port_descriptions = port_links_from_port_listing(ports)
+            .iter()
+            .map(|port_link| format!("{} ({}/{})", port_link.node_port, port_link.name, port_link.protocol))
+            .collect::<Vec<_>>();
+
+        writeln!(disc_message, "Successfully deployed **{}** on port(s) {}", meta.chall_name(), port_descriptions.join(", "))?;
     } else {
         writeln!(disc_message, "Successfully deployed **{}**. No ports provided", meta.chall_name())?;
     }
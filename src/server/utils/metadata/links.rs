@@ -92,6 +92,8 @@ pub fn static_links_to_deploy_links(links: Vec<String>) -> Vec<DeployLink> {
 
 
 
+use arcs_k8s::config::ExposedPort;
+
 use super::container_links::links_from_port_listing;
 
 pub fn into_webhook_links(links: Vec<DeployLink>) -> Vec<WebhookLink> {
@@ -111,7 +113,7 @@ pub fn into_webhook_links(links: Vec<DeployLink>) -> Vec<WebhookLink> {
         .collect()
 }
 
-pub fn get_all_links(meta: &Metadata, yaml: &YamlShape, ports: &Option<Vec<(DeployTargetType, Vec<i32>)>>) -> Result<Vec<DeployLink>, String> {
+pub fn get_all_links(meta: &Metadata, yaml: &YamlShape, ports: &Option<Vec<(DeployTargetType, Vec<ExposedPort>)>>) -> Result<Vec<DeployLink>, String> {
     let mut links = static_links_to_deploy_links(get_static_file_links(meta, yaml)?);
     links.extend(links_from_port_listing(ports));
 
@@ -1,4 +1,4 @@
-use crate::polling::{PollingId, advance_deployment_step};
+use crate::polling::{PollingId, DeployStep, advance_deployment_step};
 use crate::server::responses::Metadata;
 use crate::emitter::send_deployment_failure;
 use crate::logging::*;
@@ -23,3 +23,26 @@ pub fn advance_with_fail_log(polling_id: PollingId) -> bool {
         }
     }
 }
+
+/// Convenience function that moves an ongoing deployment back to the `Building` step and logs the result.
+/// 
+/// Used when a deployment builds more than one image (one per deploy target, plus the static file container).
+pub fn restart_steps_with_fail_log(polling_id: PollingId) -> bool {
+    reset_step_with_fail_log(polling_id, DeployStep::Building)
+}
+
+/// Convenience function that moves an ongoing deployment back to the given step and logs the result.
+/// 
+/// Used to push and deploy the deploy targets one after the other, once all of them are built.
+pub fn reset_step_with_fail_log(polling_id: PollingId, step: DeployStep) -> bool {
+    match advance_deployment_step(polling_id, Some(step)) {
+        Ok(new_step) => {
+            info!("Deployment step reset to `{}` for {polling_id}", new_step.get_str());
+            true
+        }
+        Err(e) => {
+            error!("Failed to reset deployment step for {polling_id} (KILLED): {e:?}");
+            false
+        }
+    }
+}
@@ -1,10 +1,17 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use serde::Deserialize;
 use tokio::fs::{ read_to_string, write };
 
-use arcs_static::{chall_yaml_path, fetch_chall_yaml};
+use arcs_k8s::config::{ TargetConfig, TargetOptions, target_key };
+use arcs_static::chall_yaml_path;
 use yaml_editor::Modifications;
+use yaml::deploy::structs::{ DeployTarget, DeployTargetType };
 use yaml::YamlShape;
 
 use crate::server::responses::{Response, Metadata};
+use crate::uptime::HealthcheckOption;
 use crate::logging::*;
 
 
@@ -35,8 +42,9 @@ pub async fn update_yaml_file(chall_folder_name: &str, modifications: Modificati
         },
     };
 
-    let new_yaml = match fetch_chall_yaml(chall_folder_name).await {
-        Some(Ok(new_yaml)) => new_yaml,
+    // Validated with the deploy options too, so a modification can't leave a target with options that break its deployment
+    let new_yaml = match load_chall_yaml(chall_folder_name).await {
+        Some(Ok(new_yaml)) => new_yaml.shape,
         Some(Err(e)) => {
             debug!("Yaml error: {e}");
             if std::fs::write(&yaml_location, old_yaml).is_ok() {
@@ -61,7 +69,7 @@ pub async fn update_yaml_file(chall_folder_name: &str, modifications: Modificati
     Ok(new_yaml)
 }
 
-pub async fn handle_yaml_get(meta: &Metadata) -> Option<YamlShape> {
+pub async fn handle_yaml_get(meta: &Metadata) -> Option<ChallYaml> {
     use crate::polling::fail_deployment;
     use crate::server::utils::state_management::send_failure_message;
     use yaml::YamlVerifyError;
@@ -69,14 +77,14 @@ pub async fn handle_yaml_get(meta: &Metadata) -> Option<YamlShape> {
     let meta = meta.clone();
     let polling_id = meta.poll_id();
 
-    let chall_yaml = fetch_chall_yaml(meta.chall_name().as_str()).await;
+    let chall_yaml = load_chall_yaml(meta.chall_name().as_str()).await;
 
     if let Some(chall_yaml) = chall_yaml {
         match chall_yaml {
             Ok(yaml) => Some(yaml),
             Err(e) => {
-                error!("Failed to fetch challenge yaml for {} ({}) with err {:?}", meta.chall_name(), polling_id, e);
-                if fail_deployment(polling_id, e.to_string()).is_err() {
+                error!("Failed to fetch challenge yaml for {} ({}) with err {}", meta.chall_name(), polling_id, e);
+                if fail_deployment(polling_id, e).is_err() {
                     error!("`fail_deployment` failed to mark polling id {polling_id} as errored");
                 }
                 send_failure_message(&meta, "Fetch Challenge YAML").await;
@@ -92,3 +100,105 @@ pub async fn handle_yaml_get(meta: &Metadata) -> Option<YamlShape> {
         None
     }
 }
+
+
+/// Options of a deploy target that the shared chall.yaml parser doesn't model, from its `deploy.<target>` section
+///
+/// ## Fields
+/// - `target` - Options the target's deploy config is built from (see [`TargetOptions`][TargetOptions])
+/// - `healthcheck` - Scripted uptime check of the target's main port
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct DeployOptions {
+    #[serde(flatten)]
+    pub target: TargetOptions,
+    pub healthcheck: Option<HealthcheckOption>,
+}
+
+/// A challenge's chall.yaml, read once and validated by the shared parser, along with the options of its deploy targets
+///
+/// Only the targets that the shared parser returns get options, so the two can't disagree on what the targets are, and
+/// every deploy config is built from this single load (see [`target_configs`][ChallYaml::target_configs]).
+///
+/// ## Fields
+/// - `shape` - The chall.yaml as the shared parser returns it
+/// - `options` - Options of every deploy target, keyed by the target's key in the `deploy` section
+#[derive(Debug, Clone)]
+pub struct ChallYaml {
+    pub shape: YamlShape,
+    options: HashMap<String, DeployOptions>,
+}
+
+impl ChallYaml {
+    /// Every deploy target of the challenge, as the shared parser returns them
+    pub fn targets(&self) -> Vec<(DeployTarget, DeployTargetType)> {
+        self.shape.deploy()
+            .map(|deploy_options| deploy_options.clone().into_iter().collect())
+            .unwrap_or_default()
+    }
+
+    /// Options of a deploy target, the defaults if its section doesn't set any
+    pub fn options(&self, target_type: DeployTargetType) -> DeployOptions {
+        self.options.get(target_key(target_type)).cloned().unwrap_or_default()
+    }
+
+    /// Deploy config of every target of the challenge, in the order the shared parser returns the targets
+    pub fn target_configs(&self, chall_name: &str) -> Vec<(DeployTargetType, TargetConfig)> {
+        let targets = self.targets();
+
+        targets
+            .iter()
+            .map(|(target, target_type)| {
+                let options = self.options(*target_type).target;
+                (*target_type, TargetConfig::new(chall_name, target, *target_type, &targets, options, self.shape.flag_str()))
+            })
+            .collect()
+    }
+}
+
+/// Parses a chall.yaml with the shared parser, then reads the options of each deploy target it returns from the same text
+///
+/// ## Returns
+/// - `Ok(ChallYaml)` - The parsed chall.yaml
+/// - `Err(String)` - The chall.yaml, or the options of one of its deploy targets, are invalid
+pub fn parse_chall_yaml(yaml_text: &str, folder_path: Option<&Path>) -> Result<ChallYaml, String> {
+    let shape = YamlShape::try_from_str(yaml_text, &Default::default(), folder_path)
+        .map_err(|e| format!("Invalid chall.yaml: {e}"))?;
+    let raw: serde_yaml::Value = serde_yaml::from_str(yaml_text)
+        .map_err(|e| format!("Invalid chall.yaml: {e}"))?;
+
+    let mut options = HashMap::new();
+    for (_, target_type) in shape.deploy().map(|deploy_options| deploy_options.clone().into_iter()).into_iter().flatten() {
+        let key = target_key(target_type);
+        let Some(mut section) = raw.get("deploy").and_then(|deploy| deploy.get(key)).cloned() else { continue };
+
+        // A plain `build` is the build path, which is the shared parser's, `build` options are a mapping
+        if let Some(section) = section.as_mapping_mut() {
+            if section.get("build").is_some_and(serde_yaml::Value::is_string) {
+                section.remove("build");
+            }
+        }
+
+        let target_options = if section.is_null() {
+            DeployOptions::default()
+        } else {
+            serde_yaml::from_value(section).map_err(|e| format!("Invalid chall.yaml: deploy.{key}: {e}"))?
+        };
+        options.insert(key.to_string(), target_options);
+    }
+
+    Ok(ChallYaml { shape, options })
+}
+
+/// Reads and parses a challenge's chall.yaml, see [`parse_chall_yaml`][parse_chall_yaml]
+///
+/// ## Returns
+/// - `None` - The challenge has no chall.yaml
+/// - `Some(Ok(ChallYaml))` - The parsed chall.yaml
+/// - `Some(Err(String))` - The chall.yaml, or the options of one of its deploy targets, are invalid
+pub async fn load_chall_yaml(chall_folder_name: &str) -> Option<Result<ChallYaml, String>> {
+    let yaml_path = chall_yaml_path(chall_folder_name);
+    let yaml_text = read_to_string(&yaml_path).await.ok()?;
+
+    Some(parse_chall_yaml(&yaml_text, yaml_path.parent()))
+}
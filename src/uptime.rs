@@ -0,0 +1,303 @@
+use lazy_static::lazy_static;
+use std::collections::{ BTreeMap, HashMap, VecDeque };
+use std::sync::{ Mutex, MutexGuard, PoisonError };
+use std::time::{ Duration, SystemTime, UNIX_EPOCH };
+use serde::{ Deserialize, Serialize };
+use tokio::io::{ AsyncReadExt, AsyncWriteExt };
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+
+use arcs_k8s::config::{ ExposedPort, NetworkProtocol, target_key, target_type_from_key };
+use yaml::deploy::structs::DeployTargetType;
+
+use crate::deploy_records::{ DeployRecord, load_deploy_records };
+use crate::emitter::send_developer_alert;
+use crate::env::uptime_check_interval;
+use crate::server::utils::api_types::incoming::AlertLevel;
+use crate::server::utils::metadata::container_links::{ PortLink, port_links_from_port_listing };
+use crate::server::utils::yaml::load_chall_yaml;
+use crate::logging::*;
+
+const DEFAULT_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+const DEFAULT_CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Number of checks kept in each challenge's history
+const HISTORY_LEN: usize = 60;
+
+/// Most bytes read from a challenge while waiting for its expected banner
+const MAX_BANNER_LEN: usize = 8192;
+
+/// How often every deployed challenge is checked, set with `UPTIME_CHECK_INTERVAL_SECONDS`
+fn interval() -> Duration {
+    uptime_check_interval()
+        .and_then(|secs| secs.parse().ok())
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_CHECK_INTERVAL)
+}
+
+/// Scripted check of a deploy target's main port, from the `healthcheck` key of its section of the chall.yaml
+///
+/// ```yaml
+/// deploy:
+///   nc:
+///     expose: 1337/tcp
+///     healthcheck:
+///       send: "1\n"
+///       expect: "Welcome to"
+///       timeout: 10
+/// ```
+///
+/// For web and admin targets, `path` is the path requested instead (`/` by default).
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct HealthcheckOption {
+    pub send: Option<String>,
+    pub expect: Option<String>,
+    pub path: Option<String>,
+    pub timeout: Option<u64>,
+}
+
+impl HealthcheckOption {
+    fn timeout(&self) -> Duration {
+        self.timeout.map(Duration::from_secs).unwrap_or(DEFAULT_CHECK_TIMEOUT)
+    }
+}
+
+/// Result of a single check of every exposed port of a challenge
+///
+/// ## Fields
+/// - `checked_at` - Unix timestamp of the check
+/// - `up` - Whether every port passed its check
+/// - `failures` - Why the ports that failed their check failed
+#[derive(Debug, Clone, Serialize)]
+pub struct UptimeSample {
+    pub checked_at: u64,
+    pub up: bool,
+    pub failures: Vec<String>,
+}
+
+/// Up/down history of a deployed challenge, oldest check first
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ChallUptime {
+    pub up: Option<bool>,
+    pub history: VecDeque<UptimeSample>,
+}
+
+lazy_static! {
+    static ref UPTIME: Mutex<HashMap<String, ChallUptime>> = Mutex::new(HashMap::new());
+}
+
+fn uptime() -> MutexGuard<'static, HashMap<String, ChallUptime>> {
+    UPTIME.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Up/down history of every challenge that is being checked, keyed by challenge name
+pub fn uptime_history() -> BTreeMap<String, ChallUptime> {
+    uptime().iter().map(|(name, history)| (name.clone(), history.clone())).collect()
+}
+
+fn now_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+/// Connects to a TCP port, optionally sending a line and waiting for an expected banner
+async fn check_tcp(host: &str, port: i32, healthcheck: Option<&HealthcheckOption>) -> Result<(), String> {
+    let check_timeout = healthcheck.map(HealthcheckOption::timeout).unwrap_or(DEFAULT_CHECK_TIMEOUT);
+    let address = format!("{host}:{port}");
+
+    let mut stream = match timeout(check_timeout, TcpStream::connect(&address)).await {
+        Ok(Ok(stream)) => stream,
+        Ok(Err(e)) => return Err(format!("Failed to connect to {address}: {e}")),
+        Err(_) => return Err(format!("Timed out connecting to {address}")),
+    };
+
+    let Some(healthcheck) = healthcheck else { return Ok(()) };
+
+    if let Some(send) = &healthcheck.send {
+        if let Err(e) = stream.write_all(send.as_bytes()).await {
+            return Err(format!("Failed to send healthcheck input to {address}: {e}"));
+        }
+    }
+
+    let Some(expect) = &healthcheck.expect else { return Ok(()) };
+
+    let read_banner = async {
+        let mut received = Vec::new();
+        let mut buf = [0u8; 1024];
+        loop {
+            let read = stream.read(&mut buf).await.map_err(|e| format!("Failed to read from {address}: {e}"))?;
+            if read == 0 {
+                return Err(format!("{address} closed the connection before sending {expect:?}"));
+            }
+            received.extend_from_slice(&buf[..read]);
+
+            if String::from_utf8_lossy(&received).contains(expect.as_str()) {
+                return Ok(());
+            }
+            if received.len() > MAX_BANNER_LEN {
+                return Err(format!("{address} didn't send {expect:?} in its first {MAX_BANNER_LEN} bytes"));
+            }
+        }
+    };
+
+    match timeout(check_timeout, read_banner).await {
+        Ok(result) => result,
+        Err(_) => Err(format!("Timed out waiting for {expect:?} from {address}")),
+    }
+}
+
+/// Requests a page of a web target, which is up as long as it doesn't respond with a server error
+async fn check_http(client: &reqwest::Client, host: &str, port: i32, healthcheck: Option<&HealthcheckOption>) -> Result<(), String> {
+    let check_timeout = healthcheck.map(HealthcheckOption::timeout).unwrap_or(DEFAULT_CHECK_TIMEOUT);
+    let path = healthcheck.and_then(|healthcheck| healthcheck.path.as_deref()).unwrap_or("/");
+    let url = format!("http://{host}:{port}/{}", path.trim_start_matches('/'));
+
+    let response = match client.get(&url).timeout(check_timeout).send().await {
+        Ok(response) => response,
+        Err(e) => return Err(format!("Failed to request {url}: {e}")),
+    };
+
+    if response.status().is_server_error() {
+        return Err(format!("{url} responded with {}", response.status()));
+    }
+
+    if let Some(expect) = healthcheck.and_then(|healthcheck| healthcheck.expect.as_deref()) {
+        let body = response.text().await.map_err(|e| format!("Failed to read response of {url}: {e}"))?;
+        if !body.contains(expect) {
+            return Err(format!("{url} didn't respond with {expect:?}"));
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks a single exposed port of a deployed target
+///
+/// The healthcheck only applies to the target's main port, other ports just have to accept connections.
+/// UDP ports are connectionless, so they are skipped.
+async fn check_port(client: &reqwest::Client, port_link: &PortLink, healthcheck: Option<&HealthcheckOption>) -> Result<(), String> {
+    if port_link.protocol == NetworkProtocol::Udp {
+        trace!("Skipping uptime check of UDP port {}", port_link.node_port);
+        return Ok(());
+    }
+
+    let target_type = port_link.link.deploy_target;
+    let healthcheck = healthcheck.filter(|_| port_link.name == target_key(target_type));
+
+    let result = match target_type {
+        DeployTargetType::Web | DeployTargetType::Admin if port_link.name == target_key(target_type) =>
+            check_http(client, &port_link.host, port_link.node_port, healthcheck).await,
+        _ => check_tcp(&port_link.host, port_link.node_port, healthcheck).await,
+    };
+
+    result.map_err(|e| format!("{} ({}): {e}", port_link.link.link, port_link.name))
+}
+
+/// Checks every exposed port of a deployed challenge
+async fn check_chall(client: &reqwest::Client, record: &DeployRecord) -> UptimeSample {
+    let chall_yaml = match load_chall_yaml(&record.chall_name).await {
+        Some(Ok(chall_yaml)) => Some(chall_yaml),
+        Some(Err(e)) => {
+            warn!("Failed to read healthcheck options for {}, falling back to connection checks: {e}", record.chall_name);
+            None
+        },
+        None => None,
+    };
+
+    let port_listing: Vec<(DeployTargetType, Vec<ExposedPort>)> = record.targets
+        .iter()
+        .filter_map(|target| Some((target_type_from_key(&target.target)?, target.ports.clone())))
+        .collect();
+
+    let mut failures = vec![];
+    for port_link in port_links_from_port_listing(&Some(port_listing)) {
+        let healthcheck = chall_yaml
+            .as_ref()
+            .and_then(|chall_yaml| chall_yaml.options(port_link.link.deploy_target).healthcheck);
+
+        if let Err(e) = check_port(client, &port_link, healthcheck.as_ref()).await {
+            debug!("Uptime check of {} failed: {e}", record.chall_name);
+            failures.push(e);
+        }
+    }
+
+    UptimeSample { checked_at: now_timestamp(), up: failures.is_empty(), failures }
+}
+
+/// Records a check of a challenge, returning whether it changed the challenge's up/down state
+fn record_sample(chall_name: &str, sample: UptimeSample) -> bool {
+    let mut uptime = uptime();
+    let chall_uptime = uptime.entry(chall_name.to_string()).or_default();
+
+    // A challenge that is down from the first check on is still worth an alert, one that is up isn't
+    let changed = chall_uptime.up.unwrap_or(true) != sample.up;
+
+    chall_uptime.up = Some(sample.up);
+    chall_uptime.history.push_back(sample);
+    while chall_uptime.history.len() > HISTORY_LEN {
+        chall_uptime.history.pop_front();
+    }
+
+    changed
+}
+
+async fn send_uptime_alert(chall_name: &str, sample: &UptimeSample) {
+    let message = if sample.up {
+        format!("**{chall_name}** is back up")
+    } else {
+        let mut message = format!("**{chall_name}** is down:");
+        for failure in &sample.failures {
+            message.push_str(&format!("\n- {failure}"));
+        }
+        message
+    };
+
+    let data = serde_json::to_value(sample).unwrap_or_default();
+    if let Err(e) = send_developer_alert(AlertLevel::Warn, &message, data).await {
+        error!("Failed to send uptime alert for {chall_name}: {e}");
+    }
+}
+
+/// Checks every challenge in the deploy records once, alerting on the ones that went down or came back up
+pub async fn check_all_challs() -> Result<(), String> {
+    let records = load_deploy_records()?;
+    let client = reqwest::Client::new();
+
+    // Challenges that were deleted since the last check are no longer tracked
+    uptime().retain(|chall_name, _| records.contains_key(chall_name));
+
+    for record in records.values() {
+        let sample = check_chall(&client, record).await;
+
+        if record_sample(&record.chall_name, sample.clone()) {
+            if sample.up {
+                info!("{} is back up", record.chall_name);
+            } else {
+                warn!("{} is down: {:?}", record.chall_name, sample.failures);
+            }
+            send_uptime_alert(&record.chall_name, &sample).await;
+        }
+    }
+
+    Ok(())
+}
+
+/// Spawns a Tokio task that periodically checks that every deployed challenge is reachable
+pub fn spawn_uptime_checker() {
+    info!("Starting uptime checker (interval {:?})", interval());
+
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval());
+
+        loop {
+            ticker.tick().await;
+
+            if let Err(e) = check_all_challs().await {
+                error!("Failed to run uptime checks: {e}");
+            }
+        }
+    });
+}